@@ -1,3 +1,73 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
 fn main() {
     println!("cargo:rustc-link-arg-bins=-Tlinkall.x");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_ROM").is_some() {
+        embed_rom();
+    }
+}
+
+/// Generates `embedded_rom.rs` for the `embedded-rom` feature, pulling in the
+/// files named by `GB_ROM` and the optional `GB_BOOT_ROM`.
+fn embed_rom() {
+    println!("cargo:rerun-if-env-changed=GB_ROM");
+    println!("cargo:rerun-if-env-changed=GB_BOOT_ROM");
+    let rom = env::var("GB_ROM").expect("the embedded-rom feature needs GB_ROM=path/to/game.gb");
+    let rom = fs::canonicalize(&rom).unwrap_or_else(|error| panic!("GB_ROM={}: {}", rom, error));
+    println!("cargo:rerun-if-changed={}", rom.display());
+
+    let boot_rom = match env::var("GB_BOOT_ROM") {
+        Ok(path) if !path.is_empty() => {
+            let path = fs::canonicalize(&path)
+                .unwrap_or_else(|error| panic!("GB_BOOT_ROM={}: {}", path, error));
+            let len = fs::metadata(&path)
+                .map(|metadata| metadata.len())
+                .unwrap_or(0);
+            assert_eq!(
+                len, 0x100,
+                "GB_BOOT_ROM has to be the 256 byte DMG boot ROM"
+            );
+            println!("cargo:rerun-if-changed={}", path.display());
+            format!("Some(include_bytes!({:?}))", path.display().to_string())
+        }
+        _ => String::from("None"),
+    };
+
+    let source = format!(
+        "pub const ROM_NAME: &str = {:?};\n\
+         pub static ROM: &[u8] = include_bytes!({:?});\n\
+         pub static BOOT_ROM: Option<&[u8; 0x100]> = {};\n",
+        short_name(&rom),
+        rom.display().to_string(),
+        boot_rom
+    );
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("embedded_rom.rs"), source).unwrap();
+}
+
+/// An 8.3 name for the ROM, so its saves can be written to a FAT card.
+fn short_name(rom: &Path) -> String {
+    let clean = |text: &str, len: usize| {
+        text.chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .take(len)
+            .collect::<String>()
+            .to_ascii_uppercase()
+    };
+    let stem = rom
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let extension = rom.extension().map(|extension| extension.to_string_lossy());
+    let stem = match clean(&stem, 8) {
+        stem if stem.is_empty() => String::from("ROM"),
+        stem => stem,
+    };
+    match extension.map(|extension| clean(&extension, 3)) {
+        Some(extension) if !extension.is_empty() => format!("{}.{}", stem, extension),
+        _ => format!("{}.GB", stem),
+    }
 }
@@ -0,0 +1,405 @@
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::fmt;
+
+use miniz_oxide::inflate::{
+    core::{decompress, inflate_flags::TINFL_FLAG_HAS_MORE_INPUT, DecompressorOxide},
+    TINFLStatus,
+};
+
+use crate::{checksum::Crc32, storage::StorageError, ui::rom_browser::is_rom_file};
+
+/// Deflate looks back at most 32 KiB, so the output can wrap around a buffer this size.
+const WINDOW_SIZE: usize = 32 * 1024;
+/// Compressed bytes read from the card per step.
+const INPUT_CHUNK: usize = 4 * 1024;
+
+const GZIP_MAGIC: [u8; 3] = [0x1F, 0x8B, 0x08];
+const GZIP_HEADER_LEN: usize = 10;
+const GZIP_TRAILER_LEN: usize = 8;
+/// How much of a gzip file is read to find the end of its header.
+const GZIP_HEADER_READ: usize = 512;
+const GZIP_EXTRA: u8 = 0x04;
+const GZIP_NAME: u8 = 0x08;
+const GZIP_COMMENT: u8 = 0x10;
+const GZIP_HEADER_CRC: u8 = 0x02;
+
+const ZIP_END_SIGNATURE: [u8; 4] = [0x50, 0x4B, 0x05, 0x06];
+const ZIP_CENTRAL_SIGNATURE: [u8; 4] = [0x50, 0x4B, 0x01, 0x02];
+const ZIP_LOCAL_SIGNATURE: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+const ZIP_END_LEN: usize = 22;
+const ZIP_CENTRAL_LEN: usize = 46;
+const ZIP_LOCAL_LEN: usize = 30;
+/// The end record is usually the last 22 bytes, unless the archive has a comment.
+const ZIP_END_SEARCH: usize = 1024;
+const ZIP_MAX_COMMENT: usize = 0xFFFF;
+const ZIP_ENCRYPTED: u16 = 0x0001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Gzip,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Tells compressed ROMs apart by extension: `.gz` or `.zip`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let extension = match name.rsplit_once('.') {
+            Some((_, extension)) => extension,
+            None => return None,
+        };
+        if extension.eq_ignore_ascii_case("gz") {
+            Some(ArchiveKind::Gzip)
+        } else if extension.eq_ignore_ascii_case("zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Stored,
+    Deflate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// Not a gzip or zip file, or one cut short.
+    Malformed,
+    /// Encryption, zip64 or a compression method other than deflate.
+    Unsupported,
+    /// The zip holds no `.gb`/`.gbc` file.
+    NoRom,
+    /// The compressed data is damaged.
+    Corrupt,
+    Checksum {
+        expected: u32,
+        actual: u32,
+    },
+    Storage(StorageError),
+}
+
+impl From<StorageError> for ArchiveError {
+    fn from(error: StorageError) -> Self {
+        ArchiveError::Storage(error)
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Malformed => write!(f, "not a valid zip or gzip file"),
+            ArchiveError::Unsupported => {
+                write!(f, "archive is encrypted or uses an unsupported compression")
+            }
+            ArchiveError::NoRom => write!(f, "no .gb/.gbc file in the archive"),
+            ArchiveError::Corrupt => write!(f, "compressed data is damaged"),
+            ArchiveError::Checksum { expected, actual } => write!(
+                f,
+                "archive CRC32 mismatch: expected {:08X}, got {:08X}",
+                expected, actual
+            ),
+            ArchiveError::Storage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// The ROM inside an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// Name stored in the archive, empty for gzip files without one.
+    pub name: String,
+    pub compression: Compression,
+    /// Uncompressed size.
+    pub size: usize,
+    data_offset: usize,
+    compressed_size: usize,
+    crc32: u32,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Finds the ROM in an archive of `archive_len` bytes. `read_at` fills a
+/// buffer from the given offset of the archive.
+pub fn find_rom<R>(
+    kind: ArchiveKind,
+    archive_len: usize,
+    mut read_at: R,
+) -> Result<ArchiveEntry, ArchiveError>
+where
+    R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+{
+    match kind {
+        ArchiveKind::Gzip => find_gzip_rom(archive_len, &mut read_at),
+        ArchiveKind::Zip => find_zip_rom(archive_len, &mut read_at),
+    }
+}
+
+fn find_gzip_rom<R>(archive_len: usize, read_at: &mut R) -> Result<ArchiveEntry, ArchiveError>
+where
+    R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+{
+    if archive_len < GZIP_HEADER_LEN + GZIP_TRAILER_LEN {
+        return Err(ArchiveError::Malformed);
+    }
+    let mut header = vec![0u8; archive_len.min(GZIP_HEADER_READ)];
+    read_at(0, &mut header)?;
+    if !header.starts_with(&GZIP_MAGIC) {
+        return Err(ArchiveError::Malformed);
+    }
+    let flags = header[3];
+    let mut position = GZIP_HEADER_LEN;
+    if flags & GZIP_EXTRA != 0 {
+        let extra = header
+            .get(position..position + 2)
+            .ok_or(ArchiveError::Malformed)?;
+        position += 2 + u16::from_le_bytes([extra[0], extra[1]]) as usize;
+    }
+    let mut name = String::new();
+    for (flag, keep) in [(GZIP_NAME, true), (GZIP_COMMENT, false)] {
+        if flags & flag == 0 {
+            continue;
+        }
+        let text = header.get(position..).ok_or(ArchiveError::Malformed)?;
+        let end = text
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(ArchiveError::Malformed)?;
+        if keep {
+            name = String::from_utf8_lossy(&text[..end]).into_owned();
+        }
+        position += end + 1;
+    }
+    if flags & GZIP_HEADER_CRC != 0 {
+        position += 2;
+    }
+    if position + GZIP_TRAILER_LEN > archive_len {
+        return Err(ArchiveError::Malformed);
+    }
+    let mut trailer = [0u8; GZIP_TRAILER_LEN];
+    read_at(archive_len - GZIP_TRAILER_LEN, &mut trailer)?;
+    Ok(ArchiveEntry {
+        name,
+        compression: Compression::Deflate,
+        // Stored modulo 4 GiB, far beyond any Game Boy ROM.
+        size: u32_at(&trailer, 4) as usize,
+        data_offset: position,
+        compressed_size: archive_len - GZIP_TRAILER_LEN - position,
+        crc32: u32_at(&trailer, 0),
+    })
+}
+
+fn find_zip_end<R>(archive_len: usize, read_at: &mut R) -> Result<Vec<u8>, ArchiveError>
+where
+    R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+{
+    for search in [ZIP_END_SEARCH, ZIP_END_LEN + ZIP_MAX_COMMENT] {
+        let len = archive_len.min(search);
+        let mut tail = vec![0u8; len];
+        read_at(archive_len - len, &mut tail)?;
+        let found = (0..=len.saturating_sub(ZIP_END_LEN))
+            .rev()
+            .find(|start| tail[*start..].starts_with(&ZIP_END_SIGNATURE));
+        if let Some(start) = found {
+            tail.drain(..start);
+            tail.truncate(ZIP_END_LEN);
+            return Ok(tail);
+        }
+        if len == archive_len {
+            break;
+        }
+    }
+    Err(ArchiveError::Malformed)
+}
+
+fn find_zip_rom<R>(archive_len: usize, read_at: &mut R) -> Result<ArchiveEntry, ArchiveError>
+where
+    R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+{
+    if archive_len < ZIP_END_LEN {
+        return Err(ArchiveError::Malformed);
+    }
+    let end = find_zip_end(archive_len, read_at)?;
+    let entries = u16_at(&end, 10) as usize;
+    let directory_len = u32_at(&end, 12) as usize;
+    let directory_offset = u32_at(&end, 16) as usize;
+    if directory_offset.saturating_add(directory_len) > archive_len {
+        return Err(ArchiveError::Malformed);
+    }
+    let mut directory = vec![0u8; directory_len];
+    read_at(directory_offset, &mut directory)?;
+
+    let mut position = 0;
+    for _ in 0..entries {
+        let header = directory
+            .get(position..position + ZIP_CENTRAL_LEN)
+            .ok_or(ArchiveError::Malformed)?;
+        if !header.starts_with(&ZIP_CENTRAL_SIGNATURE) {
+            return Err(ArchiveError::Malformed);
+        }
+        let name_len = u16_at(header, 28) as usize;
+        let record_len =
+            ZIP_CENTRAL_LEN + name_len + u16_at(header, 30) as usize + u16_at(header, 32) as usize;
+        let name = directory
+            .get(position + ZIP_CENTRAL_LEN..position + ZIP_CENTRAL_LEN + name_len)
+            .ok_or(ArchiveError::Malformed)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        if !is_rom_file(&name) {
+            position += record_len;
+            continue;
+        }
+        let compression = match u16_at(header, 10) {
+            0 => Compression::Stored,
+            8 => Compression::Deflate,
+            _ => return Err(ArchiveError::Unsupported),
+        };
+        let compressed_size = u32_at(header, 20);
+        let size = u32_at(header, 24);
+        let local_offset = u32_at(header, 42);
+        // All-ones sizes or offsets mean the real values are in a zip64 record.
+        if u16_at(header, 8) & ZIP_ENCRYPTED != 0
+            || [compressed_size, size, local_offset].contains(&u32::MAX)
+        {
+            return Err(ArchiveError::Unsupported);
+        }
+        // The offsets come from the file, so the sums may overflow a 32-bit
+        // usize as well as point past its end.
+        let mut local = [0u8; ZIP_LOCAL_LEN];
+        let local_end = match (local_offset as usize).checked_add(ZIP_LOCAL_LEN) {
+            Some(end) if end <= archive_len => end,
+            _ => return Err(ArchiveError::Malformed),
+        };
+        read_at(local_offset as usize, &mut local)?;
+        if !local.starts_with(&ZIP_LOCAL_SIGNATURE) {
+            return Err(ArchiveError::Malformed);
+        }
+        let data_offset = local_end
+            .checked_add(u16_at(&local, 26) as usize + u16_at(&local, 28) as usize)
+            .ok_or(ArchiveError::Malformed)?;
+        match data_offset.checked_add(compressed_size as usize) {
+            Some(end) if end <= archive_len => {}
+            _ => return Err(ArchiveError::Malformed),
+        }
+        return Ok(ArchiveEntry {
+            name,
+            compression,
+            size: size as usize,
+            data_offset,
+            compressed_size: compressed_size as usize,
+            crc32: u32_at(header, 16),
+        });
+    }
+    Err(ArchiveError::NoRom)
+}
+
+/// Decompresses `entry`, handing the ROM to `write` in order. `write` can
+/// return `false` to stop early, which skips the size and CRC checks.
+pub fn extract<R, W>(entry: &ArchiveEntry, mut read_at: R, mut write: W) -> Result<(), ArchiveError>
+where
+    R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+    W: FnMut(&[u8]) -> Result<bool, StorageError>,
+{
+    let mut crc = Crc32::new();
+    let mut written = 0;
+    let mut output = |data: &[u8]| -> Result<bool, ArchiveError> {
+        if written + data.len() > entry.size {
+            return Err(ArchiveError::Corrupt);
+        }
+        crc.update(data);
+        written += data.len();
+        Ok(write(data)?)
+    };
+    let mut input = vec![0u8; INPUT_CHUNK];
+    let mut consumed = 0;
+    match entry.compression {
+        Compression::Stored => {
+            while consumed < entry.compressed_size {
+                let len = (entry.compressed_size - consumed).min(INPUT_CHUNK);
+                read_at(entry.data_offset + consumed, &mut input[..len])?;
+                consumed += len;
+                if !output(&input[..len])? {
+                    return Ok(());
+                }
+            }
+        }
+        Compression::Deflate => {
+            let mut decompressor = Box::<DecompressorOxide>::default();
+            let mut window = vec![0u8; WINDOW_SIZE];
+            let mut window_position = 0;
+            let (mut input_start, mut input_end) = (0, 0);
+            loop {
+                if input_start == input_end && consumed < entry.compressed_size {
+                    input_end = (entry.compressed_size - consumed).min(INPUT_CHUNK);
+                    read_at(entry.data_offset + consumed, &mut input[..input_end])?;
+                    consumed += input_end;
+                    input_start = 0;
+                }
+                let flags = if consumed < entry.compressed_size {
+                    TINFL_FLAG_HAS_MORE_INPUT
+                } else {
+                    0
+                };
+                let (status, read, produced) = decompress(
+                    &mut decompressor,
+                    &input[input_start..input_end],
+                    &mut window,
+                    window_position,
+                    flags,
+                );
+                input_start += read;
+                if produced > 0 && !output(&window[window_position..window_position + produced])? {
+                    return Ok(());
+                }
+                window_position = (window_position + produced) % WINDOW_SIZE;
+                match status {
+                    TINFLStatus::Done => break,
+                    TINFLStatus::HasMoreOutput => {}
+                    TINFLStatus::NeedsMoreInput if consumed < entry.compressed_size => {}
+                    _ => return Err(ArchiveError::Corrupt),
+                }
+            }
+        }
+    }
+    if written != entry.size {
+        return Err(ArchiveError::Corrupt);
+    }
+    match crc.finish() {
+        actual if actual == entry.crc32 => Ok(()),
+        actual => Err(ArchiveError::Checksum {
+            expected: entry.crc32,
+            actual,
+        }),
+    }
+}
+
+/// Decompresses just enough of `entry` to fill `buffer`, for reading the
+/// cartridge header. Returns the number of bytes read.
+pub fn read_start<R>(
+    entry: &ArchiveEntry,
+    read_at: R,
+    buffer: &mut [u8],
+) -> Result<usize, ArchiveError>
+where
+    R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+{
+    let mut filled = 0;
+    extract(entry, read_at, |data| {
+        let len = data.len().min(buffer.len() - filled);
+        buffer[filled..filled + len].copy_from_slice(&data[..len]);
+        filled += len;
+        Ok(filled < buffer.len())
+    })?;
+    Ok(filled)
+}
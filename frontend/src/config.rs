@@ -0,0 +1,826 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+use crate::{
+    gameboy::bank_cache::{BankCacheConfig, CachePolicy},
+    storage::{FileStorage, StorageError},
+};
+
+pub const CONFIG_FILE: &str = "config.ini";
+/// Holds one `<global checksum>.ini` per game, layered over `config.ini`.
+pub const GAME_SETTINGS_DIR: &str = "settings";
+/// Settings a game file may change. Everything else is needed before the
+/// cartridge header can be read.
+const GAME_KEYS: [(&str, &str); 11] = [
+    ("display", "clear_color"),
+    ("display", "scaling"),
+    ("display", "filter"),
+    ("display", "palette"),
+    ("display", "frame_skip"),
+    ("game", "skip_boot_rom"),
+    ("buttons", "a"),
+    ("buttons", "b"),
+    ("buttons", "start"),
+    ("buttons", "select"),
+    ("cheats", "enabled"),
+];
+/// Settings only a game file may change.
+const GAME_ONLY_KEYS: [(&str, &str); 1] = [("cheats", "enabled")];
+/// Largest file read, anything longer is truncated.
+const MAX_CONFIG_LEN: usize = 4096;
+/// The ESP32-S3 SPI peripheral tops out at 80 MHz.
+const MAX_DISPLAY_MHZ: u32 = 80;
+const MAX_SD_KHZ: u32 = 40_000;
+/// GPIO numbers on the ESP32-S3 run up to 48.
+const MAX_GPIO: u8 = 48;
+/// Frames skipped after every one drawn.
+pub const MAX_FRAME_SKIP: u8 = 3;
+/// Largest bank cache, in 16 KiB banks.
+pub const MAX_CACHE_BANKS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    PortraitFlipped,
+    Landscape,
+    LandscapeFlipped,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 4] = [
+        Orientation::Portrait,
+        Orientation::PortraitFlipped,
+        Orientation::Landscape,
+        Orientation::LandscapeFlipped,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Orientation::Portrait => "portrait",
+            Orientation::PortraitFlipped => "portrait_flipped",
+            Orientation::Landscape => "landscape",
+            Orientation::LandscapeFlipped => "landscape_flipped",
+        }
+    }
+}
+
+/// How the 160x144 picture is fitted to the panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    /// One panel pixel per Game Boy pixel, centred.
+    Native,
+    /// As large as the panel allows without distorting the picture,
+    /// 266x240 in landscape.
+    Fit,
+    /// Every pixel doubled, cutting off what does not fit on the panel.
+    Double,
+    /// Fills the whole panel, ignoring the aspect ratio.
+    Stretch,
+}
+
+impl Scaling {
+    pub const ALL: [Scaling; 4] = [
+        Scaling::Native,
+        Scaling::Fit,
+        Scaling::Double,
+        Scaling::Stretch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scaling::Native => "1x",
+            Scaling::Fit => "fit",
+            Scaling::Double => "2x",
+            Scaling::Stretch => "stretch",
+        }
+    }
+}
+
+/// How pixels are interpolated when the picture is scaled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Every panel pixel shows the nearest Game Boy pixel.
+    Nearest,
+    /// Blends the four nearest pixels, soft but even.
+    Bilinear,
+    /// Nearest within each pixel and blended only at the edges between
+    /// them, so pixels stay crisp but come out the same size.
+    SharpBilinear,
+    /// Scale2x/EPX, which rounds off diagonal edges of pixel art.
+    Scale2x,
+}
+
+impl Filter {
+    pub const ALL: [Filter; 4] = [
+        Filter::Nearest,
+        Filter::Bilinear,
+        Filter::SharpBilinear,
+        Filter::Scale2x,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Nearest => "nearest",
+            Filter::Bilinear => "bilinear",
+            Filter::SharpBilinear => "sharp_bilinear",
+            Filter::Scale2x => "scale2x",
+        }
+    }
+}
+
+const fn rgb565(rgb: u32) -> u16 {
+    ((rgb >> 8 & 0xF800) | (rgb >> 5 & 0x07E0) | (rgb >> 3 & 0x001F)) as u16
+}
+
+/// Colours for the four shades of games made for the original Game Boy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// The colours the emulator draws.
+    Original,
+    /// The green of the original Game Boy's screen.
+    Green,
+    /// The Game Boy Pocket's greys.
+    Pocket,
+    /// The backlit Game Boy Light.
+    Light,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 4] = [
+        Palette::Original,
+        Palette::Green,
+        Palette::Pocket,
+        Palette::Light,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Original => "original",
+            Palette::Green => "green",
+            Palette::Pocket => "pocket",
+            Palette::Light => "light",
+        }
+    }
+
+    /// RGB565 colours from lightest to darkest, `None` to leave the picture
+    /// as it is.
+    pub fn colors(&self) -> Option<[u16; 4]> {
+        match self {
+            Palette::Original => None,
+            Palette::Green => Some([
+                rgb565(0x9BBC0F),
+                rgb565(0x8BAC0F),
+                rgb565(0x306230),
+                rgb565(0x0F380F),
+            ]),
+            Palette::Pocket => Some([
+                rgb565(0xE0E0D0),
+                rgb565(0xA8A898),
+                rgb565(0x585848),
+                rgb565(0x181810),
+            ]),
+            Palette::Light => Some([
+                rgb565(0x00B581),
+                rgb565(0x009A71),
+                rgb565(0x00694A),
+                rgb565(0x004F3B),
+            ]),
+        }
+    }
+}
+
+/// Game Boy keys the A, B, START and SELECT buttons can press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameKey {
+    A,
+    B,
+    Start,
+    Select,
+}
+
+impl GameKey {
+    pub const ALL: [GameKey; 4] = [GameKey::A, GameKey::B, GameKey::Start, GameKey::Select];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameKey::A => "a",
+            GameKey::B => "b",
+            GameKey::Start => "start",
+            GameKey::Select => "select",
+        }
+    }
+}
+
+/// The key each face button presses in game. Menus and hotkeys keep the
+/// physical layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonMap {
+    pub a: GameKey,
+    pub b: GameKey,
+    pub start: GameKey,
+    pub select: GameKey,
+}
+
+impl Default for ButtonMap {
+    fn default() -> Self {
+        Self {
+            a: GameKey::A,
+            b: GameKey::B,
+            start: GameKey::Start,
+            select: GameKey::Select,
+        }
+    }
+}
+
+impl ButtonMap {
+    /// Key names in the `[buttons]` section with their values.
+    pub fn entries(&self) -> [(&'static str, GameKey); 4] {
+        [
+            ("a", self.a),
+            ("b", self.b),
+            ("start", self.start),
+            ("select", self.select),
+        ]
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut GameKey> {
+        Some(match key {
+            "a" => &mut self.a,
+            "b" => &mut self.b,
+            "start" => &mut self.start,
+            "select" => &mut self.select,
+            _ => return None,
+        })
+    }
+}
+
+/// GPIO numbers of everything but the SD card, whose pins have to be known
+/// before this file can be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinConfig {
+    pub led: u8,
+    pub lcd_mosi: u8,
+    pub lcd_sclk: u8,
+    pub lcd_reset: u8,
+    pub lcd_dc: u8,
+    pub a: u8,
+    pub b: u8,
+    pub start: u8,
+    pub select: u8,
+    pub up: u8,
+    pub down: u8,
+    pub left: u8,
+    pub right: u8,
+    /// Active-low input from the power supply, such as a boost converter's
+    /// low battery output. The game is saved and stopped when it trips.
+    pub low_battery: Option<u8>,
+}
+
+impl Default for PinConfig {
+    fn default() -> Self {
+        Self {
+            led: 47,
+            lcd_mosi: 4,
+            lcd_sclk: 5,
+            lcd_reset: 6,
+            lcd_dc: 7,
+            a: 15,
+            b: 16,
+            start: 17,
+            select: 18,
+            up: 9,
+            down: 10,
+            left: 11,
+            right: 12,
+            low_battery: None,
+        }
+    }
+}
+
+impl PinConfig {
+    /// Key names in the `[pins]` section with their values.
+    pub fn entries(&self) -> [(&'static str, u8); 13] {
+        [
+            ("led", self.led),
+            ("lcd_mosi", self.lcd_mosi),
+            ("lcd_sclk", self.lcd_sclk),
+            ("lcd_reset", self.lcd_reset),
+            ("lcd_dc", self.lcd_dc),
+            ("a", self.a),
+            ("b", self.b),
+            ("start", self.start),
+            ("select", self.select),
+            ("up", self.up),
+            ("down", self.down),
+            ("left", self.left),
+            ("right", self.right),
+        ]
+    }
+
+    fn get_mut(&mut self, key: &str) -> Option<&mut u8> {
+        Some(match key {
+            "led" => &mut self.led,
+            "lcd_mosi" => &mut self.lcd_mosi,
+            "lcd_sclk" => &mut self.lcd_sclk,
+            "lcd_reset" => &mut self.lcd_reset,
+            "lcd_dc" => &mut self.lcd_dc,
+            "a" => &mut self.a,
+            "b" => &mut self.b,
+            "start" => &mut self.start,
+            "select" => &mut self.select,
+            "up" => &mut self.up,
+            "down" => &mut self.down,
+            "left" => &mut self.left,
+            "right" => &mut self.right,
+            _ => return None,
+        })
+    }
+
+    /// The first GPIO assigned twice, if any.
+    fn duplicate(&self) -> Option<u8> {
+        let entries = self.entries();
+        let duplicate = entries
+            .iter()
+            .enumerate()
+            .find(|(index, (_, gpio))| entries[..*index].iter().any(|(_, other)| other == gpio))
+            .map(|(_, (_, gpio))| *gpio);
+        duplicate.or_else(|| {
+            self.low_battery
+                .filter(|gpio| entries.iter().any(|(_, other)| other == gpio))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub pins: PinConfig,
+    pub orientation: Orientation,
+    /// RGB565 colour around the game picture.
+    pub clear_color: u16,
+    pub display_spi_mhz: u32,
+    /// Upper bound for the SD card clock; slower speeds are still used if
+    /// the card fails at this one.
+    pub sd_spi_khz: u32,
+    pub scaling: Scaling,
+    pub filter: Filter,
+    /// Only used for games without Game Boy Color support.
+    pub palette: Palette,
+    /// Frames emulated but not drawn after every one that is, up to
+    /// `MAX_FRAME_SKIP`.
+    pub frame_skip: u8,
+    /// Started without showing the ROM browser.
+    pub default_rom: Option<String>,
+    pub skip_boot_rom: bool,
+    pub buttons: ButtonMap,
+    /// Numbers of the `.cht` cheats switched on, replacing the file's own
+    /// `cheatN_enable` flags. Only set by a game's file.
+    pub enabled_cheats: Option<Vec<usize>>,
+    pub cache: BankCacheConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pins: PinConfig::default(),
+            orientation: Orientation::LandscapeFlipped,
+            clear_color: 0xf9b0,
+            display_spi_mhz: MAX_DISPLAY_MHZ,
+            sd_spi_khz: MAX_SD_KHZ,
+            scaling: Scaling::Stretch,
+            filter: Filter::Nearest,
+            palette: Palette::Original,
+            frame_skip: 0,
+            default_rom: None,
+            skip_boot_rom: false,
+            buttons: ButtonMap::default(),
+            enabled_cheats: None,
+            cache: BankCacheConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// A line that is neither `[section]` nor `key = value`.
+    Syntax,
+    UnknownSection(String),
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        value: String,
+    },
+    /// Two functions share a GPIO; all pins fall back to their defaults.
+    DuplicatePin(u8),
+    /// A key that only `config.ini` may set.
+    NotPerGame(String),
+    /// A key that only a game's file may set.
+    OnlyPerGame(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Syntax => write!(f, "expected [section] or key = value"),
+            ConfigError::UnknownSection(section) => write!(f, "unknown section [{}]", section),
+            ConfigError::UnknownKey(key) => write!(f, "unknown key '{}'", key),
+            ConfigError::InvalidValue { key, value } => {
+                write!(f, "invalid value '{}' for '{}'", value, key)
+            }
+            ConfigError::DuplicatePin(gpio) => {
+                write!(f, "GPIO{} is used twice, using the default pins", gpio)
+            }
+            ConfigError::NotPerGame(key) => write!(f, "'{}' cannot be set per game", key),
+            ConfigError::OnlyPerGame(key) => write!(f, "'{}' can only be set per game", key),
+        }
+    }
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// A comma-separated list of numbers, empty for none.
+fn parse_list(value: &str) -> Option<Vec<u32>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|number| !number.is_empty())
+        .map(parse_number)
+        .collect()
+}
+
+fn parse_named<T: Copy>(value: &str, all: &[T], name: fn(&T) -> &'static str) -> Option<T> {
+    all.iter()
+        .find(|item| name(item).eq_ignore_ascii_case(value))
+        .copied()
+}
+
+fn parse_policy(value: &str) -> Option<CachePolicy> {
+    match value.to_ascii_lowercase().as_str() {
+        "lru" => Some(CachePolicy::Lru),
+        "lfu" => Some(CachePolicy::Lfu),
+        _ => None,
+    }
+}
+
+pub fn policy_name(policy: &CachePolicy) -> &'static str {
+    match policy {
+        CachePolicy::Lru => "lru",
+        CachePolicy::Lfu => "lfu",
+    }
+}
+
+impl Config {
+    /// Parses an INI file. Values that are missing or invalid keep their
+    /// defaults; the problems are returned with their line numbers.
+    pub fn parse(text: &str) -> (Config, Vec<(usize, ConfigError)>) {
+        Config::default().parse_over(text, false)
+    }
+
+    /// Layers a per-game file over these settings. Keys outside `GAME_KEYS`
+    /// are reported and ignored.
+    pub fn with_game_settings(&self, text: &str) -> (Config, Vec<(usize, ConfigError)>) {
+        self.clone().parse_over(text, true)
+    }
+
+    fn parse_over(self, text: &str, game: bool) -> (Config, Vec<(usize, ConfigError)>) {
+        let mut config = self;
+        let mut errors = Vec::new();
+        let mut section = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = match line.find([';', '#']) {
+                Some(comment) => &line[..comment],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                section = name.trim().to_ascii_lowercase();
+                if ![
+                    "pins", "display", "sd", "game", "buttons", "cheats", "cache",
+                ]
+                .contains(&section.as_str())
+                {
+                    errors.push((index + 1, ConfigError::UnknownSection(section.clone())));
+                }
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => {
+                    errors.push((index + 1, ConfigError::Syntax));
+                    continue;
+                }
+            };
+            let entry = (section.as_str(), key.as_str());
+            if game && !GAME_KEYS.contains(&entry) {
+                errors.push((index + 1, ConfigError::NotPerGame(key)));
+                continue;
+            }
+            if !game && GAME_ONLY_KEYS.contains(&entry) {
+                errors.push((index + 1, ConfigError::OnlyPerGame(key)));
+                continue;
+            }
+            if let Err(error) = config.set(&section, &key, value) {
+                errors.push((index + 1, error));
+            }
+        }
+        if let Some(gpio) = config.pins.duplicate() {
+            errors.push((0, ConfigError::DuplicatePin(gpio)));
+            config.pins = PinConfig::default();
+        }
+        (config, errors)
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidValue {
+            key: String::from(key),
+            value: String::from(value),
+        };
+        match (section, key) {
+            ("pins", "low_battery") if value.is_empty() => self.pins.low_battery = None,
+            ("pins", "low_battery") => {
+                self.pins.low_battery = Some(
+                    parse_number(value)
+                        .filter(|gpio| *gpio <= MAX_GPIO as u32)
+                        .ok_or_else(invalid)? as u8,
+                )
+            }
+            ("pins", _) => {
+                let pin = self
+                    .pins
+                    .get_mut(key)
+                    .ok_or_else(|| ConfigError::UnknownKey(String::from(key)))?;
+                *pin = parse_number(value)
+                    .filter(|gpio| *gpio <= MAX_GPIO as u32)
+                    .ok_or_else(invalid)? as u8;
+            }
+            ("display", "orientation") => {
+                self.orientation =
+                    parse_named(value, &Orientation::ALL, Orientation::name).ok_or_else(invalid)?
+            }
+            ("display", "clear_color") => {
+                self.clear_color = parse_number(value)
+                    .and_then(|color| u16::try_from(color).ok())
+                    .ok_or_else(invalid)?
+            }
+            ("display", "spi_mhz") => {
+                self.display_spi_mhz = parse_number(value)
+                    .filter(|mhz| (1..=MAX_DISPLAY_MHZ).contains(mhz))
+                    .ok_or_else(invalid)?
+            }
+            ("display", "scaling") => {
+                self.scaling =
+                    parse_named(value, &Scaling::ALL, Scaling::name).ok_or_else(invalid)?
+            }
+            ("display", "filter") => {
+                self.filter = parse_named(value, &Filter::ALL, Filter::name).ok_or_else(invalid)?
+            }
+            ("display", "palette") => {
+                self.palette =
+                    parse_named(value, &Palette::ALL, Palette::name).ok_or_else(invalid)?
+            }
+            ("display", "frame_skip") => {
+                self.frame_skip = parse_number(value)
+                    .filter(|frames| *frames <= MAX_FRAME_SKIP as u32)
+                    .ok_or_else(invalid)? as u8
+            }
+            ("sd", "spi_khz") => {
+                self.sd_spi_khz = parse_number(value)
+                    .filter(|khz| (400..=MAX_SD_KHZ).contains(khz))
+                    .ok_or_else(invalid)?
+            }
+            ("game", "default_rom") => {
+                self.default_rom = (!value.is_empty()).then(|| String::from(value))
+            }
+            ("game", "skip_boot_rom") => {
+                self.skip_boot_rom = parse_bool(value).ok_or_else(invalid)?
+            }
+            ("buttons", _) => {
+                let button = self
+                    .buttons
+                    .get_mut(key)
+                    .ok_or_else(|| ConfigError::UnknownKey(String::from(key)))?;
+                *button = parse_named(value, &GameKey::ALL, GameKey::name).ok_or_else(invalid)?;
+            }
+            ("cheats", "enabled") => {
+                self.enabled_cheats = Some(
+                    parse_list(value)
+                        .map(|numbers| numbers.into_iter().map(|number| number as usize).collect())
+                        .ok_or_else(invalid)?,
+                )
+            }
+            ("cache", "banks") => {
+                self.cache.capacity = parse_number(value)
+                    .filter(|banks| (1..=MAX_CACHE_BANKS as u32).contains(banks))
+                    .ok_or_else(invalid)? as usize
+            }
+            ("cache", "policy") => self.cache.policy = parse_policy(value).ok_or_else(invalid)?,
+            ("cache", "pinned") => {
+                self.cache.pinned = parse_list(value)
+                    .map(|banks| banks.into_iter().map(|bank| bank as usize).collect())
+                    .ok_or_else(invalid)?
+            }
+            _ => return Err(ConfigError::UnknownKey(String::from(key))),
+        }
+        Ok(())
+    }
+
+    /// Every setting as `(section, key, value)`, grouped by section.
+    fn entries(&self) -> Vec<(&'static str, &'static str, String)> {
+        let mut entries = vec![
+            (
+                "display",
+                "orientation",
+                self.orientation.name().to_string(),
+            ),
+            (
+                "display",
+                "clear_color",
+                format!("0x{:04x}", self.clear_color),
+            ),
+            ("display", "spi_mhz", self.display_spi_mhz.to_string()),
+            ("display", "scaling", self.scaling.name().to_string()),
+            ("display", "filter", self.filter.name().to_string()),
+            ("display", "palette", self.palette.name().to_string()),
+            ("display", "frame_skip", self.frame_skip.to_string()),
+            ("sd", "spi_khz", self.sd_spi_khz.to_string()),
+            (
+                "game",
+                "default_rom",
+                self.default_rom.clone().unwrap_or_default(),
+            ),
+            ("game", "skip_boot_rom", self.skip_boot_rom.to_string()),
+        ];
+        entries.extend(
+            self.buttons
+                .entries()
+                .iter()
+                .map(|(key, game_key)| ("buttons", *key, game_key.name().to_string())),
+        );
+        if let Some(numbers) = &self.enabled_cheats {
+            entries.push(("cheats", "enabled", join_list(numbers)));
+        }
+        entries.extend([
+            ("cache", "banks", self.cache.capacity.to_string()),
+            (
+                "cache",
+                "policy",
+                policy_name(&self.cache.policy).to_string(),
+            ),
+            ("cache", "pinned", join_list(&self.cache.pinned)),
+        ]);
+        entries.extend(
+            self.pins
+                .entries()
+                .iter()
+                .map(|(key, gpio)| ("pins", *key, gpio.to_string())),
+        );
+        let low_battery = self.pins.low_battery.map(|gpio| gpio.to_string());
+        entries.push(("pins", "low_battery", low_battery.unwrap_or_default()));
+        entries
+    }
+
+    /// Writes every setting, so the file doubles as documentation of the keys.
+    pub fn to_ini(&self) -> String {
+        write_ini(self.entries())
+    }
+
+    /// Writes the per-game settings that differ from `global`, so later
+    /// changes to `config.ini` still reach the game.
+    pub fn game_settings_ini(&self, global: &Config) -> String {
+        let global = global.entries();
+        write_ini(
+            self.entries()
+                .into_iter()
+                .filter(|(section, key, _)| GAME_KEYS.contains(&(*section, *key)))
+                .filter(|entry| !global.contains(entry))
+                .collect(),
+        )
+    }
+
+    /// `global` with the per-game settings taken from `self`.
+    pub fn game_settings_over(&self, global: &Config) -> Config {
+        Config {
+            clear_color: self.clear_color,
+            scaling: self.scaling,
+            filter: self.filter,
+            palette: self.palette,
+            frame_skip: self.frame_skip,
+            skip_boot_rom: self.skip_boot_rom,
+            buttons: self.buttons,
+            ..global.clone()
+        }
+    }
+}
+
+fn join_list(numbers: &[usize]) -> String {
+    numbers
+        .iter()
+        .map(|number| number.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn write_ini(entries: Vec<(&str, &str, String)>) -> String {
+    let mut text = String::from("; Written by the settings menu, comments are not kept.\n");
+    let mut current = "";
+    for (section, key, value) in entries {
+        if section != current {
+            let _ = writeln!(text, "\n[{}]", section);
+            current = section;
+        }
+        let _ = writeln!(text, "{} = {}", key, value);
+    }
+    text
+}
+
+/// Per-game settings file for the game with this header checksum.
+pub fn game_settings_file(global_checksum: u16) -> String {
+    format!("{:04X}.INI", global_checksum)
+}
+
+/// Reads `config.ini`, logging every problem. A missing or unreadable file
+/// gives the defaults.
+pub fn load<S: FileStorage>(storage: &mut S) -> Config {
+    let mut data = vec![0u8; MAX_CONFIG_LEN];
+    let read = match storage.read_file(CONFIG_FILE, &mut data) {
+        Ok(read) => read,
+        Err(StorageError::NotFound) => {
+            log::info!("No {}, using the default settings", CONFIG_FILE);
+            return Config::default();
+        }
+        Err(error) => {
+            log::error!("Cannot read {}: {}, using the defaults", CONFIG_FILE, error);
+            return Config::default();
+        }
+    };
+    let (config, errors) = Config::parse(&String::from_utf8_lossy(&data[..read]));
+    for (line, error) in errors {
+        match line {
+            0 => log::warn!("{}: {}", CONFIG_FILE, error),
+            line => log::warn!("{}:{}: {}", CONFIG_FILE, line, error),
+        }
+    }
+    config
+}
+
+pub fn save<S: FileStorage>(storage: &mut S, config: &Config) -> Result<(), StorageError> {
+    storage.write_file(CONFIG_FILE, config.to_ini().as_bytes())
+}
+
+/// Layers the game's file from `GAME_SETTINGS_DIR` over `global`, logging every
+/// problem. Games without a file get `global` unchanged.
+pub fn load_game<S: FileStorage>(storage: &mut S, global: &Config, global_checksum: u16) -> Config {
+    let file_name = game_settings_file(global_checksum);
+    let mut data = vec![0u8; MAX_CONFIG_LEN];
+    let read = match storage.read_file(&file_name, &mut data) {
+        Ok(read) => read,
+        Err(StorageError::NotFound) => return global.clone(),
+        Err(error) => {
+            log::error!("Cannot read {}: {}", file_name, error);
+            return global.clone();
+        }
+    };
+    log::info!("Using the settings in {}/{}", GAME_SETTINGS_DIR, file_name);
+    let (config, errors) = global.with_game_settings(&String::from_utf8_lossy(&data[..read]));
+    for (line, error) in errors {
+        log::warn!("{}:{}: {}", file_name, line, error);
+    }
+    config
+}
+
+/// Writes the settings of `config` that differ from `global` to the game's file.
+pub fn save_game<S: FileStorage>(
+    storage: &mut S,
+    global: &Config,
+    config: &Config,
+    global_checksum: u16,
+) -> Result<(), StorageError> {
+    let file_name = game_settings_file(global_checksum);
+    storage.write_file(&file_name, config.game_settings_ini(global).as_bytes())
+}
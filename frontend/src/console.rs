@@ -0,0 +1,148 @@
+use alloc::{string::String, vec::Vec};
+
+use crate::clock::DateTime;
+
+/// Longest command line accepted; longer lines are discarded.
+const MAX_LINE: usize = 128;
+
+/// Collects bytes from the serial port into lines.
+pub struct LineReader {
+    buffer: Vec<u8>,
+    overflowed: bool,
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineReader {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::with_capacity(MAX_LINE),
+            overflowed: false,
+        }
+    }
+
+    /// Returns the finished line once `byte` is a line terminator.
+    pub fn push(&mut self, byte: u8) -> Option<String> {
+        match byte {
+            b'\r' | b'\n' => {
+                let overflowed = core::mem::replace(&mut self.overflowed, false);
+                let line = core::mem::take(&mut self.buffer);
+                if overflowed || line.is_empty() {
+                    return None;
+                }
+                Some(String::from_utf8_lossy(&line).into_owned())
+            }
+            // Backspace and delete, for people typing into a terminal.
+            0x08 | 0x7F => {
+                self.buffer.pop();
+                None
+            }
+            _ if self.buffer.len() >= MAX_LINE => {
+                self.overflowed = true;
+                None
+            }
+            _ => {
+                self.buffer.push(byte);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    ShowTime,
+    SetTime(DateTime),
+    /// Save the running game so it resumes at the next boot, then stop.
+    Shutdown,
+    /// List the ROMs in the flash partition.
+    ListRoms,
+    /// Receive `length` raw bytes into the flash partition as `name`.
+    Upload {
+        name: String,
+        length: u32,
+        crc: u32,
+    },
+    /// Remove a ROM from the flash partition.
+    Delete(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Unknown(String),
+    InvalidDate,
+    InvalidUpload,
+    MissingName,
+}
+
+pub const HELP: &str = concat!(
+    "Commands:\n",
+    "  time                       show the clock\n",
+    "  time YYYY-MM-DD HH:MM:SS   set the clock (UTC)\n",
+    "  shutdown                   save the game to resume it at the next start\n",
+    "  roms                       list the ROMs in flash\n",
+    "  upload NAME SIZE CRC32     store a ROM in flash, sent raw after 'ready'\n",
+    "  delete NAME                remove a ROM from flash",
+);
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, CommandError> {
+        let line = line.trim();
+        let (name, arguments) = match line.split_once(' ') {
+            Some((name, arguments)) => (name, arguments.trim()),
+            None => (line, ""),
+        };
+        match name {
+            "help" | "?" => Ok(Command::Help),
+            "shutdown" => Ok(Command::Shutdown),
+            "roms" => Ok(Command::ListRoms),
+            "upload" => Self::parse_upload(arguments).ok_or(CommandError::InvalidUpload),
+            "delete" if arguments.is_empty() => Err(CommandError::MissingName),
+            "delete" => Ok(Command::Delete(String::from(arguments))),
+            "time" if arguments.is_empty() => Ok(Command::ShowTime),
+            "time" => DateTime::parse(arguments)
+                .map(Command::SetTime)
+                .ok_or(CommandError::InvalidDate),
+            _ => Err(CommandError::Unknown(String::from(name))),
+        }
+    }
+
+    /// `NAME SIZE CRC32`, the size in decimal and the CRC-32 in hex.
+    fn parse_upload(arguments: &str) -> Option<Self> {
+        let mut arguments = arguments.split_whitespace();
+        let name = arguments.next()?;
+        let length = arguments.next()?.parse().ok()?;
+        let crc = arguments.next()?;
+        let crc = crc.strip_prefix("0x").unwrap_or(crc);
+        let crc = u32::from_str_radix(crc, 16).ok()?;
+        if arguments.next().is_some() {
+            return None;
+        }
+        Some(Command::Upload {
+            name: String::from(name),
+            length,
+            crc,
+        })
+    }
+}
+
+impl core::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CommandError::Unknown(name) => write!(f, "Unknown command '{}', try 'help'", name),
+            CommandError::InvalidDate => write!(f, "Expected a date like 2024-05-01 12:30:00"),
+            CommandError::InvalidUpload => {
+                write!(
+                    f,
+                    "Expected upload NAME SIZE CRC32, like upload TETRIS.GB 32768 46195f0a"
+                )
+            }
+            CommandError::MissingName => write!(f, "Expected a ROM name"),
+        }
+    }
+}
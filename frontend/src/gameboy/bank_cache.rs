@@ -0,0 +1,216 @@
+use alloc::{boxed::Box, vec::Vec};
+
+pub const BANK_SIZE: usize = 0x4000;
+
+/// Bookkeeping the eviction policies see for every cached bank.
+#[derive(Debug, Clone, Copy)]
+pub struct SlotInfo {
+    pub bank: usize,
+    /// Access tick of the most recent switch to or load of the bank.
+    pub last_used: u64,
+    /// Switches to the bank, halved periodically. A bank loaded into a full
+    /// cache starts from the fewest uses among the evictable banks, so it is
+    /// not the next victim only for being new.
+    pub uses: u32,
+    pub pinned: bool,
+}
+
+pub trait EvictionPolicy {
+    /// Picks the slot to replace in a full cache. Pinned slots must not be chosen.
+    fn choose_victim(&mut self, slots: &[SlotInfo]) -> Option<usize>;
+}
+
+/// Evicts the bank that has gone unused for longest.
+pub struct LeastRecentlyUsed;
+
+impl EvictionPolicy for LeastRecentlyUsed {
+    fn choose_victim(&mut self, slots: &[SlotInfo]) -> Option<usize> {
+        slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| !slot.pinned)
+            .min_by_key(|(_, slot)| slot.last_used)
+            .map(|(index, _)| index)
+    }
+}
+
+/// Evicts the bank with the fewest recent accesses, breaking ties by age. Banks
+/// that are read all the time (e.g. a game's engine code) stay resident even if
+/// another bank was touched more recently.
+pub struct LeastFrequentlyUsed;
+
+impl EvictionPolicy for LeastFrequentlyUsed {
+    fn choose_victim(&mut self, slots: &[SlotInfo]) -> Option<usize> {
+        slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| !slot.pinned)
+            .min_by_key(|(_, slot)| (slot.uses, slot.last_used))
+            .map(|(index, _)| index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    Lru,
+    Lfu,
+}
+
+impl CachePolicy {
+    pub fn build(&self) -> Box<dyn EvictionPolicy> {
+        match self {
+            CachePolicy::Lru => Box::new(LeastRecentlyUsed),
+            CachePolicy::Lfu => Box::new(LeastFrequentlyUsed),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BankCacheConfig {
+    /// Number of 16 KiB banks kept in RAM, not counting bank 0.
+    pub capacity: usize,
+    pub policy: CachePolicy,
+    /// Banks that are never evicted once loaded.
+    pub pinned: Vec<usize>,
+}
+
+impl Default for BankCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 4,
+            policy: CachePolicy::Lru,
+            pinned: Vec::new(),
+        }
+    }
+}
+
+/// Halve use counts every this many accesses so old popularity fades.
+const AGING_INTERVAL: u64 = 0x1_0000;
+/// Bank number of a slot whose contents could not be loaded.
+const NO_BANK: usize = usize::MAX;
+
+pub struct BankCache {
+    slots: Vec<SlotInfo>,
+    banks: Vec<Box<[u8; BANK_SIZE]>>,
+    capacity: usize,
+    pinned: Vec<usize>,
+    policy: Box<dyn EvictionPolicy>,
+    tick: u64,
+    last_slot: usize,
+}
+
+impl BankCache {
+    pub fn new(config: &BankCacheConfig) -> Self {
+        let capacity = config.capacity.max(1);
+        let mut pinned = config.pinned.clone();
+        // At least one slot has to stay evictable or new banks could never load.
+        if pinned.len() >= capacity {
+            log::warn!(
+                "Ignoring {} pinned banks, the cache only has {} slots",
+                pinned.len() + 1 - capacity,
+                capacity
+            );
+            pinned.truncate(capacity - 1);
+        }
+        Self {
+            slots: Vec::with_capacity(capacity),
+            banks: Vec::with_capacity(capacity),
+            capacity,
+            pinned,
+            policy: config.policy.build(),
+            tick: 0,
+            last_slot: 0,
+        }
+    }
+
+    fn touch(&mut self, slot: usize) {
+        self.tick += 1;
+        if self.tick % AGING_INTERVAL == 0 {
+            for info in self.slots.iter_mut() {
+                info.uses /= 2;
+            }
+        }
+        let info = &mut self.slots[slot];
+        info.last_used = self.tick;
+        info.uses = info.uses.saturating_add(1);
+        self.last_slot = slot;
+    }
+
+    fn find(&self, bank: usize) -> Option<usize> {
+        if self
+            .slots
+            .get(self.last_slot)
+            .is_some_and(|info| info.bank == bank)
+        {
+            return Some(self.last_slot);
+        }
+        self.slots.iter().position(|info| info.bank == bank)
+    }
+
+    /// The bank of the most recent `get` or `load`.
+    pub fn current_bank(&self) -> Option<usize> {
+        self.slots
+            .get(self.last_slot)
+            .map(|info| info.bank)
+            .filter(|bank| *bank != NO_BANK)
+    }
+
+    /// Looks up a cached bank. Games read a bank byte by byte, so only a
+    /// switch from another bank counts as an access for the policies.
+    pub fn get(&mut self, bank: usize) -> Option<&[u8; BANK_SIZE]> {
+        let slot = self.find(bank)?;
+        if slot != self.last_slot {
+            self.touch(slot);
+        }
+        Some(&self.banks[slot])
+    }
+
+    /// Loads `bank` with `fill`, reusing the evicted buffer when the cache is
+    /// full. Returns the bank data and the number of the bank that was evicted.
+    /// If `fill` fails the slot is left empty so the next access retries.
+    pub fn load<F, E>(
+        &mut self,
+        bank: usize,
+        fill: F,
+    ) -> Result<(&[u8; BANK_SIZE], Option<usize>), E>
+    where
+        F: FnOnce(&mut [u8; BANK_SIZE]) -> Result<(), E>,
+    {
+        let empty = SlotInfo {
+            bank: NO_BANK,
+            last_used: 0,
+            uses: 0,
+            pinned: false,
+        };
+        let mut uses = 0;
+        let (slot, evicted) = if self.slots.len() < self.capacity {
+            self.slots.push(empty);
+            self.banks.push(Box::new([0u8; BANK_SIZE]));
+            (self.slots.len() - 1, None)
+        } else {
+            uses = self
+                .slots
+                .iter()
+                .filter(|info| !info.pinned)
+                .map(|info| info.uses)
+                .min()
+                .unwrap_or(0);
+            let slot = self
+                .policy
+                .choose_victim(&self.slots)
+                .expect("bank cache has no evictable slot");
+            let evicted = self.slots[slot].bank;
+            self.slots[slot] = empty;
+            (slot, Some(evicted).filter(|bank| *bank != NO_BANK))
+        };
+        fill(&mut self.banks[slot])?;
+        self.slots[slot] = SlotInfo {
+            bank,
+            last_used: 0,
+            uses,
+            pinned: self.pinned.contains(&bank),
+        };
+        self.touch(slot);
+        Ok((&self.banks[slot], evicted))
+    }
+}
@@ -0,0 +1,292 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::{cell::RefCell, fmt};
+
+use crate::storage::{sibling_file_name, FileStorage, StorageError};
+
+/// Game Genie: replaces a ROM byte, optionally only while the original byte
+/// matches `compare` so the patch only hits the intended bank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+/// GameShark: writes `value` to `address` once per frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameSharkCode {
+    /// External RAM bank for cartridge RAM addresses; most codes use 01.
+    pub bank: u8,
+    pub value: u8,
+    pub address: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatCode {
+    GameGenie(GameGenieCode),
+    GameShark(GameSharkCode),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheatError {
+    InvalidLength(usize),
+    InvalidDigit(char),
+    /// Game Genie codes can only patch ROM (0x0000-0x7FFF).
+    NotRomAddress(u16),
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheatError::InvalidLength(length) => {
+                write!(
+                    f,
+                    "{} digits, expected 6 or 9 (Game Genie) or 8 (GameShark)",
+                    length
+                )
+            }
+            CheatError::InvalidDigit(digit) => write!(f, "'{}' is not a hex digit", digit),
+            CheatError::NotRomAddress(address) => {
+                write!(f, "Game Genie address {:04X} is outside ROM", address)
+            }
+        }
+    }
+}
+
+fn hex_digits(code: &str) -> Result<Vec<u8>, CheatError> {
+    code.chars()
+        .filter(|c| *c != '-')
+        .map(|c| {
+            c.to_digit(16)
+                .map(|digit| digit as u8)
+                .ok_or(CheatError::InvalidDigit(c))
+        })
+        .collect()
+}
+
+/// Decodes `ABC-DEF` or `ABC-DEF-GHI`: AB is the new value, the address is
+/// FCDE with F inverted, and G/I hold the compare byte, rotated and XORed
+/// with 0xBA. H is not used by the hardware.
+pub fn parse_game_genie(code: &str) -> Result<GameGenieCode, CheatError> {
+    let digits = hex_digits(code.trim())?;
+    if digits.len() != 6 && digits.len() != 9 {
+        return Err(CheatError::InvalidLength(digits.len()));
+    }
+    let value = digits[0] << 4 | digits[1];
+    let address = ((digits[5] ^ 0xF) as u16) << 12
+        | (digits[2] as u16) << 8
+        | (digits[3] as u16) << 4
+        | digits[4] as u16;
+    if address >= 0x8000 {
+        return Err(CheatError::NotRomAddress(address));
+    }
+    let compare = (digits.len() == 9).then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+    Ok(GameGenieCode {
+        address,
+        value,
+        compare,
+    })
+}
+
+/// Decodes `BBVVLLHH`: RAM bank, value and the little-endian address.
+pub fn parse_game_shark(code: &str) -> Result<GameSharkCode, CheatError> {
+    let digits = hex_digits(code.trim())?;
+    if digits.len() != 8 {
+        return Err(CheatError::InvalidLength(digits.len()));
+    }
+    let byte = |index: usize| digits[index * 2] << 4 | digits[index * 2 + 1];
+    Ok(GameSharkCode {
+        bank: byte(0),
+        value: byte(1),
+        address: u16::from_le_bytes([byte(2), byte(3)]),
+    })
+}
+
+/// Tells the two formats apart by their number of digits.
+pub fn parse_code(code: &str) -> Result<CheatCode, CheatError> {
+    let code = code.trim();
+    match code.chars().filter(|c| *c != '-').count() {
+        8 => parse_game_shark(code).map(CheatCode::GameShark),
+        _ => parse_game_genie(code).map(CheatCode::GameGenie),
+    }
+}
+
+/// Most cheats read from a `.cht` file, far more than any game has.
+pub const MAX_CHEATS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    /// The `N` of the `cheatN_` keys in the `.cht` file.
+    pub number: usize,
+    pub description: String,
+    pub codes: Vec<CheatCode>,
+    pub enabled: bool,
+}
+
+/// Reads the libretro `.cht` layout:
+///
+/// ```text
+/// cheats = 1
+/// cheat0_desc = "Infinite lives"
+/// cheat0_code = "00A-17B-C49+01FF34D2"
+/// cheat0_enable = false
+/// ```
+///
+/// Cheats with an unparseable code are dropped and reported in `errors` as
+/// `(cheat index, error)`. Keys for cheats past `cheats = N`, or past
+/// `MAX_CHEATS`, are skipped.
+pub fn parse_cht(text: &str) -> (Vec<Cheat>, Vec<(usize, CheatError)>) {
+    let mut cheats: Vec<Cheat> = Vec::new();
+    let mut errors = Vec::new();
+    let mut broken = Vec::new();
+    let mut count = MAX_CHEATS;
+    for line in text.lines() {
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+            None => continue,
+        };
+        if key == "cheats" {
+            if let Ok(declared) = value.parse::<usize>() {
+                count = declared.min(MAX_CHEATS);
+            }
+            continue;
+        }
+        // Keys look like `cheat12_code`; anything else is skipped.
+        let (index, field) = match key
+            .strip_prefix("cheat")
+            .and_then(|rest| rest.split_once('_'))
+            .and_then(|(index, field)| Some((index.parse::<usize>().ok()?, field)))
+        {
+            Some((index, field)) if index < count => (index, field),
+            _ => continue,
+        };
+        while cheats.len() <= index {
+            cheats.push(Cheat {
+                number: cheats.len(),
+                description: String::new(),
+                codes: Vec::new(),
+                enabled: false,
+            });
+        }
+        let cheat = &mut cheats[index];
+        match field {
+            "desc" => cheat.description = String::from(value),
+            "enable" => cheat.enabled = value.eq_ignore_ascii_case("true"),
+            "code" => {
+                for code in value.split('+').filter(|code| !code.trim().is_empty()) {
+                    match parse_code(code) {
+                        Ok(code) => cheat.codes.push(code),
+                        Err(error) => {
+                            errors.push((index, error));
+                            broken.push(index);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let cheats = cheats
+        .into_iter()
+        .enumerate()
+        .filter(|(index, cheat)| !cheat.codes.is_empty() && !broken.contains(index))
+        .map(|(_, cheat)| cheat)
+        .collect();
+    (cheats, errors)
+}
+
+/// Loads `<rom>.cht`, logging codes that could not be used. A missing file
+/// means no cheats.
+pub fn load_cheats<S: FileStorage>(
+    storage: &mut S,
+    rom_name: &str,
+) -> Result<Vec<Cheat>, StorageError> {
+    let file_name = sibling_file_name(rom_name, "CHT");
+    let size = match storage.file_len(&file_name) {
+        Ok(size) => size,
+        Err(StorageError::NotFound) => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    let mut data = vec![0u8; size];
+    let read = storage.read_file(&file_name, &mut data)?;
+    let (cheats, errors) = parse_cht(&String::from_utf8_lossy(&data[..read]));
+    for (index, error) in errors {
+        log::warn!("{}: cheat{} skipped, {}", file_name, index, error);
+    }
+    for code in cheats.iter().flat_map(|cheat| cheat.codes.iter()) {
+        if let CheatCode::GameShark(code) = code {
+            if code.bank > 0x01 && (0xA000..0xC000).contains(&code.address) {
+                log::warn!(
+                    "{}: GameShark code for RAM bank {:02X} writes to the current bank instead",
+                    file_name,
+                    code.bank
+                );
+            }
+        }
+    }
+    log::info!("Loaded {} cheats from {}", cheats.len(), file_name);
+    Ok(cheats)
+}
+
+/// Switches on the cheats with these numbers and off all others, for the
+/// choice a game's settings file keeps.
+pub fn enable_only(cheats: &mut [Cheat], numbers: &[usize]) {
+    for cheat in cheats {
+        cheat.enabled = numbers.contains(&cheat.number);
+    }
+}
+
+/// Numbers of the cheats that are switched on.
+pub fn enabled_numbers(cheats: &[Cheat]) -> Vec<usize> {
+    cheats
+        .iter()
+        .filter(|cheat| cheat.enabled)
+        .map(|cheat| cheat.number)
+        .collect()
+}
+
+/// Enabled Game Genie codes, shared with the ROM manager which applies them
+/// to every byte it serves.
+#[derive(Default)]
+pub struct RomPatches {
+    codes: RefCell<Vec<GameGenieCode>>,
+}
+
+impl RomPatches {
+    pub fn update(&self, cheats: &[Cheat]) {
+        let mut codes = self.codes.borrow_mut();
+        codes.clear();
+        codes.extend(enabled_codes(cheats).filter_map(|code| match code {
+            CheatCode::GameGenie(code) => Some(code),
+            CheatCode::GameShark(_) => None,
+        }));
+    }
+
+    /// `value` as the CPU should see it at `address`.
+    #[inline(always)]
+    pub fn apply(&self, address: u16, value: u8) -> u8 {
+        let codes = self.codes.borrow();
+        if codes.is_empty() {
+            return value;
+        }
+        codes
+            .iter()
+            .find(|code| code.address == address && code.compare.map_or(true, |c| c == value))
+            .map_or(value, |code| code.value)
+    }
+}
+
+/// GameShark codes of the enabled cheats, to be written after every frame.
+pub fn ram_writes(cheats: &[Cheat]) -> impl Iterator<Item = GameSharkCode> + '_ {
+    enabled_codes(cheats).filter_map(|code| match code {
+        CheatCode::GameShark(code) => Some(code),
+        CheatCode::GameGenie(_) => None,
+    })
+}
+
+fn enabled_codes(cheats: &[Cheat]) -> impl Iterator<Item = CheatCode> + '_ {
+    cheats
+        .iter()
+        .filter(|cheat| cheat.enabled)
+        .flat_map(|cheat| cheat.codes.iter().copied())
+}
@@ -0,0 +1,291 @@
+use alloc::string::String;
+use core::fmt;
+
+/// Offset right after the last header byte; bank 0 must be at least this long.
+pub const HEADER_END: usize = 0x150;
+
+const TITLE: usize = 0x134;
+const CGB_FLAG: usize = 0x143;
+const NEW_LICENSEE: usize = 0x144;
+const SGB_FLAG: usize = 0x146;
+const CARTRIDGE_TYPE: usize = 0x147;
+const ROM_SIZE: usize = 0x148;
+const RAM_SIZE: usize = 0x149;
+const OLD_LICENSEE: usize = 0x14B;
+const VERSION: usize = 0x14C;
+const HEADER_CHECKSUM: usize = 0x14D;
+const GLOBAL_CHECKSUM: usize = 0x14E;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mbc {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mmm01,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1,
+}
+
+impl Mbc {
+    /// Controllers the emulator core implements.
+    pub fn is_supported(&self) -> bool {
+        matches!(
+            self,
+            Mbc::RomOnly | Mbc::Mbc1 | Mbc::Mbc2 | Mbc::Mbc3 | Mbc::Mbc5
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mbc: Mbc,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Option<Self> {
+        let (mbc, ram, battery, timer, rumble) = match code {
+            0x00 => (Mbc::RomOnly, false, false, false, false),
+            0x01 => (Mbc::Mbc1, false, false, false, false),
+            0x02 => (Mbc::Mbc1, true, false, false, false),
+            0x03 => (Mbc::Mbc1, true, true, false, false),
+            0x05 => (Mbc::Mbc2, false, false, false, false),
+            0x06 => (Mbc::Mbc2, false, true, false, false),
+            0x08 => (Mbc::RomOnly, true, false, false, false),
+            0x09 => (Mbc::RomOnly, true, true, false, false),
+            0x0B => (Mbc::Mmm01, false, false, false, false),
+            0x0C => (Mbc::Mmm01, true, false, false, false),
+            0x0D => (Mbc::Mmm01, true, true, false, false),
+            0x0F => (Mbc::Mbc3, false, true, true, false),
+            0x10 => (Mbc::Mbc3, true, true, true, false),
+            0x11 => (Mbc::Mbc3, false, false, false, false),
+            0x12 => (Mbc::Mbc3, true, false, false, false),
+            0x13 => (Mbc::Mbc3, true, true, false, false),
+            0x19 => (Mbc::Mbc5, false, false, false, false),
+            0x1A => (Mbc::Mbc5, true, false, false, false),
+            0x1B => (Mbc::Mbc5, true, true, false, false),
+            0x1C => (Mbc::Mbc5, false, false, false, true),
+            0x1D => (Mbc::Mbc5, true, false, false, true),
+            0x1E => (Mbc::Mbc5, true, true, false, true),
+            0x20 => (Mbc::Mbc6, true, true, false, false),
+            0x22 => (Mbc::Mbc7, true, true, false, true),
+            0xFC => (Mbc::PocketCamera, true, true, false, false),
+            0xFD => (Mbc::Tama5, true, true, false, false),
+            0xFE => (Mbc::HuC3, true, true, true, false),
+            0xFF => (Mbc::HuC1, true, true, false, false),
+            _ => return None,
+        };
+        Some(Self {
+            code,
+            mbc,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    None,
+    Compatible,
+    Required,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Licensee {
+    Old(u8),
+    New([u8; 2]),
+}
+
+impl fmt::Display for Licensee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Licensee::Old(code) => write!(f, "{:02X}", code),
+            Licensee::New([first, second]) => write!(f, "{}{}", *first as char, *second as char),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderError {
+    TooShort(usize),
+    HeaderChecksum { stored: u8, computed: u8 },
+    UnknownCartridgeType(u8),
+    UnsupportedMbc(Mbc),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::TooShort(size) => {
+                write!(f, "File is too small to be a ROM ({} bytes)", size)
+            }
+            HeaderError::HeaderChecksum { stored, computed } => write!(
+                f,
+                "Header checksum mismatch: stored {:02X}, computed {:02X}",
+                stored, computed
+            ),
+            HeaderError::UnknownCartridgeType(code) => {
+                write!(f, "Unknown cartridge type {:02X}", code)
+            }
+            HeaderError::UnsupportedMbc(mbc) => write!(f, "Unsupported cartridge: {:?}", mbc),
+            HeaderError::UnknownRomSize(code) => write!(f, "Unknown ROM size code {:02X}", code),
+            HeaderError::UnknownRamSize(code) => write!(f, "Unknown RAM size code {:02X}", code),
+        }
+    }
+}
+
+/// The cartridge header stored at 0x100-0x14F of bank 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub licensee: Licensee,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    pub fn parse(bank_0: &[u8]) -> Result<Self, HeaderError> {
+        if bank_0.len() < HEADER_END {
+            return Err(HeaderError::TooShort(bank_0.len()));
+        }
+        let computed = header_checksum(bank_0);
+        let stored = bank_0[HEADER_CHECKSUM];
+        if computed != stored {
+            return Err(HeaderError::HeaderChecksum { stored, computed });
+        }
+
+        let code = bank_0[CARTRIDGE_TYPE];
+        let cartridge_type =
+            CartridgeType::from_code(code).ok_or(HeaderError::UnknownCartridgeType(code))?;
+        if !cartridge_type.mbc.is_supported() {
+            return Err(HeaderError::UnsupportedMbc(cartridge_type.mbc));
+        }
+
+        let rom_size = match bank_0[ROM_SIZE] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            code => return Err(HeaderError::UnknownRomSize(code)),
+        };
+        let ram_size = match (cartridge_type.mbc, bank_0[RAM_SIZE]) {
+            // MBC2 has 512 half-bytes built in and always reports no RAM.
+            (Mbc::Mbc2, _) => 0x200,
+            (_, 0x00) => 0,
+            (_, 0x01) => 0x800,
+            (_, 0x02) => 0x2000,
+            (_, 0x03) => 0x8000,
+            (_, 0x04) => 0x20000,
+            (_, 0x05) => 0x10000,
+            (_, code) => return Err(HeaderError::UnknownRamSize(code)),
+        };
+
+        let cgb = match bank_0[CGB_FLAG] {
+            0xC0 => CgbSupport::Required,
+            flag if flag & 0x80 != 0 => CgbSupport::Compatible,
+            _ => CgbSupport::None,
+        };
+        // CGB titles give up the last title byte to the CGB flag.
+        let title_end = if cgb == CgbSupport::None {
+            NEW_LICENSEE
+        } else {
+            CGB_FLAG
+        };
+        let title = bank_0[TITLE..title_end]
+            .iter()
+            .take_while(|byte| **byte != 0)
+            .map(|byte| match *byte {
+                0x20..=0x7E => *byte as char,
+                _ => '?',
+            })
+            .collect::<String>();
+
+        let licensee = match bank_0[OLD_LICENSEE] {
+            0x33 => Licensee::New([bank_0[NEW_LICENSEE], bank_0[NEW_LICENSEE + 1]]),
+            code => Licensee::Old(code),
+        };
+
+        Ok(Self {
+            title: String::from(title.trim_end()),
+            cgb,
+            sgb: bank_0[SGB_FLAG] == 0x03,
+            cartridge_type,
+            rom_size,
+            ram_size,
+            licensee,
+            version: bank_0[VERSION],
+            header_checksum: stored,
+            global_checksum: u16::from_be_bytes([
+                bank_0[GLOBAL_CHECKSUM],
+                bank_0[GLOBAL_CHECKSUM + 1],
+            ]),
+        })
+    }
+
+    /// Bytes the `.sav` file needs, zero for cartridges without a battery.
+    pub fn save_size(&self) -> usize {
+        if self.cartridge_type.battery {
+            self.ram_size
+        } else {
+            0
+        }
+    }
+}
+
+fn header_checksum(bank_0: &[u8]) -> u8 {
+    bank_0[TITLE..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |checksum, byte| {
+            checksum.wrapping_sub(*byte).wrapping_sub(1)
+        })
+}
+
+/// Sum of every ROM byte except the two checksum bytes themselves. Real
+/// hardware never checks it, so a mismatch is only worth a warning.
+pub struct GlobalChecksum {
+    offset: usize,
+    sum: u16,
+}
+
+impl Default for GlobalChecksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GlobalChecksum {
+    pub fn new() -> Self {
+        Self { offset: 0, sum: 0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            if self.offset != GLOBAL_CHECKSUM && self.offset != GLOBAL_CHECKSUM + 1 {
+                self.sum = self.sum.wrapping_add(*byte as u16);
+            }
+            self.offset += 1;
+        }
+    }
+
+    pub fn finish(&self) -> u16 {
+        self.sum
+    }
+}
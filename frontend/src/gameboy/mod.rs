@@ -0,0 +1,9 @@
+pub mod bank_cache;
+pub mod cheats;
+pub mod header;
+pub mod patch;
+pub mod resume;
+pub mod rom_partition;
+pub mod rtc;
+pub mod save;
+pub mod savestate;
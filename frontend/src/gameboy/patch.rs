@@ -0,0 +1,672 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    checksum::{crc32, Crc32},
+    storage::{sibling_file_name, FileStorage, StorageError},
+};
+
+/// Patch files looked for next to the ROM, in this order.
+pub const PATCH_FORMATS: [PatchFormat; 3] = [PatchFormat::Ips, PatchFormat::Bps, PatchFormat::Ups];
+
+/// BPS and UPS end with the source, target and patch CRC-32s.
+const FOOTER_LEN: usize = 12;
+/// Marks the end of the records in an IPS file.
+const IPS_EOF: usize = 0x454F46;
+/// The largest ROM a cartridge header can describe.
+const MAX_TARGET_SIZE: usize = 0x80_0000;
+/// Patched ranges kept at most. Copies of copies can double the count with
+/// every command, so a hostile patch is stopped before it fills the heap.
+const MAX_SEGMENTS: usize = 0x1_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+    Ups,
+}
+
+impl PatchFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PatchFormat::Ips => "IPS",
+            PatchFormat::Bps => "BPS",
+            PatchFormat::Ups => "UPS",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// Wrong magic, or a record running past the end of the file.
+    Malformed,
+    /// The patch's own CRC-32 does not match, the file is damaged.
+    PatchChecksum,
+    SourceSize {
+        expected: usize,
+        actual: usize,
+    },
+    SourceChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    TargetChecksum {
+        expected: u32,
+        actual: u32,
+    },
+    /// Not enough free memory to read a patch file of this many bytes.
+    OutOfMemory(usize),
+    /// The patch changes more than `MAX_SEGMENTS` separate ranges.
+    TooManyRanges,
+    Storage(StorageError),
+}
+
+impl From<StorageError> for PatchError {
+    fn from(error: StorageError) -> Self {
+        PatchError::Storage(error)
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Malformed => write!(f, "not a valid patch file"),
+            PatchError::PatchChecksum => write!(f, "patch file is damaged"),
+            PatchError::SourceSize { expected, actual } => write!(
+                f,
+                "patch expects a {} byte ROM, this one has {} bytes",
+                expected, actual
+            ),
+            PatchError::SourceChecksum { expected, actual } => write!(
+                f,
+                "patch is for another ROM (CRC32 {:08X}, this one has {:08X})",
+                expected, actual
+            ),
+            PatchError::TargetChecksum { expected, actual } => write!(
+                f,
+                "patched ROM has CRC32 {:08X}, expected {:08X}",
+                actual, expected
+            ),
+            PatchError::OutOfMemory(size) => write!(
+                f,
+                "not enough memory for a {} KiB patch",
+                size.div_ceil(1024)
+            ),
+            PatchError::TooManyRanges => write!(f, "patch changes too many separate ranges"),
+            PatchError::Storage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Where the bytes of a patched range come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fragment {
+    /// Offset into `RomPatch::data`.
+    Data(usize),
+    Fill(u8),
+    /// The source byte at the same offset XORed with `RomPatch::data`.
+    Xor(usize),
+    /// Offset into the unpatched ROM.
+    Source(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    start: usize,
+    len: usize,
+    fragment: Fragment,
+}
+
+impl Segment {
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// `len` bytes of this segment starting `skip` bytes in, moved to `start`.
+    fn slice(&self, skip: usize, len: usize, start: usize) -> Segment {
+        let fragment = match self.fragment {
+            Fragment::Data(offset) => Fragment::Data(offset + skip),
+            Fragment::Fill(value) => Fragment::Fill(value),
+            Fragment::Xor(offset) => Fragment::Xor(offset + skip),
+            Fragment::Source(offset) => Fragment::Source(offset + skip),
+        };
+        Segment {
+            start,
+            len,
+            fragment,
+        }
+    }
+}
+
+/// Big-endian integer of `N` bytes, as used by IPS.
+fn read_be<const N: usize>(patch: &[u8], position: &mut usize) -> Result<usize, PatchError> {
+    let bytes = patch
+        .get(*position..*position + N)
+        .ok_or(PatchError::Malformed)?;
+    *position += N;
+    Ok(bytes
+        .iter()
+        .fold(0, |value, byte| value << 8 | *byte as usize))
+}
+
+/// The variable-length integers of BPS and UPS.
+fn read_number(patch: &[u8], position: &mut usize) -> Result<usize, PatchError> {
+    let mut value = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = *patch.get(*position).ok_or(PatchError::Malformed)?;
+        *position += 1;
+        value = value
+            .checked_add((byte & 0x7F) as usize * shift)
+            .ok_or(PatchError::Malformed)?;
+        if byte & 0x80 != 0 {
+            return Ok(value);
+        }
+        shift = shift.checked_shl(7).ok_or(PatchError::Malformed)?;
+        value = value.checked_add(shift).ok_or(PatchError::Malformed)?;
+    }
+}
+
+/// Signed offset used by the BPS copy commands.
+fn read_relative(patch: &[u8], position: &mut usize, base: &mut usize) -> Result<(), PatchError> {
+    let data = read_number(patch, position)?;
+    let distance = data >> 1;
+    *base = if data & 1 != 0 {
+        base.checked_sub(distance)
+    } else {
+        base.checked_add(distance)
+    }
+    .ok_or(PatchError::Malformed)?;
+    Ok(())
+}
+
+/// A parsed patch kept as a list of patched ranges, so any part of the
+/// patched ROM can be produced from the original file without holding
+/// either in memory.
+pub struct RomPatch {
+    source_size: usize,
+    target_size: usize,
+    /// Sorted and non-overlapping; bytes outside them come from the source
+    /// at the same offset.
+    segments: Vec<Segment>,
+    data: Vec<u8>,
+    /// Source and target CRC-32 for BPS and UPS.
+    checksums: Option<(u32, u32)>,
+}
+
+impl RomPatch {
+    /// Parses `patch` for a ROM of `source_size` bytes.
+    pub fn parse(
+        format: PatchFormat,
+        patch: &[u8],
+        source_size: usize,
+    ) -> Result<Self, PatchError> {
+        let mut rom_patch = RomPatch {
+            source_size,
+            target_size: source_size,
+            segments: Vec::new(),
+            data: Vec::new(),
+            checksums: None,
+        };
+        match format {
+            PatchFormat::Ips => rom_patch.parse_ips(patch)?,
+            PatchFormat::Bps => rom_patch.parse_bps(patch)?,
+            PatchFormat::Ups => rom_patch.parse_ups(patch)?,
+        }
+        Ok(rom_patch)
+    }
+
+    pub fn target_size(&self) -> usize {
+        self.target_size
+    }
+
+    fn parse_ips(&mut self, patch: &[u8]) -> Result<(), PatchError> {
+        if !patch.starts_with(b"PATCH") {
+            return Err(PatchError::Malformed);
+        }
+        let mut position = 5;
+        loop {
+            let offset = read_be::<3>(patch, &mut position)?;
+            if offset == IPS_EOF {
+                break;
+            }
+            let len = read_be::<2>(patch, &mut position)?;
+            let segment = if len == 0 {
+                // Run-length record: a 16-bit count and the byte to repeat.
+                let len = read_be::<2>(patch, &mut position)?;
+                let value = read_be::<1>(patch, &mut position)? as u8;
+                Segment {
+                    start: offset,
+                    len,
+                    fragment: Fragment::Fill(value),
+                }
+            } else {
+                let bytes = patch
+                    .get(position..position + len)
+                    .ok_or(PatchError::Malformed)?;
+                position += len;
+                self.data.extend_from_slice(bytes);
+                Segment {
+                    start: offset,
+                    len,
+                    fragment: Fragment::Data(self.data.len() - len),
+                }
+            };
+            self.place(segment)?;
+        }
+        self.target_size = self.segments.last().map_or(self.source_size, |segment| {
+            segment.end().max(self.source_size)
+        });
+        // Lunar IPS appends the size to truncate the ROM to.
+        if let Ok(size) = read_be::<3>(patch, &mut position) {
+            self.target_size = size;
+        }
+        Ok(())
+    }
+
+    /// Reads the common BPS/UPS header and checks the patch CRC, returning
+    /// the position after the sizes and the end of the commands.
+    fn parse_beat_header(
+        &mut self,
+        patch: &[u8],
+        magic: &[u8],
+    ) -> Result<(usize, usize), PatchError> {
+        if !patch.starts_with(magic) || patch.len() < magic.len() + FOOTER_LEN {
+            return Err(PatchError::Malformed);
+        }
+        let end = patch.len() - FOOTER_LEN;
+        let footer = |index: usize| {
+            let start = end + index * 4;
+            u32::from_le_bytes([
+                patch[start],
+                patch[start + 1],
+                patch[start + 2],
+                patch[start + 3],
+            ])
+        };
+        if crc32(&patch[..patch.len() - 4]) != footer(2) {
+            return Err(PatchError::PatchChecksum);
+        }
+        self.checksums = Some((footer(0), footer(1)));
+        let mut position = magic.len();
+        let expected = read_number(patch, &mut position)?;
+        if expected != self.source_size {
+            return Err(PatchError::SourceSize {
+                expected,
+                actual: self.source_size,
+            });
+        }
+        self.target_size = read_number(patch, &mut position)?;
+        if self.target_size > MAX_TARGET_SIZE {
+            return Err(PatchError::Malformed);
+        }
+        Ok((position, end))
+    }
+
+    fn parse_ups(&mut self, patch: &[u8]) -> Result<(), PatchError> {
+        let (mut position, end) = self.parse_beat_header(patch, b"UPS1")?;
+        let mut offset = 0usize;
+        while position < end {
+            offset = offset
+                .checked_add(read_number(patch, &mut position)?)
+                .ok_or(PatchError::Malformed)?;
+            // XOR bytes up to and including a zero terminator.
+            let len = patch[position..end]
+                .iter()
+                .position(|byte| *byte == 0)
+                .ok_or(PatchError::Malformed)?;
+            // The terminator may fall just past the end, the bytes may not.
+            if offset > self.target_size || self.target_size - offset < len {
+                return Err(PatchError::Malformed);
+            }
+            self.data
+                .extend_from_slice(&patch[position..position + len]);
+            self.place(Segment {
+                start: offset,
+                len,
+                fragment: Fragment::Xor(self.data.len() - len),
+            })?;
+            position += len + 1;
+            offset += len + 1;
+        }
+        Ok(())
+    }
+
+    fn parse_bps(&mut self, patch: &[u8]) -> Result<(), PatchError> {
+        let (mut position, end) = self.parse_beat_header(patch, b"BPS1")?;
+        let metadata = read_number(patch, &mut position)?;
+        position = match position.checked_add(metadata) {
+            Some(position) if position <= end => position,
+            _ => return Err(PatchError::Malformed),
+        };
+        let mut output = 0usize;
+        let mut source_offset = 0usize;
+        let mut target_offset = 0usize;
+        while position < end {
+            let command = read_number(patch, &mut position)?;
+            let len = (command >> 2) + 1;
+            // Checked before anything is placed, so `len` is bounded by the
+            // target size from here on.
+            let next = match output.checked_add(len) {
+                Some(next) if next <= self.target_size => next,
+                _ => return Err(PatchError::Malformed),
+            };
+            match command & 3 {
+                // SourceRead: the source at the same offset, which is what
+                // unpatched ranges already mean.
+                0 => {}
+                // TargetRead
+                1 => {
+                    let bytes = match position.checked_add(len) {
+                        Some(bytes_end) if bytes_end <= end => &patch[position..bytes_end],
+                        _ => return Err(PatchError::Malformed),
+                    };
+                    position += len;
+                    self.data.extend_from_slice(bytes);
+                    self.place(Segment {
+                        start: output,
+                        len,
+                        fragment: Fragment::Data(self.data.len() - len),
+                    })?;
+                }
+                // SourceCopy
+                2 => {
+                    read_relative(patch, &mut position, &mut source_offset)?;
+                    self.place(Segment {
+                        start: output,
+                        len,
+                        fragment: Fragment::Source(source_offset),
+                    })?;
+                    source_offset = source_offset
+                        .checked_add(len)
+                        .ok_or(PatchError::Malformed)?;
+                }
+                // TargetCopy
+                _ => {
+                    read_relative(patch, &mut position, &mut target_offset)?;
+                    if target_offset >= output {
+                        return Err(PatchError::Malformed);
+                    }
+                    self.copy_target(target_offset, output, len)?;
+                    // Below `output`, so adding `len` cannot pass `next`.
+                    target_offset += len;
+                }
+            }
+            output = next;
+        }
+        if output != self.target_size {
+            return Err(PatchError::Malformed);
+        }
+        Ok(())
+    }
+
+    /// BPS TargetCopy: repeats the already produced `from..to` range until
+    /// `len` bytes have been written at `to`. The ranges overlap when
+    /// `len` exceeds `to - from`, which patchers use for runs.
+    fn copy_target(&mut self, from: usize, to: usize, len: usize) -> Result<(), PatchError> {
+        let period = to - from;
+        let pattern = self.describe(from, period.min(len));
+        let needs_source = pattern
+            .iter()
+            .any(|segment| matches!(segment.fragment, Fragment::Source(_)));
+        if len > period && !needs_source {
+            // Runs are stored as bytes rather than as one segment per period.
+            let mut bytes = Vec::with_capacity(period);
+            for segment in &pattern {
+                match segment.fragment {
+                    Fragment::Data(offset) => {
+                        bytes.extend_from_slice(&self.data[offset..offset + segment.len])
+                    }
+                    Fragment::Fill(value) => {
+                        bytes.extend(core::iter::repeat(value).take(segment.len))
+                    }
+                    Fragment::Xor(_) | Fragment::Source(_) => unreachable!(),
+                }
+            }
+            let fragment = if period == 1 {
+                Fragment::Fill(bytes[0])
+            } else {
+                let start = self.data.len();
+                self.data
+                    .try_reserve(len)
+                    .map_err(|_| PatchError::OutOfMemory(len))?;
+                self.data.extend(bytes.iter().copied().cycle().take(len));
+                Fragment::Data(start)
+            };
+            return self.place(Segment {
+                start: to,
+                len,
+                fragment,
+            });
+        }
+        // Every chunk adds at least one segment, so `place` ends long runs.
+        let mut written = 0;
+        while written < len {
+            let chunk = (len - written).min(period);
+            for segment in self.describe(from + written, chunk) {
+                let start = segment.start - from + to;
+                self.place(Segment { start, ..segment })?;
+            }
+            written += chunk;
+        }
+        Ok(())
+    }
+
+    /// Segments producing `start..start + len`, with unpatched ranges made
+    /// explicit as source reads.
+    fn describe(&self, start: usize, len: usize) -> Vec<Segment> {
+        let end = start + len;
+        let mut described = Vec::new();
+        let mut position = start;
+        let first = self
+            .segments
+            .partition_point(|segment| segment.end() <= start);
+        for segment in &self.segments[first..] {
+            if segment.start >= end {
+                break;
+            }
+            if segment.start > position {
+                described.push(Segment {
+                    start: position,
+                    len: segment.start - position,
+                    fragment: Fragment::Source(position),
+                });
+                position = segment.start;
+            }
+            let segment_end = segment.end().min(end);
+            described.push(segment.slice(
+                position - segment.start,
+                segment_end - position,
+                position,
+            ));
+            position = segment_end;
+        }
+        if position < end {
+            described.push(Segment {
+                start: position,
+                len: end - position,
+                fragment: Fragment::Source(position),
+            });
+        }
+        described
+    }
+
+    /// Inserts `segment`, overriding whatever previously covered its range.
+    fn place(&mut self, segment: Segment) -> Result<(), PatchError> {
+        if segment.len == 0 {
+            return Ok(());
+        }
+        let end = segment.end();
+        let first = self
+            .segments
+            .partition_point(|existing| existing.end() <= segment.start);
+        let mut last = first;
+        let mut replacement = vec![];
+        while let Some(existing) = self.segments.get(last) {
+            if existing.start >= end {
+                break;
+            }
+            if existing.start < segment.start {
+                replacement.push(existing.slice(0, segment.start - existing.start, existing.start));
+            }
+            last += 1;
+        }
+        replacement.push(segment);
+        if let Some(existing) = self.segments[first..last].last() {
+            if existing.end() > end {
+                let skip = end - existing.start;
+                replacement.push(existing.slice(skip, existing.len - skip, end));
+            }
+        }
+        self.segments.splice(first..last, replacement);
+        if self.segments.len() > MAX_SEGMENTS {
+            return Err(PatchError::TooManyRanges);
+        }
+        Ok(())
+    }
+
+    /// Fills `buffer` with the patched ROM from `offset`. `read_source` reads
+    /// the unpatched ROM and is only asked for bytes inside it; the source is
+    /// zero past its end and the patched ROM 0xFF past its end.
+    pub fn read<R>(
+        &self,
+        offset: usize,
+        buffer: &mut [u8],
+        mut read_source: R,
+    ) -> Result<(), StorageError>
+    where
+        R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+    {
+        let end = offset + buffer.len();
+        self.read_source(offset, buffer, &mut read_source)?;
+        let first = self
+            .segments
+            .partition_point(|segment| segment.end() <= offset);
+        for segment in &self.segments[first..] {
+            if segment.start >= end {
+                break;
+            }
+            let low = segment.start.max(offset);
+            let high = segment.end().min(end);
+            let skip = low - segment.start;
+            let target = &mut buffer[low - offset..high - offset];
+            match segment.fragment {
+                Fragment::Data(start) => {
+                    target.copy_from_slice(&self.data[start + skip..start + skip + target.len()])
+                }
+                Fragment::Fill(value) => target.fill(value),
+                Fragment::Xor(start) => {
+                    for (byte, xor) in target.iter_mut().zip(&self.data[start + skip..]) {
+                        *byte ^= xor;
+                    }
+                }
+                Fragment::Source(start) => {
+                    self.read_source(start + skip, target, &mut read_source)?
+                }
+            }
+        }
+        if end > self.target_size {
+            buffer[self.target_size.saturating_sub(offset)..].fill(0xFF);
+        }
+        Ok(())
+    }
+
+    fn read_source<R>(
+        &self,
+        offset: usize,
+        buffer: &mut [u8],
+        read_source: &mut R,
+    ) -> Result<(), StorageError>
+    where
+        R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+    {
+        let available = self.source_size.saturating_sub(offset).min(buffer.len());
+        if available > 0 {
+            read_source(offset, &mut buffer[..available])?;
+        }
+        buffer[available..].fill(0);
+        Ok(())
+    }
+
+    /// Checks the unpatched ROM against the BPS/UPS source CRC, reading it
+    /// through `scratch`.
+    pub fn verify_source<R>(&self, scratch: &mut [u8], mut read_source: R) -> Result<(), PatchError>
+    where
+        R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+    {
+        let expected = match self.checksums {
+            Some((source, _)) => source,
+            None => return Ok(()),
+        };
+        let mut crc = Crc32::new();
+        let mut offset = 0;
+        while offset < self.source_size {
+            let len = (self.source_size - offset).min(scratch.len());
+            read_source(offset, &mut scratch[..len])?;
+            crc.update(&scratch[..len]);
+            offset += len;
+        }
+        match crc.finish() {
+            actual if actual == expected => Ok(()),
+            actual => Err(PatchError::SourceChecksum { expected, actual }),
+        }
+    }
+
+    /// Checks the CRC of the whole patched ROM, as computed by the caller.
+    pub fn verify_target(&self, actual: u32) -> Result<(), PatchError> {
+        match self.checksums {
+            Some((_, expected)) if expected != actual => {
+                Err(PatchError::TargetChecksum { expected, actual })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// CRC of the patched ROM, produced through `scratch`.
+    pub fn target_crc<R>(&self, scratch: &mut [u8], mut read_source: R) -> Result<u32, StorageError>
+    where
+        R: FnMut(usize, &mut [u8]) -> Result<(), StorageError>,
+    {
+        let mut crc = Crc32::new();
+        let mut offset = 0;
+        while offset < self.target_size {
+            let len = (self.target_size - offset).min(scratch.len());
+            self.read(offset, &mut scratch[..len], &mut read_source)?;
+            crc.update(&scratch[..len]);
+            offset += len;
+        }
+        Ok(crc.finish())
+    }
+}
+
+/// Looks for `<rom>.ips`, `.bps` and `.ups` and parses the first one found.
+/// Returns the patch with its file name.
+pub fn load_patch<S: FileStorage>(
+    storage: &mut S,
+    rom_name: &str,
+    rom_size: usize,
+) -> Result<Option<(String, RomPatch)>, PatchError> {
+    for format in PATCH_FORMATS {
+        let file_name = sibling_file_name(rom_name, format.extension());
+        let size = match storage.file_len(&file_name) {
+            Ok(size) => size,
+            Err(StorageError::NotFound) => continue,
+            Err(error) => return Err(error.into()),
+        };
+        // The size comes from the card, so a huge file must not abort.
+        let mut data = Vec::new();
+        data.try_reserve_exact(size)
+            .map_err(|_| PatchError::OutOfMemory(size))?;
+        data.resize(size, 0);
+        let read = storage.read_file(&file_name, &mut data)?;
+        let patch = RomPatch::parse(format, &data[..read], rom_size)?;
+        log::info!(
+            "Applying {} ({} patched ranges, {} KiB ROM)",
+            file_name,
+            patch.segments.len(),
+            patch.target_size / 1024
+        );
+        return Ok(Some((file_name, patch)));
+    }
+    Ok(None)
+}
@@ -0,0 +1,78 @@
+use alloc::string::String;
+use core::fmt;
+
+use crate::storage::{FileStorage, StorageError};
+
+/// Names the game stopped by the last shutdown. Empty when there is nothing
+/// to resume.
+pub const RESUME_FILE: &str = "RESUME.TXT";
+/// Frames the low battery input has to stay asserted before the game is
+/// stopped, so a dip while the backlight or SD card draws current is ignored.
+const LOW_BATTERY_FRAMES: u32 = 60;
+
+/// Why the game is being stopped with an automatic save state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownRequest {
+    /// "Save and switch off" in the pause menu.
+    Menu,
+    /// `shutdown` on the serial console.
+    Console,
+    LowBattery,
+}
+
+impl fmt::Display for ShutdownRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownRequest::Menu => write!(f, "switch off requested from the menu"),
+            ShutdownRequest::Console => write!(f, "shutdown requested over serial"),
+            ShutdownRequest::LowBattery => write!(f, "battery low"),
+        }
+    }
+}
+
+/// Records `rom_name` so the next boot resumes it from its `.ssa` state.
+pub fn record<S: FileStorage>(storage: &mut S, rom_name: &str) -> Result<(), StorageError> {
+    storage.write_file(RESUME_FILE, rom_name.as_bytes())
+}
+
+/// Returns the game to resume, clearing the record so that a game that
+/// crashes or loses power after resuming is not rewound to the same state
+/// again on the following boot.
+pub fn take<S: FileStorage>(storage: &mut S) -> Result<Option<String>, StorageError> {
+    // Long enough for any 8.3 name.
+    let mut data = [0u8; 64];
+    let read = match storage.read_file(RESUME_FILE, &mut data) {
+        Ok(read) => read,
+        Err(StorageError::NotFound) => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let rom_name = String::from(String::from_utf8_lossy(&data[..read]).trim());
+    if rom_name.is_empty() {
+        return Ok(None);
+    }
+    storage.write_file(RESUME_FILE, &[])?;
+    Ok(Some(rom_name))
+}
+
+/// Debounces the low battery input, checked once per frame.
+pub struct LowBatteryMonitor {
+    frames: u32,
+}
+
+impl Default for LowBatteryMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LowBatteryMonitor {
+    pub fn new() -> Self {
+        Self { frames: 0 }
+    }
+
+    /// Returns `true` once the input has been low for long enough.
+    pub fn update(&mut self, low: bool) -> bool {
+        self.frames = if low { self.frames + 1 } else { 0 };
+        self.frames >= LOW_BATTERY_FRAMES
+    }
+}
@@ -0,0 +1,347 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    storage::StorageError,
+    ui::rom_browser::{is_rom_file, RomDirectory},
+};
+
+/// Where the bootloader expects the partition table.
+pub const PARTITION_TABLE_OFFSET: u32 = 0x8000;
+pub const PARTITION_TABLE_SIZE: usize = 0xC00;
+/// Label of the data partition holding the ROMs, see `partitions-gbroms.csv`.
+pub const ROM_PARTITION_LABEL: &str = "gbroms";
+/// Flash erase unit. The directory takes the first sector of the partition and
+/// every ROM starts on a sector boundary.
+pub const SECTOR_SIZE: usize = 0x1000;
+
+pub const MAGIC: [u8; 4] = *b"GBRP";
+/// Bumped whenever the layout of the directory changes.
+pub const VERSION: u16 = 1;
+const HEADER_LEN: usize = 16;
+const ENTRY_LEN: usize = 32;
+const NAME_LEN: usize = 12;
+pub const MAX_ROMS: usize = (SECTOR_SIZE - HEADER_LEN) / ENTRY_LEN;
+
+const ENTRY_MAGIC: [u8; 2] = [0xAA, 0x50];
+const PARTITION_TYPE_DATA: u8 = 0x01;
+
+/// A data partition found in the partition table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partition {
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Finds the data partition called `label` in the bootloader's partition
+/// table: 32 byte entries starting with `AA 50`, ended by an MD5 entry or
+/// erased flash.
+pub fn find_partition(table: &[u8], label: &str) -> Option<Partition> {
+    for entry in table.chunks_exact(32) {
+        if entry[0..2] != ENTRY_MAGIC {
+            return None;
+        }
+        let name = &entry[12..28];
+        let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(16)];
+        if entry[2] == PARTITION_TYPE_DATA && name == label.as_bytes() {
+            return Some(Partition {
+                offset: read_u32(entry, 4),
+                size: read_u32(entry, 8),
+            });
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    TooManyRoms(usize),
+    /// An entry points outside the partition or into the directory sector.
+    OutOfBounds(String),
+    Overlap(String),
+    BadName(String),
+    EmptyRom,
+    /// No gap is large enough for a ROM of this many bytes.
+    NoSpace(u32),
+    NotFound(String),
+}
+
+impl fmt::Display for PartitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartitionError::BadMagic => write!(f, "not a ROM partition"),
+            PartitionError::UnsupportedVersion(version) => {
+                write!(f, "unsupported ROM partition version {}", version)
+            }
+            PartitionError::TooManyRoms(count) => {
+                write!(f, "{} ROMs, at most {} fit", count, MAX_ROMS)
+            }
+            PartitionError::OutOfBounds(name) => write!(f, "{} lies outside the partition", name),
+            PartitionError::Overlap(name) => write!(f, "{} overlaps another ROM", name),
+            PartitionError::BadName(name) => write!(f, "'{}' is not an 8.3 file name", name),
+            PartitionError::EmptyRom => write!(f, "empty ROM"),
+            PartitionError::NoSpace(length) => {
+                write!(f, "no room for {} KiB, delete a ROM first", length / 1024)
+            }
+            PartitionError::NotFound(name) => write!(f, "{} is not in flash", name),
+        }
+    }
+}
+
+/// A ROM stored in the partition. `offset` is relative to the partition start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashRom {
+    pub name: String,
+    pub offset: u32,
+    pub length: u32,
+    /// CRC-32 of the ROM, checked when it is uploaded.
+    pub crc: u32,
+}
+
+impl FlashRom {
+    pub fn end(&self) -> u32 {
+        self.offset + self.length
+    }
+}
+
+/// Upper case 8.3 name of a `.gb`/`.gbc` file, so ROMs in flash share saves
+/// with a copy of the same name on the card.
+pub fn normalize_name(name: &str) -> Result<String, PartitionError> {
+    let bad_name = || PartitionError::BadName(String::from(name));
+    let (stem, extension) = name.split_once('.').unwrap_or((name, ""));
+    let valid = |part: &str, max: usize| {
+        part.len() <= max
+            && part
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || b"_-~!#$%&".contains(&byte))
+    };
+    if stem.is_empty() || !valid(stem, 8) || !valid(extension, 3) || !is_rom_file(name) {
+        return Err(bad_name());
+    }
+    Ok(name.to_ascii_uppercase())
+}
+
+/// The directory kept in the first sector of the partition:
+///
+/// | offset | size | field                            |
+/// |--------|------|----------------------------------|
+/// | 0      | 4    | magic `GBRP`                     |
+/// | 4      | 2    | format version (LE)              |
+/// | 6      | 2    | number of ROMs (LE)              |
+/// | 8      | 8    | reserved, zero                   |
+/// | 16     | 32n  | one entry per ROM                |
+///
+/// and each entry:
+///
+/// | offset | size | field                                  |
+/// |--------|------|----------------------------------------|
+/// | 0      | 12   | 8.3 name, zero padded                  |
+/// | 12     | 4    | offset from the partition start (LE)   |
+/// | 16     | 4    | length (LE)                            |
+/// | 20     | 4    | CRC-32 of the ROM (LE)                 |
+/// | 24     | 8    | reserved, zero                         |
+///
+/// ROMs start on a sector boundary after the directory. An erased sector is
+/// an empty partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomPartition {
+    size: u32,
+    roms: Vec<FlashRom>,
+}
+
+impl RomPartition {
+    pub fn empty(size: u32) -> Self {
+        Self {
+            size,
+            roms: Vec::new(),
+        }
+    }
+
+    /// Parses the directory sector of a partition of `size` bytes.
+    pub fn parse(size: u32, sector: &[u8]) -> Result<Self, PartitionError> {
+        if sector.len() < HEADER_LEN {
+            return Err(PartitionError::BadMagic);
+        }
+        if sector[0..4] == [0xFF; 4] {
+            return Ok(Self::empty(size));
+        }
+        if sector[0..4] != MAGIC {
+            return Err(PartitionError::BadMagic);
+        }
+        let version = u16::from_le_bytes([sector[4], sector[5]]);
+        if version != VERSION {
+            return Err(PartitionError::UnsupportedVersion(version));
+        }
+        let count = u16::from_le_bytes([sector[6], sector[7]]) as usize;
+        if count > MAX_ROMS || HEADER_LEN + count * ENTRY_LEN > sector.len() {
+            return Err(PartitionError::TooManyRoms(count));
+        }
+        let mut partition = Self::empty(size);
+        for entry in sector[HEADER_LEN..].chunks_exact(ENTRY_LEN).take(count) {
+            let name = &entry[..NAME_LEN];
+            let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(NAME_LEN)];
+            let name = String::from_utf8_lossy(name);
+            let rom = FlashRom {
+                name: normalize_name(&name)?,
+                offset: read_u32(entry, 12),
+                length: read_u32(entry, 16),
+                crc: read_u32(entry, 20),
+            };
+            partition.insert(rom)?;
+        }
+        Ok(partition)
+    }
+
+    pub fn roms(&self) -> &[FlashRom] {
+        &self.roms
+    }
+
+    pub fn find(&self, name: &str) -> Option<&FlashRom> {
+        self.roms
+            .iter()
+            .find(|rom| rom.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn free_bytes(&self) -> u32 {
+        let used: u32 = self.roms.iter().map(|rom| align_up(rom.length)).sum();
+        self.size.saturating_sub(SECTOR_SIZE as u32 + used)
+    }
+
+    /// Offset of the first gap that holds `length` bytes. The ROMs already
+    /// stored are left alone, so a replacement can be written before the
+    /// entry it replaces is dropped.
+    pub fn allocate(&self, length: u32) -> Result<u32, PartitionError> {
+        if length == 0 {
+            return Err(PartitionError::EmptyRom);
+        }
+        let mut roms: Vec<&FlashRom> = self.roms.iter().collect();
+        roms.sort_unstable_by_key(|rom| rom.offset);
+        let mut start = SECTOR_SIZE as u32;
+        for rom in roms {
+            if rom.offset.saturating_sub(start) >= length {
+                return Ok(start);
+            }
+            start = start.max(align_up(rom.end()));
+        }
+        match self.size.checked_sub(start) {
+            Some(free) if free >= length => Ok(start),
+            _ => Err(PartitionError::NoSpace(length)),
+        }
+    }
+
+    /// Adds `rom`, replacing a ROM of the same name.
+    pub fn insert(&mut self, rom: FlashRom) -> Result<(), PartitionError> {
+        if rom.length == 0 {
+            return Err(PartitionError::EmptyRom);
+        }
+        let in_bounds = rom.offset >= SECTOR_SIZE as u32
+            && rom.offset as usize % SECTOR_SIZE == 0
+            && rom
+                .offset
+                .checked_add(rom.length)
+                .is_some_and(|end| end <= self.size);
+        if !in_bounds {
+            return Err(PartitionError::OutOfBounds(rom.name));
+        }
+        let others = self
+            .roms
+            .iter()
+            .filter(|other| !other.name.eq_ignore_ascii_case(&rom.name));
+        for other in others {
+            if rom.offset < other.end() && other.offset < rom.end() {
+                return Err(PartitionError::Overlap(rom.name));
+            }
+        }
+        match self
+            .roms
+            .iter()
+            .position(|other| other.name.eq_ignore_ascii_case(&rom.name))
+        {
+            Some(index) => self.roms[index] = rom,
+            None if self.roms.len() == MAX_ROMS => {
+                return Err(PartitionError::TooManyRoms(MAX_ROMS + 1))
+            }
+            None => self.roms.push(rom),
+        }
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<FlashRom, PartitionError> {
+        match self
+            .roms
+            .iter()
+            .position(|rom| rom.name.eq_ignore_ascii_case(name))
+        {
+            Some(index) => Ok(self.roms.remove(index)),
+            None => Err(PartitionError::NotFound(String::from(name))),
+        }
+    }
+
+    /// The directory sector, padded with 0xFF like erased flash.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut sector = vec![0xFF; SECTOR_SIZE];
+        sector[0..4].copy_from_slice(&MAGIC);
+        sector[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        sector[6..8].copy_from_slice(&(self.roms.len() as u16).to_le_bytes());
+        sector[8..HEADER_LEN].fill(0);
+        for (rom, entry) in self
+            .roms
+            .iter()
+            .zip(sector[HEADER_LEN..].chunks_exact_mut(ENTRY_LEN))
+        {
+            entry.fill(0);
+            entry[..rom.name.len()].copy_from_slice(rom.name.as_bytes());
+            entry[12..16].copy_from_slice(&rom.offset.to_le_bytes());
+            entry[16..20].copy_from_slice(&rom.length.to_le_bytes());
+            entry[20..24].copy_from_slice(&rom.crc.to_le_bytes());
+        }
+        sector
+    }
+}
+
+/// The partition as mapped into the address space, listed in the ROM browser.
+pub struct MappedRoms<'a> {
+    pub partition: &'a RomPartition,
+    pub data: &'a [u8],
+}
+
+impl<'a> MappedRoms<'a> {
+    pub fn rom(&self, name: &str) -> Option<&'a [u8]> {
+        let rom = self.partition.find(name)?;
+        self.data.get(rom.offset as usize..rom.end() as usize)
+    }
+}
+
+impl RomDirectory for MappedRoms<'_> {
+    fn for_each_file(&mut self, f: &mut dyn FnMut(&str)) -> Result<(), StorageError> {
+        for rom in self.partition.roms() {
+            f(&rom.name);
+        }
+        Ok(())
+    }
+
+    fn read_start(&mut self, name: &str, buffer: &mut [u8]) -> usize {
+        let rom = match self.rom(name) {
+            Some(rom) => rom,
+            None => return 0,
+        };
+        let read = rom.len().min(buffer.len());
+        buffer[..read].copy_from_slice(&rom[..read]);
+        read
+    }
+}
+
+fn align_up(offset: u32) -> u32 {
+    offset.div_ceil(SECTOR_SIZE as u32) * SECTOR_SIZE as u32
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
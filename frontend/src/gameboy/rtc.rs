@@ -0,0 +1,137 @@
+/// Size of the RTC block appended to `.sav` files by VBA, BGB, SameBoy and
+/// most other emulators.
+pub const TRAILER_LEN: usize = 48;
+/// Older emulators wrote a 32-bit timestamp instead.
+const SHORT_TRAILER_LEN: usize = 44;
+
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+const DAY_HIGH: u8 = 0x01;
+const DAY_COUNTER_RANGE: u64 = 512;
+
+/// The five MBC3 clock registers (0x08-0x0C).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days_low: u8,
+    /// Bit 0: day counter bit 8, bit 6: halt, bit 7: day counter carry.
+    pub days_high: u8,
+}
+
+impl RtcRegisters {
+    pub fn from_bytes(bytes: [u8; 5]) -> Self {
+        Self {
+            seconds: bytes[0],
+            minutes: bytes[1],
+            hours: bytes[2],
+            days_low: bytes[3],
+            days_high: bytes[4],
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_low,
+            self.days_high,
+        ]
+    }
+
+    pub fn halted(&self) -> bool {
+        self.days_high & HALT != 0
+    }
+
+    pub fn days(&self) -> u16 {
+        (((self.days_high & DAY_HIGH) as u16) << 8) | self.days_low as u16
+    }
+
+    /// Runs the clock forward by `elapsed` seconds, as the cartridge would have
+    /// while the console was switched off. A halted clock does not move.
+    pub fn advance(&mut self, elapsed: u64) {
+        if self.halted() || elapsed == 0 {
+            return;
+        }
+        // Games can store out-of-range values (e.g. 70 seconds); they are
+        // folded into the larger units instead of emulating the odd rollover.
+        let total = self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days() as u64 * 86_400
+            + elapsed;
+        let mut days = total / 86_400;
+        if days >= DAY_COUNTER_RANGE {
+            days %= DAY_COUNTER_RANGE;
+            self.days_high |= DAY_CARRY;
+        }
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        self.days_low = days as u8;
+        self.days_high = (self.days_high & !DAY_HIGH) | (days >> 8) as u8 & DAY_HIGH;
+    }
+}
+
+/// The clock of an MBC3 cartridge: the running registers and the copy the
+/// game last latched for reading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcState {
+    pub current: RtcRegisters,
+    pub latched: RtcRegisters,
+}
+
+/// RTC registers as stored after the cartridge RAM in a `.sav` file: five
+/// current and five latched registers as 32-bit little-endian words, then the
+/// Unix time of the save as a 64-bit little-endian word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcTrailer {
+    pub current: RtcRegisters,
+    pub latched: RtcRegisters,
+    pub saved_at: u64,
+}
+
+impl RtcTrailer {
+    pub fn encode(&self) -> [u8; TRAILER_LEN] {
+        let mut data = [0u8; TRAILER_LEN];
+        let registers = self.current.to_bytes().into_iter();
+        let latched = self.latched.to_bytes().into_iter();
+        for (word, value) in data.chunks_exact_mut(4).zip(registers.chain(latched)) {
+            word.copy_from_slice(&(value as u32).to_le_bytes());
+        }
+        data[40..48].copy_from_slice(&self.saved_at.to_le_bytes());
+        data
+    }
+
+    /// Accepts both the 48-byte and the older 44-byte layout.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let saved_at = match data.len() {
+            TRAILER_LEN => u64::from_le_bytes(data[40..48].try_into().ok()?),
+            SHORT_TRAILER_LEN => u32::from_le_bytes(data[40..44].try_into().ok()?) as u64,
+            _ => return None,
+        };
+        let mut registers = [0u8; 10];
+        for (register, word) in registers.iter_mut().zip(data.chunks_exact(4)) {
+            *register = word[0];
+        }
+        Some(Self {
+            current: RtcRegisters::from_bytes(registers[..5].try_into().ok()?),
+            latched: RtcRegisters::from_bytes(registers[5..].try_into().ok()?),
+            saved_at,
+        })
+    }
+
+    /// The clock with the current registers moved forward to `now`. A clock
+    /// that went backwards (e.g. the RTC lost power) leaves them as saved.
+    /// The latched copy only changes when the game latches again.
+    pub fn resume(&self, now: u64) -> RtcState {
+        let mut current = self.current;
+        current.advance(now.saturating_sub(self.saved_at));
+        RtcState {
+            current,
+            latched: self.latched,
+        }
+    }
+}
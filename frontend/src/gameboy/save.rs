@@ -0,0 +1,188 @@
+use alloc::{string::String, vec, vec::Vec};
+
+use super::rtc::{RtcState, RtcTrailer, TRAILER_LEN};
+use crate::{
+    clock::Clock,
+    storage::{sibling_file_name, FileStorage, StorageError},
+};
+
+/// How often cartridge RAM is checked for changes.
+const CHECK_INTERVAL_MILLIS: u64 = 500;
+/// How long cartridge RAM has to stay untouched before it is written back.
+const IDLE_FLUSH_MILLIS: u64 = 2_000;
+
+/// Keeps battery-backed cartridge RAM in sync with a `.sav` file holding a raw
+/// dump of external RAM, the same layout other emulators use. Cartridges with
+/// an MBC3 clock get the clock registers appended as an [`RtcTrailer`].
+pub struct BatterySave {
+    file_name: String,
+    size: usize,
+    last_hash: u32,
+    /// The clock registers as last loaded or written.
+    last_rtc: Option<RtcState>,
+    last_check: u64,
+    last_change: u64,
+    dirty: bool,
+}
+
+impl BatterySave {
+    /// `size` is the external RAM size declared in the cartridge header.
+    pub fn new(rom_name: &str, size: usize) -> Self {
+        Self {
+            file_name: sibling_file_name(rom_name, "SAV"),
+            size,
+            last_hash: 0,
+            last_rtc: None,
+            last_check: 0,
+            last_change: 0,
+            dirty: false,
+        }
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Fills `ram` from the save file, zeroing whatever a short save does not
+    /// cover. Returns `false`, leaving `ram` alone, when there is no save yet.
+    /// If `rtc` is given it is set from the trailer and advanced by the time
+    /// that passed on `clock` since the save was written.
+    pub fn load<S: FileStorage, C: Clock>(
+        &mut self,
+        storage: &mut S,
+        ram: &mut [u8],
+        rtc: Option<&mut RtcState>,
+        clock: &C,
+    ) -> Result<bool, StorageError> {
+        let ram = self.saved_part_mut(ram);
+        let loaded = match rtc {
+            None => {
+                let loaded = self.read(storage, ram, ram.len())?;
+                if let Some(size) = loaded {
+                    ram[size..].fill(0);
+                }
+                loaded
+            }
+            Some(rtc) => {
+                let mut data = vec![0u8; ram.len() + TRAILER_LEN];
+                let loaded = self.read(storage, &mut data, ram.len())?;
+                let trailer = data.get(ram.len()..loaded.unwrap_or(0));
+                match trailer.and_then(RtcTrailer::decode) {
+                    Some(trailer) => *rtc = trailer.resume(clock.unix_seconds()),
+                    None if loaded.is_some() => {
+                        log::warn!(
+                            "{} has no clock data, the RTC starts at zero",
+                            self.file_name
+                        )
+                    }
+                    None => {}
+                }
+                if loaded.is_some() {
+                    let size = ram.len();
+                    ram.copy_from_slice(&data[..size]);
+                }
+                self.last_rtc = Some(*rtc);
+                loaded
+            }
+        };
+        self.last_hash = hash(ram);
+        self.dirty = false;
+        Ok(loaded.is_some())
+    }
+
+    /// Reads the save into `buffer`, returning the file size or `None` if
+    /// there is no save. `expected` is the size of the RAM part.
+    fn read<S: FileStorage>(
+        &self,
+        storage: &mut S,
+        buffer: &mut [u8],
+        expected: usize,
+    ) -> Result<Option<usize>, StorageError> {
+        match storage.read_file(&self.file_name, buffer) {
+            Ok(size) => {
+                if size < expected {
+                    log::warn!(
+                        "{} holds {} bytes, cartridge has {} bytes of RAM",
+                        self.file_name,
+                        size,
+                        expected
+                    );
+                }
+                Ok(Some(size))
+            }
+            Err(StorageError::NotFound) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Called once per frame; returns `true` when RAM has changed and writes
+    /// have stopped long enough for a flush to be worthwhile.
+    pub fn poll(&mut self, ram: &[u8], now_millis: u64) -> bool {
+        let ram = self.saved_part(ram);
+        if now_millis.saturating_sub(self.last_check) >= CHECK_INTERVAL_MILLIS {
+            self.last_check = now_millis;
+            let current = hash(ram);
+            if current != self.last_hash {
+                self.last_hash = current;
+                self.last_change = now_millis;
+                self.dirty = true;
+            }
+        }
+        self.dirty && now_millis.saturating_sub(self.last_change) >= IDLE_FLUSH_MILLIS
+    }
+
+    /// Writes `ram` (and `rtc`, stamped with the time on `clock`) to the save
+    /// file if RAM or the clock changed since the last flush. On failure the
+    /// save stays dirty so the next flush tries again.
+    pub fn flush<S: FileStorage, C: Clock>(
+        &mut self,
+        storage: &mut S,
+        ram: &[u8],
+        rtc: Option<&RtcState>,
+        clock: &C,
+    ) -> Result<(), StorageError> {
+        let ram = self.saved_part(ram);
+        let current = hash(ram);
+        if !self.dirty && current == self.last_hash && rtc == self.last_rtc.as_ref() {
+            return Ok(());
+        }
+        match rtc {
+            None => storage.write_file(&self.file_name, ram)?,
+            Some(rtc) => {
+                let trailer = RtcTrailer {
+                    current: rtc.current,
+                    latched: rtc.latched,
+                    saved_at: clock.unix_seconds(),
+                };
+                let mut data = Vec::with_capacity(ram.len() + TRAILER_LEN);
+                data.extend_from_slice(ram);
+                data.extend_from_slice(&trailer.encode());
+                storage.write_file(&self.file_name, &data)?;
+            }
+        }
+        self.last_hash = current;
+        self.last_rtc = rtc.copied();
+        self.dirty = false;
+        log::info!("Saved {} bytes to {}", ram.len(), self.file_name);
+        Ok(())
+    }
+
+    fn saved_part<'r>(&self, ram: &'r [u8]) -> &'r [u8] {
+        &ram[..self.size.min(ram.len())]
+    }
+
+    fn saved_part_mut<'r>(&self, ram: &'r mut [u8]) -> &'r mut [u8] {
+        let size = self.size.min(ram.len());
+        &mut ram[..size]
+    }
+}
+
+/// FNV-1a, cheap enough to run over cartridge RAM a couple of times a second.
+fn hash(data: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in data {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
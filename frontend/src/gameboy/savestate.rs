@@ -0,0 +1,180 @@
+use alloc::{format, string::String, vec, vec::Vec};
+
+use crate::{
+    checksum::crc32,
+    storage::{sibling_file_name, FileStorage, StorageError},
+};
+
+pub const MAGIC: [u8; 4] = *b"GBSS";
+/// Bumped whenever the layout of the header or of the emulator snapshot changes.
+pub const VERSION: u16 = 1;
+pub const SLOT_COUNT: u8 = 10;
+const HEADER_LEN: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    Missing,
+    Storage(StorageError),
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u16),
+    RomMismatch { expected: u32, found: u32 },
+    Corrupted,
+}
+
+impl From<StorageError> for SaveStateError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::NotFound => SaveStateError::Missing,
+            error => SaveStateError::Storage(error),
+        }
+    }
+}
+
+/// Wraps an emulator snapshot with the header used by `.ss0`-`.ss9` files:
+///
+/// | offset | size | field                       |
+/// |--------|------|-----------------------------|
+/// | 0      | 4    | magic `GBSS`                |
+/// | 4      | 2    | format version (LE)         |
+/// | 6      | 2    | reserved, zero              |
+/// | 8      | 4    | CRC-32 of ROM bank 0 (LE)   |
+/// | 12     | 4    | snapshot length (LE)        |
+/// | 16     | 4    | CRC-32 of the snapshot (LE) |
+pub fn encode(rom_checksum: u32, snapshot: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(HEADER_LEN + snapshot.len());
+    data.extend_from_slice(&MAGIC);
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&rom_checksum.to_le_bytes());
+    data.extend_from_slice(&(snapshot.len() as u32).to_le_bytes());
+    data.extend_from_slice(&crc32(snapshot).to_le_bytes());
+    data.extend_from_slice(snapshot);
+    data
+}
+
+/// Validates the header and returns the emulator snapshot it carries.
+pub fn decode(rom_checksum: u32, data: &[u8]) -> Result<&[u8], SaveStateError> {
+    if data.len() < HEADER_LEN {
+        return Err(SaveStateError::Truncated);
+    }
+    if data[0..4] != MAGIC {
+        return Err(SaveStateError::BadMagic);
+    }
+    let version = u16::from_le_bytes([data[4], data[5]]);
+    if version != VERSION {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+    let found = read_u32(data, 8);
+    if found != rom_checksum {
+        return Err(SaveStateError::RomMismatch {
+            expected: rom_checksum,
+            found,
+        });
+    }
+    let length = read_u32(data, 12) as usize;
+    // The length comes from the file, so it may point past the end of memory.
+    let snapshot = HEADER_LEN
+        .checked_add(length)
+        .and_then(|end| data.get(HEADER_LEN..end))
+        .ok_or(SaveStateError::Truncated)?;
+    if crc32(snapshot) != read_u32(data, 16) {
+        return Err(SaveStateError::Corrupted);
+    }
+    Ok(snapshot)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Tracks the selected slot and moves snapshots between the emulator and storage.
+pub struct SaveStates {
+    rom_name: String,
+    rom_checksum: u32,
+    slot: u8,
+}
+
+impl SaveStates {
+    pub fn new(rom_name: &str, rom_checksum: u32) -> Self {
+        Self {
+            rom_name: String::from(rom_name),
+            rom_checksum,
+            slot: 0,
+        }
+    }
+
+    pub fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    pub fn next_slot(&mut self) {
+        self.slot = (self.slot + 1) % SLOT_COUNT;
+    }
+
+    pub fn previous_slot(&mut self) {
+        self.slot = (self.slot + SLOT_COUNT - 1) % SLOT_COUNT;
+    }
+
+    pub fn file_name(&self) -> String {
+        sibling_file_name(&self.rom_name, &format!("SS{}", self.slot))
+    }
+
+    /// The `.ssa` file written on shutdown and restored by auto-resume, kept
+    /// apart from the slots the player manages.
+    pub fn auto_file_name(&self) -> String {
+        sibling_file_name(&self.rom_name, "SSA")
+    }
+
+    pub fn save<S: FileStorage>(
+        &self,
+        storage: &mut S,
+        snapshot: &[u8],
+    ) -> Result<(), StorageError> {
+        self.write(storage, &self.file_name(), snapshot)
+    }
+
+    pub fn load<S: FileStorage>(&self, storage: &mut S) -> Result<Vec<u8>, SaveStateError> {
+        self.read(storage, &self.file_name())
+    }
+
+    pub fn save_auto<S: FileStorage>(
+        &self,
+        storage: &mut S,
+        snapshot: &[u8],
+    ) -> Result<(), StorageError> {
+        self.write(storage, &self.auto_file_name(), snapshot)
+    }
+
+    pub fn load_auto<S: FileStorage>(&self, storage: &mut S) -> Result<Vec<u8>, SaveStateError> {
+        self.read(storage, &self.auto_file_name())
+    }
+
+    fn write<S: FileStorage>(
+        &self,
+        storage: &mut S,
+        file_name: &str,
+        snapshot: &[u8],
+    ) -> Result<(), StorageError> {
+        storage.write_file(file_name, &encode(self.rom_checksum, snapshot))
+    }
+
+    fn read<S: FileStorage>(
+        &self,
+        storage: &mut S,
+        file_name: &str,
+    ) -> Result<Vec<u8>, SaveStateError> {
+        let size = storage.file_len(file_name)?;
+        let mut data = vec![0u8; size];
+        let read = storage.read_file(file_name, &mut data)?;
+        let length = decode(self.rom_checksum, &data[..read])?.len();
+        data.drain(..HEADER_LEN);
+        data.truncate(length);
+        Ok(data)
+    }
+}
@@ -0,0 +1,14 @@
+#![no_std]
+
+extern crate alloc;
+
+pub mod archive;
+pub mod checksum;
+pub mod clock;
+pub mod config;
+pub mod console;
+pub mod gameboy;
+pub mod presenter;
+pub mod scaler;
+pub mod storage;
+pub mod ui;
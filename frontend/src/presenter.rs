@@ -0,0 +1,221 @@
+use alloc::{format, vec, vec::Vec};
+
+use crate::{
+    config::Scaling,
+    storage::{FileStorage, StorageError},
+};
+
+/// Size of the emulated screen.
+pub const GB_WIDTH: usize = 160;
+pub const GB_HEIGHT: usize = 144;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// Where a frame goes on a panel for one `Scaling` mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Part of the 160x144 frame that is shown, smaller than the frame when
+    /// the integer modes do not fit.
+    pub source: Rect,
+    /// Where that part ends up on the panel.
+    pub target: Rect,
+}
+
+impl Layout {
+    pub fn new(scaling: Scaling, panel_width: u16, panel_height: u16) -> Self {
+        let (frame_width, frame_height) = (GB_WIDTH as u16, GB_HEIGHT as u16);
+        let (source, width, height) = match scaling {
+            Scaling::Native | Scaling::Double => {
+                let factor = if scaling == Scaling::Double { 2 } else { 1 };
+                let width = frame_width.min(panel_width / factor);
+                let height = frame_height.min(panel_height / factor);
+                let source = Rect {
+                    x: (frame_width - width) / 2,
+                    y: (frame_height - height) / 2,
+                    width,
+                    height,
+                };
+                (source, width * factor, height * factor)
+            }
+            Scaling::Fit => {
+                let full = Rect {
+                    width: frame_width,
+                    height: frame_height,
+                    ..Rect::default()
+                };
+                let panel_width = panel_width as u32;
+                let panel_height = panel_height as u32;
+                if panel_width * frame_height as u32 <= panel_height * frame_width as u32 {
+                    let height = panel_width * frame_height as u32 / frame_width as u32;
+                    (full, panel_width as u16, height as u16)
+                } else {
+                    let width = panel_height * frame_width as u32 / frame_height as u32;
+                    (full, width as u16, panel_height as u16)
+                }
+            }
+            Scaling::Stretch => {
+                let full = Rect {
+                    width: frame_width,
+                    height: frame_height,
+                    ..Rect::default()
+                };
+                (full, panel_width, panel_height)
+            }
+        };
+        Self {
+            source,
+            target: Rect {
+                x: (panel_width - width) / 2,
+                y: (panel_height - height) / 2,
+                width,
+                height,
+            },
+        }
+    }
+
+    /// The letterbox around `target`: top, bottom, left and right, some of
+    /// them empty.
+    pub fn borders(&self, panel_width: u16, panel_height: u16) -> [Rect; 4] {
+        let target = self.target;
+        let below = target.y + target.height;
+        let right = target.x + target.width;
+        [
+            Rect {
+                x: 0,
+                y: 0,
+                width: panel_width,
+                height: target.y,
+            },
+            Rect {
+                x: 0,
+                y: below,
+                width: panel_width,
+                height: panel_height - below,
+            },
+            Rect {
+                x: 0,
+                y: target.y,
+                width: target.x,
+                height: target.height,
+            },
+            Rect {
+                x: right,
+                y: target.y,
+                width: panel_width - right,
+                height: target.height,
+            },
+        ]
+    }
+}
+
+/// Somewhere emulated frames are shown: the LCD, or image files on the host.
+pub trait FramePresenter {
+    type Error;
+
+    /// Shows one frame of `GB_WIDTH` x `GB_HEIGHT` RGB565 pixels, row by row.
+    /// The frame may end early when the game switches the screen off.
+    fn present(&mut self, frame: &mut dyn Iterator<Item = u16>) -> Result<(), Self::Error>;
+}
+
+const BMP_HEADER_LEN: usize = 54;
+
+/// Writes every frame to `storage` as a 24-bit BMP, `GB000000.BMP` onwards.
+/// Pixels missing from a frame that ended early keep the previous frame's.
+pub struct ImagePresenter<S: FileStorage> {
+    storage: S,
+    frame: Vec<u16>,
+    frame_number: u32,
+}
+
+impl<S: FileStorage> ImagePresenter<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            frame: vec![0; GB_WIDTH * GB_HEIGHT],
+            frame_number: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.storage
+    }
+}
+
+impl<S: FileStorage> FramePresenter for ImagePresenter<S> {
+    type Error = StorageError;
+
+    fn present(&mut self, frame: &mut dyn Iterator<Item = u16>) -> Result<(), StorageError> {
+        for (pixel, color) in self.frame.iter_mut().zip(frame) {
+            *pixel = color;
+        }
+        let file_name = format!("GB{:06}.BMP", self.frame_number % 1_000_000);
+        self.frame_number += 1;
+        self.storage
+            .write_file(&file_name, &encode_bmp(&self.frame, GB_WIDTH, GB_HEIGHT))
+    }
+}
+
+/// Encodes RGB565 `pixels`, row by row from the top, as a 24-bit BMP.
+pub fn encode_bmp(pixels: &[u16], width: usize, height: usize) -> Vec<u8> {
+    let row_len = (width * 3).next_multiple_of(4);
+    let file_len = BMP_HEADER_LEN + row_len * height;
+    let mut data = Vec::with_capacity(file_len);
+    data.extend_from_slice(b"BM");
+    data.extend_from_slice(&(file_len as u32).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&(BMP_HEADER_LEN as u32).to_le_bytes());
+    // BITMAPINFOHEADER
+    data.extend_from_slice(&40u32.to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    data.extend_from_slice(&(height as i32).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&24u16.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&((row_len * height) as u32).to_le_bytes());
+    data.extend_from_slice(&2835i32.to_le_bytes());
+    data.extend_from_slice(&2835i32.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    // Rows are stored bottom up, each pixel as blue, green, red.
+    for row in pixels.chunks_exact(width).take(height).rev() {
+        for pixel in row {
+            let [red, green, blue] = rgb888(*pixel);
+            data.extend_from_slice(&[blue, green, red]);
+        }
+        data.resize(data.len() + row_len - width * 3, 0);
+    }
+    data
+}
+
+/// Expands RGB565 to 8 bits per channel, so white stays white.
+pub fn rgb888(pixel: u16) -> [u8; 3] {
+    let red = (pixel >> 11) as u8 & 0x1F;
+    let green = (pixel >> 5) as u8 & 0x3F;
+    let blue = pixel as u8 & 0x1F;
+    [
+        red << 3 | red >> 2,
+        green << 2 | green >> 4,
+        blue << 3 | blue >> 2,
+    ]
+}
+
+/// The colour in `colors` (lightest first) for the shade of grey nearest to
+/// the brightness of `pixel`, to recolour games made for the original Game Boy.
+pub fn recolor(pixel: u16, colors: &[u16; 4]) -> u16 {
+    let [red, green, blue] = rgb888(pixel);
+    let luma = (red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) / 1000;
+    let level = (luma * 3 + 127) / 255;
+    colors[3 - level as usize]
+}
@@ -0,0 +1,319 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{config::Filter, presenter::Rect};
+
+/// Source lines kept while scaling: the current one and the ones above and
+/// below it for Scale2x. Bilinear filtering only needs the one below.
+const HISTORY_LINES: usize = 3;
+/// Blending weights are in 32nds, the resolution of RGB565's red and blue.
+const WEIGHT_BITS: u32 = 5;
+const WEIGHT_ONE: u32 = 1 << WEIGHT_BITS;
+/// RGB565 spread over 32 bits as `00000GGGGGG00000RRRRR000000BBBBB`, leaving
+/// room above every channel to multiply it by a weight.
+const SPREAD_MASK: u32 = 0x07E0_F81F;
+/// Half a step of every spread channel, so blends round to nearest.
+const SPREAD_HALF: u32 = 0x0200_8010;
+
+/// Where an output column or row reads from in the source.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// Source pixel, or the first of the two that are blended.
+    index: u16,
+    /// Weight of the next source pixel in 32nds for the bilinear filters,
+    /// which half of the source pixel is shown for Scale2x, unused otherwise.
+    fraction: u8,
+}
+
+/// Scales RGB565 frames one line at a time, keeping only a few source lines
+/// so it fits in internal RAM. Sizes and the cropped part of the input are
+/// runtime values, so one scaler serves every panel orientation and scaling
+/// mode.
+pub struct ScreenScaler {
+    in_width: u16,
+    source: Rect,
+    filter: Filter,
+    columns: Vec<Sample>,
+    rows: Vec<Sample>,
+    /// `HISTORY_LINES` source lines, line `n` in slot `n % HISTORY_LINES`.
+    history: Vec<u16>,
+    /// The output line being sent.
+    line: Vec<u16>,
+}
+
+impl ScreenScaler {
+    /// Scales the `source` part of frames `in_width` pixels wide to
+    /// `out_width` x `out_height`, nearest neighbour until `with_filter`.
+    pub fn new(in_width: u16, source: Rect, out_width: u16, out_height: u16) -> Self {
+        let (width, height) = if source.is_empty() {
+            (0, 0)
+        } else {
+            (out_width, out_height)
+        };
+        Self {
+            in_width,
+            source,
+            filter: Filter::Nearest,
+            columns: samples(Filter::Nearest, source.width, width),
+            rows: samples(Filter::Nearest, source.height, height),
+            history: vec![0; HISTORY_LINES * source.width as usize],
+            line: vec![0; width as usize],
+        }
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self.columns = samples(filter, self.source.width, self.out_width());
+        self.rows = samples(filter, self.source.height, self.out_height());
+        self
+    }
+
+    pub fn out_width(&self) -> u16 {
+        self.line.len() as u16
+    }
+
+    pub fn out_height(&self) -> u16 {
+        self.rows.len() as u16
+    }
+
+    /// Reads `iterator` to its end even after the last visible line, so the
+    /// emulator always finishes the frame. Nothing is allocated per frame.
+    #[inline(always)]
+    pub fn scale_iterator<'a, I>(&'a mut self, iterator: I) -> impl Iterator<Item = u16> + 'a
+    where
+        I: Iterator<Item = u16> + 'a,
+    {
+        let line_len = self.line.len();
+        ScalerIterator {
+            iterator,
+            in_width: self.in_width,
+            source: self.source,
+            filter: self.filter,
+            columns: &self.columns,
+            rows: &self.rows,
+            history: &mut self.history,
+            line: &mut self.line,
+            input_y: 0,
+            loaded_lines: 0,
+            output_y: 0,
+            output_x: line_len,
+            line_source: None,
+        }
+    }
+}
+
+struct ScalerIterator<'a, I: Iterator<Item = u16>> {
+    iterator: I,
+    in_width: u16,
+    source: Rect,
+    filter: Filter,
+    columns: &'a [Sample],
+    rows: &'a [Sample],
+    history: &'a mut [u16],
+    line: &'a mut [u16],
+    input_y: u16,
+    /// Source lines read into `history` so far.
+    loaded_lines: u16,
+    /// The next output line to compute.
+    output_y: u16,
+    /// Position in `line`, at its end once the line is sent.
+    output_x: usize,
+    /// Source line `line` was computed from with `Filter::Nearest`, which
+    /// is reused while the following output lines show the same one.
+    line_source: Option<u16>,
+}
+
+impl<'a, I> Iterator for ScalerIterator<'a, I>
+where
+    I: Iterator<Item = u16>,
+{
+    type Item = u16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.output_x < self.line.len() {
+                let pixel = self.line[self.output_x];
+                self.output_x += 1;
+                return Some(pixel);
+            }
+
+            let row = match self.rows.get(self.output_y as usize) {
+                Some(row) => *row,
+                None => {
+                    // Lines below the source are read and dropped.
+                    for _ in self.iterator.by_ref() {}
+                    return None;
+                }
+            };
+            let last_line = self.source.height - 1;
+            let needed = match self.filter {
+                Filter::Nearest => row.index,
+                _ => (row.index + 1).min(last_line),
+            };
+            while self.loaded_lines <= needed {
+                self.load_line()?;
+            }
+            self.compute_line(row);
+            self.output_y += 1;
+            self.output_x = 0;
+        }
+    }
+}
+
+impl<'a, I> ScalerIterator<'a, I>
+where
+    I: Iterator<Item = u16>,
+{
+    /// Reads input lines until the next one inside the source is stored.
+    fn load_line(&mut self) -> Option<()> {
+        let width = self.source.width as usize;
+        loop {
+            let source_y = self.input_y.wrapping_sub(self.source.y);
+            let visible = source_y < self.source.height;
+            let slot = self.loaded_lines as usize % HISTORY_LINES * width;
+            for input_x in 0..self.in_width {
+                let pixel = self.iterator.next()?;
+                let source_x = input_x.wrapping_sub(self.source.x);
+                if visible && source_x < self.source.width {
+                    self.history[slot + source_x as usize] = pixel;
+                }
+            }
+            self.input_y += 1;
+            if visible {
+                self.loaded_lines += 1;
+                return Some(());
+            }
+        }
+    }
+
+    fn compute_line(&mut self, row: Sample) {
+        let last_column = self.source.width as usize - 1;
+        let source_line = |index: u16| {
+            let width = self.source.width as usize;
+            let slot = index.min(self.source.height - 1) as usize % HISTORY_LINES * width;
+            &self.history[slot..slot + width]
+        };
+        match self.filter {
+            Filter::Nearest => {
+                if self.line_source == Some(row.index) {
+                    return;
+                }
+                self.line_source = Some(row.index);
+                let source = source_line(row.index);
+                for (pixel, column) in self.line.iter_mut().zip(self.columns) {
+                    *pixel = source[column.index as usize];
+                }
+            }
+            Filter::Bilinear | Filter::SharpBilinear => {
+                let top = source_line(row.index);
+                let bottom = source_line(row.index + 1);
+                for (pixel, column) in self.line.iter_mut().zip(self.columns) {
+                    let left = column.index as usize;
+                    let right = (left + 1).min(last_column);
+                    let upper = blend(spread(top[left]), spread(top[right]), column.fraction);
+                    let lower = blend(spread(bottom[left]), spread(bottom[right]), column.fraction);
+                    *pixel = pack(blend(upper, lower, row.fraction));
+                }
+            }
+            Filter::Scale2x => {
+                let center = source_line(row.index);
+                let above = source_line(row.index.saturating_sub(1));
+                let below = source_line(row.index + 1);
+                let lower_half = row.fraction != 0;
+                for (pixel, column) in self.line.iter_mut().zip(self.columns) {
+                    let x = column.index as usize;
+                    let right_half = column.fraction != 0;
+                    let left = center[x.saturating_sub(1)];
+                    let right = center[(x + 1).min(last_column)];
+                    let (vertical, horizontal, opposite_vertical, opposite_horizontal) =
+                        match (lower_half, right_half) {
+                            (false, false) => (above[x], left, below[x], right),
+                            (false, true) => (above[x], right, below[x], left),
+                            (true, false) => (below[x], left, above[x], right),
+                            (true, true) => (below[x], right, above[x], left),
+                        };
+                    *pixel = if vertical == horizontal
+                        && vertical != opposite_horizontal
+                        && horizontal != opposite_vertical
+                    {
+                        vertical
+                    } else {
+                        center[x]
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Where each of `out_size` output pixels reads from `in_size` source pixels.
+fn samples(filter: Filter, in_size: u16, out_size: u16) -> Vec<Sample> {
+    let (in_size, out_size) = (in_size as u32, out_size as u32);
+    (0..out_size)
+        .map(|out| match filter {
+            // Output pixel `out` shows source pixel `out * in_size / out_size`.
+            Filter::Nearest => Sample {
+                index: (out * in_size / out_size) as u16,
+                fraction: 0,
+            },
+            // Pixel centres line up: `out + 0.5` maps to `source + 0.5`.
+            Filter::Bilinear => {
+                let position = ((2 * out + 1) * in_size * WEIGHT_ONE + out_size) / (2 * out_size);
+                clamp_sample(position.saturating_sub(WEIGHT_ONE / 2), in_size)
+            }
+            // Bilinear sampling of the picture first enlarged by the largest
+            // integer factor that fits, using nearest neighbour.
+            Filter::SharpBilinear => {
+                let scale = (out_size / in_size).max(1) as f32;
+                let center = (out as f32 + 0.5) * in_size as f32 / out_size as f32;
+                let pixel = center as u32 as f32;
+                let offset = center - pixel - 0.5;
+                let edge = 0.5 - 0.5 / scale;
+                let sharpened = (offset - offset.clamp(-edge, edge)) * scale;
+                let position = (pixel + sharpened).max(0.0);
+                clamp_sample((position * WEIGHT_ONE as f32 + 0.5) as u32, in_size)
+            }
+            // Each source pixel becomes two, enlarged further by nearest
+            // neighbour when the output is more than twice the source.
+            Filter::Scale2x => {
+                let half = out * 2 * in_size / out_size;
+                Sample {
+                    index: (half / 2) as u16,
+                    fraction: (half % 2) as u8,
+                }
+            }
+        })
+        .collect()
+}
+
+/// A bilinear sample at `position` 32nds of a pixel, kept inside the source.
+fn clamp_sample(position: u32, in_size: u32) -> Sample {
+    let index = position >> WEIGHT_BITS;
+    if index + 1 >= in_size {
+        Sample {
+            index: (in_size - 1) as u16,
+            fraction: 0,
+        }
+    } else {
+        Sample {
+            index: index as u16,
+            fraction: (position % WEIGHT_ONE) as u8,
+        }
+    }
+}
+
+#[inline(always)]
+fn spread(pixel: u16) -> u32 {
+    (pixel as u32 | (pixel as u32) << 16) & SPREAD_MASK
+}
+
+#[inline(always)]
+fn pack(spread: u32) -> u16 {
+    (spread | spread >> 16) as u16
+}
+
+/// `from` moved `weight` 32nds of the way to `to`, every channel at once.
+#[inline(always)]
+fn blend(from: u32, to: u32, weight: u8) -> u32 {
+    let weight = weight as u32;
+    ((from * (WEIGHT_ONE - weight) + to * weight + SPREAD_HALF) >> WEIGHT_BITS) & SPREAD_MASK
+}
@@ -0,0 +1,69 @@
+use alloc::string::String;
+use core::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    NotFound,
+    Full,
+    /// Built without a card slot, or the card could not be opened.
+    NoCard,
+    /// Any other card or filesystem failure, with the driver's description.
+    Device(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::NotFound => write!(f, "file not found"),
+            StorageError::Full => write!(f, "SD card is full"),
+            StorageError::NoCard => write!(f, "no SD card"),
+            StorageError::Device(detail) => write!(f, "SD card error: {}", detail),
+        }
+    }
+}
+
+/// Flat file access used by the frontend for saves and other per-game data.
+pub trait FileStorage {
+    /// Reads `name` into `buffer`, returning the number of bytes read.
+    fn read_file(&mut self, name: &str, buffer: &mut [u8]) -> Result<usize, StorageError>;
+
+    /// Size of `name` in bytes.
+    fn file_len(&mut self, name: &str) -> Result<usize, StorageError>;
+
+    /// Creates `name`, replacing any previous contents with `data`.
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), StorageError>;
+}
+
+/// Storage that may be missing. Without a card every file reads as absent, so
+/// settings, saves and cheats fall back to their defaults, and writes fail.
+impl<S: FileStorage> FileStorage for Option<S> {
+    fn read_file(&mut self, name: &str, buffer: &mut [u8]) -> Result<usize, StorageError> {
+        match self {
+            Some(storage) => storage.read_file(name, buffer),
+            None => Err(StorageError::NotFound),
+        }
+    }
+
+    fn file_len(&mut self, name: &str) -> Result<usize, StorageError> {
+        match self {
+            Some(storage) => storage.file_len(name),
+            None => Err(StorageError::NotFound),
+        }
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), StorageError> {
+        match self {
+            Some(storage) => storage.write_file(name, data),
+            None => Err(StorageError::NoCard),
+        }
+    }
+}
+
+/// Replaces the extension of `rom_name` (if any) with `extension`.
+pub fn sibling_file_name(rom_name: &str, extension: &str) -> String {
+    let stem = match rom_name.rsplit_once('.') {
+        Some((stem, _)) => stem,
+        None => rom_name,
+    };
+    alloc::format!("{}.{}", stem, extension)
+}
@@ -0,0 +1,107 @@
+use alloc::{format, vec::Vec};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+
+use super::{draw_row, row_count, MenuInput, BACKGROUND, HIGHLIGHT, TEXT};
+use crate::gameboy::cheats::Cheat;
+
+/// Cursor and scroll position of the in-game cheat list.
+pub struct CheatMenu {
+    selected: usize,
+    first_visible: usize,
+    visible_rows: usize,
+}
+
+impl CheatMenu {
+    pub fn new(visible_rows: usize) -> Self {
+        Self {
+            selected: 0,
+            first_visible: 0,
+            visible_rows: visible_rows.max(1),
+        }
+    }
+
+    /// Moves the cursor or toggles the selected cheat. Returns `false` once
+    /// the menu should close.
+    pub fn handle_input(&mut self, cheats: &mut [Cheat], input: MenuInput) -> bool {
+        if cheats.is_empty() {
+            return false;
+        }
+        let last = cheats.len() - 1;
+        match input {
+            MenuInput::Up if self.selected == 0 => self.selected = last,
+            MenuInput::Up => self.selected -= 1,
+            MenuInput::Down if self.selected == last => self.selected = 0,
+            MenuInput::Down => self.selected += 1,
+            MenuInput::Left => self.selected = self.selected.saturating_sub(self.visible_rows),
+            MenuInput::Right => self.selected = (self.selected + self.visible_rows).min(last),
+            MenuInput::Accept => cheats[self.selected].enabled ^= true,
+            MenuInput::Back | MenuInput::Start => return false,
+        }
+        if self.selected < self.first_visible {
+            self.first_visible = self.selected;
+        } else if self.selected >= self.first_visible + self.visible_rows {
+            self.first_visible = self.selected + 1 - self.visible_rows;
+        }
+        true
+    }
+
+    pub fn draw<DT: DrawTarget<Color = Rgb565>>(
+        &self,
+        display: &mut DT,
+        cheats: &[Cheat],
+    ) -> Result<(), DT::Error> {
+        draw_row(display, 0, "Cheats (A: toggle, B: back)", HIGHLIGHT, TEXT)?;
+        let end = (self.first_visible + self.visible_rows).min(cheats.len());
+        for row in 0..self.visible_rows {
+            let index = self.first_visible + row;
+            if index >= end {
+                draw_row(display, row + 1, "", BACKGROUND, TEXT)?;
+                continue;
+            }
+            let cheat = &cheats[index];
+            let label = format!(
+                "[{}] {}",
+                if cheat.enabled { 'x' } else { ' ' },
+                cheat.description
+            );
+            let (background, text) = if index == self.selected {
+                (TEXT, BACKGROUND)
+            } else {
+                (BACKGROUND, TEXT)
+            };
+            draw_row(display, row + 1, &label, background, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the cheat list until the player backs out. Returns `true` if any
+/// cheat was switched on or off.
+pub fn edit_cheats<DT, I>(
+    display: &mut DT,
+    cheats: &mut [Cheat],
+    mut poll_input: I,
+) -> Result<bool, DT::Error>
+where
+    DT: DrawTarget<Color = Rgb565>,
+    I: FnMut() -> Option<MenuInput>,
+{
+    let before = cheats.iter().map(|cheat| cheat.enabled).collect::<Vec<_>>();
+    let mut menu = CheatMenu::new(row_count(display).saturating_sub(1));
+    display.clear(BACKGROUND)?;
+    menu.draw(display, cheats)?;
+    loop {
+        let input = match poll_input() {
+            Some(input) => input,
+            None => continue,
+        };
+        if !menu.handle_input(cheats, input) {
+            break;
+        }
+        menu.draw(display, cheats)?;
+    }
+    Ok(cheats
+        .iter()
+        .zip(before)
+        .any(|(cheat, enabled)| cheat.enabled != enabled))
+}
@@ -0,0 +1,65 @@
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+
+use super::{column_count, draw_row, row_count, MenuInput, BACKGROUND, HIGHLIGHT, TEXT};
+
+/// Clears the screen and shows `title` followed by `message`, word-wrapped to
+/// the panel width. Lines that do not fit are dropped.
+pub fn show_message<DT: DrawTarget<Color = Rgb565>>(
+    display: &mut DT,
+    title: &str,
+    message: &str,
+) -> Result<(), DT::Error> {
+    display.clear(BACKGROUND)?;
+    draw_row(display, 0, title, HIGHLIGHT, TEXT)?;
+    let columns = column_count(display).max(1);
+    let rows = row_count(display);
+    for (row, line) in (2..rows).zip(wrap(message, columns)) {
+        draw_row(display, row, line, BACKGROUND, TEXT)?;
+    }
+    Ok(())
+}
+
+/// Shows a message and blocks until any button is pressed.
+pub fn show_message_and_wait<DT, I>(
+    display: &mut DT,
+    title: &str,
+    message: &str,
+    mut poll_input: I,
+) -> Result<MenuInput, DT::Error>
+where
+    DT: DrawTarget<Color = Rgb565>,
+    I: FnMut() -> Option<MenuInput>,
+{
+    show_message(display, title, message)?;
+    loop {
+        if let Some(input) = poll_input() {
+            return Ok(input);
+        }
+    }
+}
+
+/// Splits `text` into lines of at most `columns` characters, breaking at
+/// spaces where possible.
+pub fn wrap(text: &str, columns: usize) -> impl Iterator<Item = &str> {
+    let mut rest = text.trim();
+    core::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        if rest.len() <= columns {
+            let line = rest;
+            rest = "";
+            return Some(line);
+        }
+        let mut split = columns;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        let (line, remainder) = match rest[..split].rfind(' ') {
+            Some(space) if space > 0 => (&rest[..space], &rest[space + 1..]),
+            _ => rest.split_at(split),
+        };
+        rest = remainder.trim_start();
+        Some(line.trim_end())
+    })
+}
@@ -0,0 +1,62 @@
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Baseline, Text},
+};
+
+pub mod cheat_menu;
+pub mod message;
+pub mod pause_menu;
+pub mod rom_browser;
+pub mod settings_menu;
+
+pub const ROW_HEIGHT: u32 = 20;
+pub const COLUMN_WIDTH: u32 = 10;
+const TEXT_MARGIN: i32 = 4;
+
+pub const BACKGROUND: Rgb565 = Rgb565::BLACK;
+pub const TEXT: Rgb565 = Rgb565::WHITE;
+/// 0xf9b0, the default colour `main` clears the panel with.
+pub const HIGHLIGHT: Rgb565 = Rgb565::new(0x1f, 0x0d, 0x10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Accept,
+    Back,
+    Start,
+}
+
+/// Number of text rows that fit on the display.
+pub fn row_count<DT: DrawTarget<Color = Rgb565>>(display: &DT) -> usize {
+    (display.bounding_box().size.height / ROW_HEIGHT) as usize
+}
+
+/// Number of characters that fit on a row.
+pub fn column_count<DT: DrawTarget<Color = Rgb565>>(display: &DT) -> usize {
+    ((display.bounding_box().size.width - 2 * TEXT_MARGIN as u32) / COLUMN_WIDTH) as usize
+}
+
+/// Paints a full-width row and writes `label` on it.
+pub fn draw_row<DT: DrawTarget<Color = Rgb565>>(
+    display: &mut DT,
+    row: usize,
+    label: &str,
+    background: Rgb565,
+    text: Rgb565,
+) -> Result<(), DT::Error> {
+    let width = display.bounding_box().size.width;
+    let top = (row as u32 * ROW_HEIGHT) as i32;
+    display.fill_solid(
+        &Rectangle::new(Point::new(0, top), Size::new(width, ROW_HEIGHT)),
+        background,
+    )?;
+    let style = MonoTextStyle::new(&FONT_10X20, text);
+    Text::with_baseline(label, Point::new(TEXT_MARGIN, top), style, Baseline::Top).draw(display)?;
+    Ok(())
+}
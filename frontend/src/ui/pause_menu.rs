@@ -0,0 +1,96 @@
+use alloc::vec::Vec;
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+
+use super::{draw_row, MenuInput, BACKGROUND, HIGHLIGHT, TEXT};
+
+/// Entries of the menu opened with SELECT+START in game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseItem {
+    Resume,
+    Cheats,
+    Settings,
+    PowerOff,
+}
+
+impl PauseItem {
+    fn label(&self) -> &'static str {
+        match self {
+            PauseItem::Resume => "Resume",
+            PauseItem::Cheats => "Cheats",
+            PauseItem::Settings => "Game settings",
+            PauseItem::PowerOff => "Save and switch off",
+        }
+    }
+}
+
+pub struct PauseMenu {
+    items: Vec<PauseItem>,
+    selected: usize,
+}
+
+impl PauseMenu {
+    /// The cheat entry is only offered when the game has a `.cht` file.
+    pub fn new(has_cheats: bool) -> Self {
+        let mut items = Vec::from([PauseItem::Resume]);
+        if has_cheats {
+            items.push(PauseItem::Cheats);
+        }
+        items.push(PauseItem::Settings);
+        items.push(PauseItem::PowerOff);
+        Self { items, selected: 0 }
+    }
+
+    /// Moves the cursor. Returns the chosen entry once the player picks one;
+    /// B and START resume.
+    pub fn handle_input(&mut self, input: MenuInput) -> Option<PauseItem> {
+        let last = self.items.len() - 1;
+        match input {
+            MenuInput::Up if self.selected == 0 => self.selected = last,
+            MenuInput::Up => self.selected -= 1,
+            MenuInput::Down if self.selected == last => self.selected = 0,
+            MenuInput::Down => self.selected += 1,
+            MenuInput::Left | MenuInput::Right => {}
+            MenuInput::Accept => return Some(self.items[self.selected]),
+            MenuInput::Back | MenuInput::Start => return Some(PauseItem::Resume),
+        }
+        None
+    }
+
+    pub fn draw<DT: DrawTarget<Color = Rgb565>>(&self, display: &mut DT) -> Result<(), DT::Error> {
+        draw_row(display, 0, "Paused", HIGHLIGHT, TEXT)?;
+        for (index, item) in self.items.iter().enumerate() {
+            let (background, text) = if index == self.selected {
+                (TEXT, BACKGROUND)
+            } else {
+                (BACKGROUND, TEXT)
+            };
+            draw_row(display, index + 1, item.label(), background, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the pause menu until the player picks an entry.
+pub fn pause<DT, I>(
+    display: &mut DT,
+    has_cheats: bool,
+    mut poll_input: I,
+) -> Result<PauseItem, DT::Error>
+where
+    DT: DrawTarget<Color = Rgb565>,
+    I: FnMut() -> Option<MenuInput>,
+{
+    let mut menu = PauseMenu::new(has_cheats);
+    display.clear(BACKGROUND)?;
+    menu.draw(display)?;
+    loop {
+        let input = match poll_input() {
+            Some(input) => input,
+            None => continue,
+        };
+        match menu.handle_input(input) {
+            Some(item) => return Ok(item),
+            None => menu.draw(display)?,
+        }
+    }
+}
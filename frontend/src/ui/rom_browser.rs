@@ -0,0 +1,270 @@
+use alloc::{string::String, vec::Vec};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+
+use super::{draw_row, row_count, MenuInput, BACKGROUND, HIGHLIGHT, TEXT};
+use crate::{
+    archive::ArchiveKind,
+    gameboy::header::{CartridgeHeader, HEADER_END},
+    storage::StorageError,
+};
+
+/// A listing of files that may contain ROM images.
+pub trait RomDirectory {
+    /// Calls `f` with the name of every regular file in the directory.
+    fn for_each_file(&mut self, f: &mut dyn FnMut(&str)) -> Result<(), StorageError>;
+
+    /// Reads the start of `name` into `buffer`, returning the number of bytes
+    /// read. Compressed ROMs are read from inside the archive.
+    fn read_start(&mut self, name: &str, buffer: &mut [u8]) -> usize;
+}
+
+/// Without a card there is nothing to list.
+impl<R: RomDirectory> RomDirectory for Option<R> {
+    fn for_each_file(&mut self, f: &mut dyn FnMut(&str)) -> Result<(), StorageError> {
+        match self {
+            Some(directory) => directory.for_each_file(f),
+            None => Err(StorageError::NoCard),
+        }
+    }
+
+    fn read_start(&mut self, name: &str, buffer: &mut [u8]) -> usize {
+        match self {
+            Some(directory) => directory.read_start(name, buffer),
+            None => 0,
+        }
+    }
+}
+
+impl<R: RomDirectory> RomDirectory for &mut R {
+    fn for_each_file(&mut self, f: &mut dyn FnMut(&str)) -> Result<(), StorageError> {
+        (**self).for_each_file(f)
+    }
+
+    fn read_start(&mut self, name: &str, buffer: &mut [u8]) -> usize {
+        (**self).read_start(name, buffer)
+    }
+}
+
+/// Lists the ROMs of both directories. A file in the first hides one of the
+/// same name in the second, since that is the copy that gets started. The
+/// first only adds to the list, so errors listing it are ignored; errors
+/// listing the second are reported when the first had nothing either.
+impl<A: RomDirectory, B: RomDirectory> RomDirectory for (A, B) {
+    fn for_each_file(&mut self, f: &mut dyn FnMut(&str)) -> Result<(), StorageError> {
+        let mut first_names = Vec::new();
+        let _ = self.0.for_each_file(&mut |name| {
+            first_names.push(String::from(name));
+            f(name);
+        });
+        let second = self.1.for_each_file(&mut |name| {
+            if !first_names
+                .iter()
+                .any(|first_name| first_name.eq_ignore_ascii_case(name))
+            {
+                f(name);
+            }
+        });
+        match second {
+            Err(_) if !first_names.is_empty() => Ok(()),
+            second => second,
+        }
+    }
+
+    fn read_start(&mut self, name: &str, buffer: &mut [u8]) -> usize {
+        match self.0.read_start(name, buffer) {
+            0 => self.1.read_start(name, buffer),
+            read => read,
+        }
+    }
+}
+
+pub fn is_rom_file(name: &str) -> bool {
+    let extension = match name.rsplit_once('.') {
+        Some((_, extension)) => extension,
+        None => return false,
+    };
+    extension.eq_ignore_ascii_case("gb") || extension.eq_ignore_ascii_case("gbc")
+}
+
+pub struct RomEntry {
+    pub file_name: String,
+    /// Cartridge title from the header, or the file name if the header is unreadable.
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserAction {
+    None,
+    Redraw,
+    Launch(usize),
+    Settings,
+}
+
+/// Why the browser closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrowserExit {
+    Launch(String),
+    Settings,
+}
+
+pub struct RomBrowser {
+    entries: Vec<RomEntry>,
+    selected: usize,
+    first_visible: usize,
+    visible_rows: usize,
+}
+
+impl RomBrowser {
+    pub fn new<R: RomDirectory>(
+        directory: &mut R,
+        visible_rows: usize,
+    ) -> Result<Self, StorageError> {
+        let mut file_names = Vec::new();
+        directory.for_each_file(&mut |name| {
+            if is_rom_file(name) || ArchiveKind::from_name(name).is_some() {
+                file_names.push(String::from(name));
+            }
+        })?;
+        let mut header = [0u8; HEADER_END];
+        let mut entries: Vec<RomEntry> = file_names
+            .into_iter()
+            .map(|file_name| {
+                let read = directory.read_start(&file_name, &mut header);
+                let label = match CartridgeHeader::parse(&header[..read]) {
+                    Ok(header) if !header.title.is_empty() => header.title,
+                    _ => file_name.clone(),
+                };
+                RomEntry { file_name, label }
+            })
+            .collect();
+        entries.sort_unstable_by_key(|entry| entry.label.to_ascii_lowercase());
+        Ok(Self {
+            entries,
+            selected: 0,
+            first_visible: 0,
+            visible_rows: visible_rows.max(1),
+        })
+    }
+
+    pub fn entries(&self) -> &[RomEntry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Indexes of the entries that fit on screen for the current scroll position.
+    pub fn visible_range(&self) -> core::ops::Range<usize> {
+        let end = (self.first_visible + self.visible_rows).min(self.entries.len());
+        self.first_visible..end
+    }
+
+    pub fn handle_input(&mut self, input: MenuInput) -> BrowserAction {
+        if self.entries.is_empty() {
+            return BrowserAction::None;
+        }
+        let last = self.entries.len() - 1;
+        let selected = match input {
+            MenuInput::Up if self.selected == 0 => last,
+            MenuInput::Up => self.selected - 1,
+            MenuInput::Down if self.selected == last => 0,
+            MenuInput::Down => self.selected + 1,
+            MenuInput::Left => self.selected.saturating_sub(self.visible_rows),
+            MenuInput::Right => (self.selected + self.visible_rows).min(last),
+            MenuInput::Accept => return BrowserAction::Launch(self.selected),
+            MenuInput::Start => return BrowserAction::Settings,
+            MenuInput::Back => return BrowserAction::None,
+        };
+        if selected == self.selected {
+            return BrowserAction::None;
+        }
+        self.selected = selected;
+        if self.selected < self.first_visible {
+            self.first_visible = self.selected;
+        } else if self.selected >= self.first_visible + self.visible_rows {
+            self.first_visible = self.selected + 1 - self.visible_rows;
+        }
+        BrowserAction::Redraw
+    }
+}
+
+pub struct RomBrowserView;
+
+impl RomBrowserView {
+    /// Number of list rows that fit below the title bar.
+    pub fn visible_rows<DT: DrawTarget<Color = Rgb565>>(display: &DT) -> usize {
+        row_count(display).saturating_sub(1)
+    }
+
+    pub fn draw<DT: DrawTarget<Color = Rgb565>>(
+        display: &mut DT,
+        browser: &RomBrowser,
+    ) -> Result<(), DT::Error> {
+        draw_row(
+            display,
+            0,
+            "Select a game  START: settings",
+            HIGHLIGHT,
+            TEXT,
+        )?;
+        if browser.entries().is_empty() {
+            draw_row(
+                display,
+                1,
+                "No .gb/.gbc/.zip/.gz files found",
+                BACKGROUND,
+                TEXT,
+            )?;
+            return Ok(());
+        }
+        let range = browser.visible_range();
+        let shown = range.len();
+        for (row, index) in range.enumerate() {
+            let (background, text) = if index == browser.selected() {
+                (TEXT, BACKGROUND)
+            } else {
+                (BACKGROUND, TEXT)
+            };
+            let label = browser.entries()[index].label.as_str();
+            draw_row(display, row + 1, label, background, text)?;
+        }
+        for row in shown..RomBrowserView::visible_rows(display) {
+            draw_row(display, row + 1, "", BACKGROUND, TEXT)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the ROM list until the player launches a game or opens the settings.
+/// Returns `None` when the browser holds no ROM files.
+pub fn select_rom<DT, I>(
+    display: &mut DT,
+    browser: &mut RomBrowser,
+    mut poll_input: I,
+) -> Result<Option<BrowserExit>, DT::Error>
+where
+    DT: DrawTarget<Color = Rgb565>,
+    I: FnMut() -> Option<MenuInput>,
+{
+    display.clear(BACKGROUND)?;
+    RomBrowserView::draw(display, browser)?;
+    if browser.entries().is_empty() {
+        return Ok(None);
+    }
+    loop {
+        let input = match poll_input() {
+            Some(input) => input,
+            None => continue,
+        };
+        match browser.handle_input(input) {
+            BrowserAction::None => {}
+            BrowserAction::Redraw => RomBrowserView::draw(display, browser)?,
+            BrowserAction::Launch(index) => {
+                let file_name = browser.entries()[index].file_name.clone();
+                return Ok(Some(BrowserExit::Launch(file_name)));
+            }
+            BrowserAction::Settings => return Ok(Some(BrowserExit::Settings)),
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use alloc::{format, string::String, vec::Vec};
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*};
+
+use super::{draw_row, row_count, MenuInput, BACKGROUND, HIGHLIGHT, TEXT};
+use crate::{
+    config::{
+        policy_name, ButtonMap, Config, Filter, GameKey, Orientation, Palette, Scaling,
+        MAX_CACHE_BANKS, MAX_FRAME_SKIP,
+    },
+    gameboy::bank_cache::CachePolicy,
+};
+
+const CLEAR_COLORS: [u16; 5] = [0xf9b0, 0x0000, 0xffff, 0x423f, 0x8410];
+const DISPLAY_SPI_MHZ: [u32; 4] = [10, 20, 40, 80];
+const SD_SPI_KHZ: [u32; 4] = [4_000, 10_000, 20_000, 40_000];
+const CACHE_POLICIES: [CachePolicy; 2] = [CachePolicy::Lru, CachePolicy::Lfu];
+/// Width of the label column, in characters.
+const LABEL_WIDTH: usize = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Orientation,
+    Scaling,
+    Filter,
+    Palette,
+    FrameSkip,
+    ClearColor,
+    DisplaySpi,
+    SdSpi,
+    DefaultRom,
+    SkipBootRom,
+    Button(Button),
+    CacheBanks,
+    CachePolicy,
+}
+
+/// A face button, for the key it presses in game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Button {
+    A,
+    B,
+    Start,
+    Select,
+}
+
+impl Button {
+    fn key(self, buttons: &ButtonMap) -> GameKey {
+        match self {
+            Button::A => buttons.a,
+            Button::B => buttons.b,
+            Button::Start => buttons.start,
+            Button::Select => buttons.select,
+        }
+    }
+
+    fn key_mut(self, buttons: &mut ButtonMap) -> &mut GameKey {
+        match self {
+            Button::A => &mut buttons.a,
+            Button::B => &mut buttons.b,
+            Button::Start => &mut buttons.start,
+            Button::Select => &mut buttons.select,
+        }
+    }
+}
+
+const GLOBAL_ITEMS: [Item; 16] = [
+    Item::Orientation,
+    Item::Scaling,
+    Item::Filter,
+    Item::Palette,
+    Item::FrameSkip,
+    Item::ClearColor,
+    Item::DisplaySpi,
+    Item::SdSpi,
+    Item::DefaultRom,
+    Item::SkipBootRom,
+    Item::Button(Button::A),
+    Item::Button(Button::B),
+    Item::Button(Button::Start),
+    Item::Button(Button::Select),
+    Item::CacheBanks,
+    Item::CachePolicy,
+];
+/// The settings a game's own file can change, see `config::GAME_KEYS`.
+/// Cheats are switched on and off in the cheat menu.
+const GAME_ITEMS: [Item; 10] = [
+    Item::Scaling,
+    Item::Filter,
+    Item::Palette,
+    Item::FrameSkip,
+    Item::ClearColor,
+    Item::SkipBootRom,
+    Item::Button(Button::A),
+    Item::Button(Button::B),
+    Item::Button(Button::Start),
+    Item::Button(Button::Select),
+];
+
+/// Where edited settings are saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsScope {
+    /// `config.ini`, for every game.
+    Global,
+    /// The running game's file in `config::GAME_SETTINGS_DIR`.
+    Game,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsAction {
+    None,
+    Redraw,
+    Save(SettingsScope),
+    Cancel,
+}
+
+/// The option `step` places away from `current`, wrapping around. A value
+/// that is not among `options` moves to the first or last one.
+fn cycle<T: Clone + PartialEq>(options: &[T], current: &T, step: isize) -> T {
+    let len = options.len() as isize;
+    let index = match options.iter().position(|option| option == current) {
+        Some(index) => (index as isize + step).rem_euclid(len),
+        None if step > 0 => 0,
+        None => len - 1,
+    };
+    options[index as usize].clone()
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Edits a copy of the settings; nothing changes until the player saves.
+pub struct SettingsMenu {
+    config: Config,
+    /// Choices for the default ROM, after "none".
+    rom_names: Vec<String>,
+    scope: SettingsScope,
+    selected: usize,
+}
+
+impl SettingsMenu {
+    /// From the browser, every setting is shown and saved to `config.ini`. In
+    /// game only the per-game ones are, and A saves them for this game only.
+    pub fn new(config: Config, rom_names: Vec<String>, scope: SettingsScope) -> Self {
+        Self {
+            config,
+            rom_names,
+            scope,
+            selected: 0,
+        }
+    }
+
+    fn items(&self) -> &'static [Item] {
+        match self.scope {
+            SettingsScope::Global => &GLOBAL_ITEMS,
+            SettingsScope::Game => &GAME_ITEMS,
+        }
+    }
+
+    pub fn handle_input(&mut self, input: MenuInput) -> SettingsAction {
+        let last = self.items().len() - 1;
+        match input {
+            MenuInput::Up if self.selected == 0 => self.selected = last,
+            MenuInput::Up => self.selected -= 1,
+            MenuInput::Down if self.selected == last => self.selected = 0,
+            MenuInput::Down => self.selected += 1,
+            MenuInput::Left => self.change(-1),
+            MenuInput::Right => self.change(1),
+            MenuInput::Accept => return SettingsAction::Save(self.scope),
+            MenuInput::Start => return SettingsAction::Save(SettingsScope::Global),
+            MenuInput::Back => return SettingsAction::Cancel,
+        }
+        SettingsAction::Redraw
+    }
+
+    fn change(&mut self, step: isize) {
+        let item = self.items()[self.selected];
+        let config = &mut self.config;
+        match item {
+            Item::Orientation => {
+                config.orientation = cycle(&Orientation::ALL, &config.orientation, step)
+            }
+            Item::Scaling => config.scaling = cycle(&Scaling::ALL, &config.scaling, step),
+            Item::Filter => config.filter = cycle(&Filter::ALL, &config.filter, step),
+            Item::Palette => config.palette = cycle(&Palette::ALL, &config.palette, step),
+            Item::FrameSkip => {
+                let frames = config.frame_skip as isize + step;
+                config.frame_skip = frames.rem_euclid(MAX_FRAME_SKIP as isize + 1) as u8;
+            }
+            Item::ClearColor => {
+                config.clear_color = cycle(&CLEAR_COLORS, &config.clear_color, step)
+            }
+            Item::DisplaySpi => {
+                config.display_spi_mhz = cycle(&DISPLAY_SPI_MHZ, &config.display_spi_mhz, step)
+            }
+            Item::SdSpi => config.sd_spi_khz = cycle(&SD_SPI_KHZ, &config.sd_spi_khz, step),
+            Item::DefaultRom => {
+                let options = core::iter::once(None)
+                    .chain(self.rom_names.iter().cloned().map(Some))
+                    .collect::<Vec<_>>();
+                config.default_rom = cycle(&options, &config.default_rom, step);
+            }
+            Item::SkipBootRom => config.skip_boot_rom ^= true,
+            Item::Button(button) => {
+                let key = button.key_mut(&mut config.buttons);
+                *key = cycle(&GameKey::ALL, key, step);
+            }
+            Item::CacheBanks => {
+                let banks = config.cache.capacity as isize + step;
+                config.cache.capacity = banks.clamp(1, MAX_CACHE_BANKS as isize) as usize;
+            }
+            Item::CachePolicy => {
+                config.cache.policy = cycle(&CACHE_POLICIES, &config.cache.policy, step)
+            }
+        }
+    }
+
+    fn label(item: Item) -> &'static str {
+        match item {
+            Item::Orientation => "Orientation",
+            Item::Scaling => "Scaling",
+            Item::Filter => "Filter",
+            Item::Palette => "Palette",
+            Item::FrameSkip => "Frame skip",
+            Item::ClearColor => "Border colour",
+            Item::DisplaySpi => "LCD clock",
+            Item::SdSpi => "SD clock",
+            Item::DefaultRom => "Start game",
+            Item::SkipBootRom => "Skip boot ROM",
+            Item::Button(Button::A) => "A button",
+            Item::Button(Button::B) => "B button",
+            Item::Button(Button::Start) => "START button",
+            Item::Button(Button::Select) => "SELECT button",
+            Item::CacheBanks => "Cached banks",
+            Item::CachePolicy => "Cache policy",
+        }
+    }
+
+    fn value(&self, item: Item) -> String {
+        let config = &self.config;
+        match item {
+            Item::Orientation => String::from(config.orientation.name()),
+            Item::Scaling => String::from(config.scaling.name()),
+            Item::Filter => String::from(config.filter.name()),
+            Item::Palette => String::from(config.palette.name()),
+            Item::FrameSkip => format!("{}", config.frame_skip),
+            Item::ClearColor => format!("0x{:04x}", config.clear_color),
+            Item::DisplaySpi => format!("{} MHz", config.display_spi_mhz),
+            Item::SdSpi => format!("{} kHz", config.sd_spi_khz),
+            Item::DefaultRom => match &config.default_rom {
+                Some(name) => name.clone(),
+                None => String::from("(browser)"),
+            },
+            Item::SkipBootRom => String::from(yes_no(config.skip_boot_rom)),
+            Item::Button(button) => String::from(button.key(&config.buttons).name()),
+            Item::CacheBanks => format!("{}", config.cache.capacity),
+            Item::CachePolicy => String::from(policy_name(&config.cache.policy)),
+        }
+    }
+
+    pub fn draw<DT: DrawTarget<Color = Rgb565>>(&self, display: &mut DT) -> Result<(), DT::Error> {
+        let title = match self.scope {
+            SettingsScope::Global => "Settings (A: save, B: back)",
+            SettingsScope::Game => "A: this game  START: all games",
+        };
+        draw_row(display, 0, title, HIGHLIGHT, TEXT)?;
+        let rows = row_count(display).saturating_sub(1);
+        // Scrolls once the selection moves past the last row.
+        let first = (self.selected + 1).saturating_sub(rows);
+        let items = self.items().iter().enumerate().skip(first).take(rows);
+        for (row, (index, item)) in items.enumerate() {
+            let label = format!(
+                "{:width$}{}",
+                SettingsMenu::label(*item),
+                self.value(*item),
+                width = LABEL_WIDTH
+            );
+            let (background, text) = if index == self.selected {
+                (TEXT, BACKGROUND)
+            } else {
+                (BACKGROUND, TEXT)
+            };
+            draw_row(display, row + 1, &label, background, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the settings until the player saves or backs out. Returns the new
+/// settings and where to save them, if they were saved.
+pub fn edit_settings<DT, I>(
+    display: &mut DT,
+    config: &Config,
+    rom_names: Vec<String>,
+    scope: SettingsScope,
+    mut poll_input: I,
+) -> Result<Option<(SettingsScope, Config)>, DT::Error>
+where
+    DT: DrawTarget<Color = Rgb565>,
+    I: FnMut() -> Option<MenuInput>,
+{
+    let mut menu = SettingsMenu::new(config.clone(), rom_names, scope);
+    display.clear(BACKGROUND)?;
+    menu.draw(display)?;
+    loop {
+        let input = match poll_input() {
+            Some(input) => input,
+            None => continue,
+        };
+        match menu.handle_input(input) {
+            SettingsAction::None => {}
+            SettingsAction::Redraw => menu.draw(display)?,
+            SettingsAction::Save(scope) => return Ok(Some((scope, menu.config.clone()))),
+            SettingsAction::Cancel => return Ok(None),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use gb_frontend::{
+    archive::{
+        extract, find_rom, read_start, ArchiveEntry, ArchiveError, ArchiveKind, Compression,
+    },
+    checksum::crc32,
+    storage::StorageError,
+};
+
+/// Written by `fixtures/archives.py`, which packs the ROM `rom` returns.
+const STORED_ZIP: &[u8] = include_bytes!("fixtures/stored.zip");
+const DEFLATE_ZIP: &[u8] = include_bytes!("fixtures/deflate.zip");
+const COMMENT_ZIP: &[u8] = include_bytes!("fixtures/comment.zip");
+const NO_ROM_ZIP: &[u8] = include_bytes!("fixtures/norom.zip");
+const CRC_ZIP: &[u8] = include_bytes!("fixtures/crc.zip");
+const PLAIN_GZ: &[u8] = include_bytes!("fixtures/plain.gz");
+const NAMED_GZ: &[u8] = include_bytes!("fixtures/named.gz");
+
+fn rom() -> Vec<u8> {
+    (0..0x2000).map(|i| ((i * 7 + 3) % 251) as u8).collect()
+}
+
+/// Reads from `data` like the card would, failing past its end.
+fn reader(data: &[u8]) -> impl FnMut(usize, &mut [u8]) -> Result<(), StorageError> + '_ {
+    move |offset, buffer| {
+        let source = data
+            .get(offset..offset + buffer.len())
+            .ok_or(StorageError::Device("read past the end".to_string()))?;
+        buffer.copy_from_slice(source);
+        Ok(())
+    }
+}
+
+fn find(kind: ArchiveKind, data: &[u8]) -> Result<ArchiveEntry, ArchiveError> {
+    find_rom(kind, data.len(), reader(data))
+}
+
+fn unpack(kind: ArchiveKind, data: &[u8]) -> Result<(ArchiveEntry, Vec<u8>), ArchiveError> {
+    let entry = find(kind, data)?;
+    let mut output = Vec::new();
+    extract(&entry, reader(data), |chunk| {
+        output.extend_from_slice(chunk);
+        Ok(true)
+    })?;
+    Ok((entry, output))
+}
+
+/// `data` with the little-endian `value` written at `offset`.
+fn patched(data: &[u8], offset: usize, value: u32) -> Vec<u8> {
+    let mut data = data.to_vec();
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    data
+}
+
+fn central_directory(data: &[u8]) -> usize {
+    data.windows(4)
+        .position(|window| window == [0x50, 0x4B, 0x01, 0x02])
+        .unwrap()
+}
+
+#[test]
+fn kind_follows_the_extension() {
+    assert_eq!(ArchiveKind::from_name("TETRIS.GZ"), Some(ArchiveKind::Gzip));
+    assert_eq!(ArchiveKind::from_name("zelda.zip"), Some(ArchiveKind::Zip));
+    assert_eq!(ArchiveKind::from_name("TETRIS.GB"), None);
+    assert_eq!(ArchiveKind::from_name("ZIP"), None);
+}
+
+#[test]
+fn stored_zip_skips_files_that_are_not_roms() {
+    let (entry, output) = unpack(ArchiveKind::Zip, STORED_ZIP).unwrap();
+    assert_eq!(entry.name, "GAME.GB");
+    assert_eq!(entry.compression, Compression::Stored);
+    assert_eq!(entry.size, 0x2000);
+    assert_eq!(output, rom());
+}
+
+#[test]
+fn deflated_zip() {
+    let (entry, output) = unpack(ArchiveKind::Zip, DEFLATE_ZIP).unwrap();
+    assert_eq!(entry.name, "Game.GBC");
+    assert_eq!(entry.compression, Compression::Deflate);
+    assert_eq!(output, rom());
+}
+
+#[test]
+fn zip_with_a_comment() {
+    let (entry, output) = unpack(ArchiveKind::Zip, COMMENT_ZIP).unwrap();
+    assert_eq!(entry.name, "GAME.GB");
+    assert_eq!(output, rom());
+}
+
+#[test]
+fn zip_without_a_rom() {
+    assert_eq!(find(ArchiveKind::Zip, NO_ROM_ZIP), Err(ArchiveError::NoRom));
+}
+
+#[test]
+fn gzip_without_a_name() {
+    let (entry, output) = unpack(ArchiveKind::Gzip, PLAIN_GZ).unwrap();
+    assert_eq!(entry.name, "");
+    assert_eq!(entry.size, 0x2000);
+    assert_eq!(output, rom());
+}
+
+#[test]
+fn gzip_with_a_name_and_extra_field() {
+    let (entry, output) = unpack(ArchiveKind::Gzip, NAMED_GZ).unwrap();
+    assert_eq!(entry.name, "tetris.gb");
+    assert_eq!(output, rom());
+}
+
+#[test]
+fn read_start_stops_after_the_header() {
+    for (kind, data) in [
+        (ArchiveKind::Zip, STORED_ZIP),
+        (ArchiveKind::Zip, DEFLATE_ZIP),
+        (ArchiveKind::Gzip, NAMED_GZ),
+    ] {
+        let entry = find(kind, data).unwrap();
+        let mut header = [0; 0x150];
+        assert_eq!(read_start(&entry, reader(data), &mut header), Ok(0x150));
+        assert_eq!(header[..], rom()[..0x150]);
+    }
+}
+
+#[test]
+fn crc_mismatch_is_reported() {
+    // archives.py flipped the low byte of the CRC in the central directory.
+    let actual = crc32(&rom());
+    assert_eq!(
+        unpack(ArchiveKind::Zip, CRC_ZIP).map(|_| ()),
+        Err(ArchiveError::Checksum {
+            expected: actual ^ 0xFF,
+            actual,
+        })
+    );
+    let mut gzip = PLAIN_GZ.to_vec();
+    let trailer = gzip.len() - 8;
+    gzip[trailer] ^= 0xFF;
+    assert!(matches!(
+        unpack(ArchiveKind::Gzip, &gzip),
+        Err(ArchiveError::Checksum { .. })
+    ));
+}
+
+#[test]
+fn truncated_archives_are_rejected() {
+    for length in [0, 10, 21, DEFLATE_ZIP.len() - 1] {
+        assert_eq!(
+            find(ArchiveKind::Zip, &DEFLATE_ZIP[..length]),
+            Err(ArchiveError::Malformed),
+            "{} bytes",
+            length
+        );
+    }
+    assert_eq!(
+        find(ArchiveKind::Gzip, &PLAIN_GZ[..17]),
+        Err(ArchiveError::Malformed)
+    );
+    // The end is missing, so the trailer is read from the middle of the data.
+    let cut = &PLAIN_GZ[..PLAIN_GZ.len() / 2];
+    assert_eq!(
+        unpack(ArchiveKind::Gzip, cut).map(|_| ()),
+        Err(ArchiveError::Corrupt)
+    );
+}
+
+#[test]
+fn zip_offsets_past_the_end_are_rejected() {
+    let central = central_directory(DEFLATE_ZIP);
+    // The local header offset.
+    for offset in [DEFLATE_ZIP.len() as u32, u32::MAX - 1] {
+        assert_eq!(
+            find(
+                ArchiveKind::Zip,
+                &patched(DEFLATE_ZIP, central + 42, offset)
+            ),
+            Err(ArchiveError::Malformed)
+        );
+    }
+    // The compressed size.
+    assert_eq!(
+        find(
+            ArchiveKind::Zip,
+            &patched(DEFLATE_ZIP, central + 20, u32::MAX - 1)
+        ),
+        Err(ArchiveError::Malformed)
+    );
+    // All ones means zip64.
+    assert_eq!(
+        find(
+            ArchiveKind::Zip,
+            &patched(DEFLATE_ZIP, central + 42, u32::MAX)
+        ),
+        Err(ArchiveError::Unsupported)
+    );
+}
@@ -0,0 +1,103 @@
+use gb_frontend::gameboy::bank_cache::{BankCache, BankCacheConfig, CachePolicy};
+
+fn cache(capacity: usize, policy: CachePolicy, pinned: Vec<usize>) -> BankCache {
+    BankCache::new(&BankCacheConfig {
+        capacity,
+        policy,
+        pinned,
+    })
+}
+
+/// Loads `bank` filled with its own number and returns the evicted bank.
+fn load(cache: &mut BankCache, bank: usize) -> Option<usize> {
+    let (buffer, evicted) = cache
+        .load(bank, |buffer| {
+            buffer.fill(bank as u8);
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    assert_eq!(buffer[0], bank as u8);
+    evicted
+}
+
+#[test]
+fn lru_keeps_pinned_banks() {
+    let mut cache = cache(2, CachePolicy::Lru, vec![1]);
+    assert!(cache.get(1).is_none());
+    assert_eq!(load(&mut cache, 1), None);
+    assert_eq!(load(&mut cache, 2), None);
+    assert_eq!(load(&mut cache, 3), Some(2));
+    assert_eq!(load(&mut cache, 4), Some(3));
+    assert_eq!(cache.get(1).unwrap()[0], 1);
+    assert_eq!(cache.current_bank(), Some(1));
+}
+
+#[test]
+fn lru_evicts_the_bank_switched_to_least_recently() {
+    let mut cache = cache(2, CachePolicy::Lru, Vec::new());
+    load(&mut cache, 1);
+    load(&mut cache, 2);
+    cache.get(1);
+    assert_eq!(load(&mut cache, 3), Some(2));
+}
+
+#[test]
+fn lfu_keeps_the_bank_switched_to_most_often() {
+    let mut cache = cache(2, CachePolicy::Lfu, Vec::new());
+    load(&mut cache, 1);
+    load(&mut cache, 2);
+    cache.get(1);
+    // Every bank loaded in between is used once, bank 1 after each of them.
+    for bank in 3..10 {
+        assert_eq!(load(&mut cache, bank), Some(bank - 1));
+        assert_eq!(cache.get(1).unwrap()[0], 1);
+    }
+}
+
+#[test]
+fn lfu_new_banks_are_not_evicted_for_being_new() {
+    let mut cache = cache(3, CachePolicy::Lfu, Vec::new());
+    for bank in 1..=3 {
+        load(&mut cache, bank);
+    }
+    for _ in 0..10 {
+        for bank in 1..=3 {
+            cache.get(bank);
+        }
+    }
+    // The game moves on to two new banks. Counting from one, the first of
+    // them would be evicted by the second and the two would keep replacing
+    // each other while the old banks stay.
+    assert_eq!(load(&mut cache, 4), Some(1));
+    assert_eq!(load(&mut cache, 5), Some(2));
+    assert_eq!(cache.get(4).unwrap()[0], 4);
+    assert_eq!(cache.get(5).unwrap()[0], 5);
+    assert_eq!(load(&mut cache, 6), Some(3));
+}
+
+#[test]
+fn reading_one_bank_counts_as_one_access() {
+    let mut cache = cache(2, CachePolicy::Lfu, Vec::new());
+    load(&mut cache, 1);
+    load(&mut cache, 2);
+    cache.get(1);
+    cache.get(2);
+    // Byte reads from bank 1 must not outweigh the switches to bank 2.
+    for _ in 0..1_000_000 {
+        cache.get(1);
+    }
+    cache.get(2);
+    assert_eq!(load(&mut cache, 3), Some(1));
+}
+
+#[test]
+fn failed_load_is_retried() {
+    let mut cache = cache(1, CachePolicy::Lru, Vec::new());
+    load(&mut cache, 1);
+    assert!(cache.load(2, |_| Err(())).is_err());
+    assert!(cache.get(1).is_none());
+    assert!(cache.get(2).is_none());
+    assert_eq!(cache.current_bank(), None);
+    assert_eq!(load(&mut cache, 2), None);
+    assert_eq!(cache.current_bank(), Some(2));
+}
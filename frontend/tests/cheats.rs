@@ -0,0 +1,230 @@
+mod common;
+
+use common::MemoryStorage;
+use gb_frontend::gameboy::cheats::{
+    enable_only, enabled_numbers, load_cheats, parse_cht, parse_code, parse_game_genie,
+    parse_game_shark, ram_writes, Cheat, CheatCode, CheatError, GameGenieCode, GameSharkCode,
+    RomPatches, MAX_CHEATS,
+};
+
+const CHT: &str = r#"cheats = 4
+
+cheat0_desc = "Infinite lives"
+cheat0_code = "00A-17B-C49+010FC1DA"
+cheat0_enable = true
+
+cheat1_desc = "Broken"
+cheat1_code = "123"
+cheat1_enable = true
+
+cheat2_desc = "Start with 9 coins"
+cheat2_code = "3EB-DCF"
+cheat2_enable = false
+
+cheat3_desc = "Empty"
+cheat3_code = ""
+"#;
+
+#[test]
+fn game_genie_six_digits() {
+    // Value 3E; the address is the last digit inverted, then B, D and C.
+    assert_eq!(
+        parse_game_genie("3EB-DCF"),
+        Ok(GameGenieCode {
+            address: 0x0BDC,
+            value: 0x3E,
+            compare: None,
+        })
+    );
+    // The dashes are optional.
+    assert_eq!(parse_game_genie("3EBDCF"), parse_game_genie("3EB-DCF"));
+}
+
+#[test]
+fn game_genie_nine_digits_has_a_compare_byte() {
+    // C and 9 give C9, rotated right by two 72, XORed with BA C8.
+    assert_eq!(
+        parse_game_genie("00A-17B-C49"),
+        Ok(GameGenieCode {
+            address: 0x4A17,
+            value: 0x00,
+            compare: Some(0xC8),
+        })
+    );
+    // The middle digit of the last group is ignored.
+    assert_eq!(
+        parse_game_genie("00A-17B-CF9"),
+        parse_game_genie("00A-17B-C49")
+    );
+    assert_eq!(
+        parse_game_genie("00a-17b-c49"),
+        parse_game_genie("00A-17B-C49")
+    );
+}
+
+#[test]
+fn game_genie_only_patches_rom() {
+    assert_eq!(
+        parse_game_genie("00A-170-C49"),
+        Err(CheatError::NotRomAddress(0xFA17))
+    );
+    assert_eq!(
+        parse_game_genie("00A-177"),
+        Err(CheatError::NotRomAddress(0x8A17))
+    );
+}
+
+#[test]
+fn game_shark_is_bank_value_and_little_endian_address() {
+    assert_eq!(
+        parse_game_shark("010FC1DA"),
+        Ok(GameSharkCode {
+            bank: 0x01,
+            value: 0x0F,
+            address: 0xDAC1,
+        })
+    );
+    assert_eq!(
+        parse_game_shark(" 9063A2A0 "),
+        Ok(GameSharkCode {
+            bank: 0x90,
+            value: 0x63,
+            address: 0xA0A2,
+        })
+    );
+    assert_eq!(
+        parse_game_shark("010FC1D"),
+        Err(CheatError::InvalidLength(7))
+    );
+}
+
+#[test]
+fn parse_code_tells_the_formats_apart() {
+    assert!(matches!(
+        parse_code("010FC1DA"),
+        Ok(CheatCode::GameShark(_))
+    ));
+    assert!(matches!(parse_code("3EB-DCF"), Ok(CheatCode::GameGenie(_))));
+    assert!(matches!(
+        parse_code(" 00A-17B-C49 "),
+        Ok(CheatCode::GameGenie(_))
+    ));
+}
+
+#[test]
+fn parse_code_rejects_bad_codes() {
+    assert_eq!(parse_code(""), Err(CheatError::InvalidLength(0)));
+    assert_eq!(parse_code("123"), Err(CheatError::InvalidLength(3)));
+    assert_eq!(
+        parse_code("00A-17B-C49-0"),
+        Err(CheatError::InvalidLength(10))
+    );
+    assert_eq!(parse_code("01G0C1DA"), Err(CheatError::InvalidDigit('G')));
+    assert_eq!(parse_code("00A 17B"), Err(CheatError::InvalidDigit(' ')));
+    assert_eq!(
+        parse_code("00A-170-C49"),
+        Err(CheatError::NotRomAddress(0xFA17))
+    );
+}
+
+#[test]
+fn cht_keeps_cheats_with_valid_codes() {
+    let (cheats, errors) = parse_cht(CHT);
+    assert_eq!(
+        cheats,
+        [
+            Cheat {
+                number: 0,
+                description: "Infinite lives".to_string(),
+                codes: vec![
+                    parse_code("00A-17B-C49").unwrap(),
+                    parse_code("010FC1DA").unwrap(),
+                ],
+                enabled: true,
+            },
+            Cheat {
+                number: 2,
+                description: "Start with 9 coins".to_string(),
+                codes: vec![parse_code("3EB-DCF").unwrap()],
+                enabled: false,
+            },
+        ]
+    );
+    assert_eq!(errors, [(1, CheatError::InvalidLength(3))]);
+}
+
+#[test]
+fn cht_drops_a_cheat_with_any_bad_code() {
+    let text = "cheat0_code = \"3EB-DCF+XYZ-DCF\"\ncheat0_enable = TRUE\ncheat1_code = 3EB-DCF\n";
+    let (cheats, errors) = parse_cht(text);
+    assert_eq!(cheats.len(), 1);
+    assert!(!cheats[0].enabled);
+    assert_eq!(errors, [(0, CheatError::InvalidDigit('X'))]);
+    assert_eq!(parse_cht(""), (Vec::new(), Vec::new()));
+}
+
+#[test]
+fn cht_skips_cheats_past_the_declared_count() {
+    let text = "cheats = 1\ncheat0_code = 3EB-DCF\ncheat1_code = 3EB-DCF\n";
+    let (cheats, errors) = parse_cht(text);
+    assert_eq!(cheats.len(), 1);
+    assert_eq!(cheats[0].number, 0);
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn cht_skips_huge_cheat_numbers() {
+    // Without a count only the fixed limit applies; either way a huge
+    // number must not allocate a cheat for every number below it.
+    for count in ["", "cheats = 4000000000\n"] {
+        let mut text = count.to_string();
+        text += "cheat4000000000_desc = \"Huge\"\ncheat4000000000_code = 3EB-DCF\n";
+        for index in [MAX_CHEATS - 1, MAX_CHEATS] {
+            text += &format!("cheat{}_code = 3EB-DCF\n", index);
+        }
+        let (cheats, errors) = parse_cht(&text);
+        assert_eq!(cheats.len(), 1, "{:?}", count);
+        assert_eq!(cheats[0].number, MAX_CHEATS - 1);
+        assert!(errors.is_empty());
+    }
+}
+
+#[test]
+fn enabled_codes_patch_rom_and_ram() {
+    let (mut cheats, _) = parse_cht(CHT);
+    let patches = RomPatches::default();
+    patches.update(&cheats);
+    // Only while the ROM holds the compare byte.
+    assert_eq!(patches.apply(0x4A17, 0xC8), 0x00);
+    assert_eq!(patches.apply(0x4A17, 0xC7), 0xC7);
+    // Disabled.
+    assert_eq!(patches.apply(0x0BDC, 0x05), 0x05);
+    assert_eq!(
+        ram_writes(&cheats).collect::<Vec<_>>(),
+        [parse_game_shark("010FC1DA").unwrap()]
+    );
+
+    cheats[0].enabled = false;
+    cheats[1].enabled = true;
+    patches.update(&cheats);
+    assert_eq!(patches.apply(0x4A17, 0xC8), 0xC8);
+    assert_eq!(patches.apply(0x0BDC, 0x05), 0x3E);
+    assert_eq!(ram_writes(&cheats).count(), 0);
+}
+
+#[test]
+fn cheats_are_loaded_next_to_the_rom() {
+    let mut storage = MemoryStorage::default().with_file("TETRIS.CHT", CHT.as_bytes());
+    assert_eq!(load_cheats(&mut storage, "TETRIS.GB").unwrap().len(), 2);
+    assert!(load_cheats(&mut storage, "ZELDA.GB").unwrap().is_empty());
+}
+
+#[test]
+fn enabled_cheats_are_chosen_by_number() {
+    let (mut cheats, _) = parse_cht(CHT);
+    assert_eq!(enabled_numbers(&cheats), [0]);
+    enable_only(&mut cheats, &[2, 7]);
+    assert_eq!(enabled_numbers(&cheats), [2]);
+    enable_only(&mut cheats, &[]);
+    assert!(enabled_numbers(&cheats).is_empty());
+}
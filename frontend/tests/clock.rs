@@ -0,0 +1,143 @@
+use gb_frontend::clock::DateTime;
+
+fn date_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+    DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+    }
+}
+
+fn leap(year: u16) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Days from 1970 to the start of `year`, summed year by year.
+fn days_before(year: u16) -> u64 {
+    (1970..year)
+        .map(|year| if leap(year) { 366 } else { 365 })
+        .sum()
+}
+
+#[test]
+fn known_instants_convert_both_ways() {
+    let cases = [
+        (0, date_time(1970, 1, 1, 0, 0, 0)),
+        (946_684_799, date_time(1999, 12, 31, 23, 59, 59)),
+        (951_782_400, date_time(2000, 2, 29, 0, 0, 0)),
+        (951_868_800, date_time(2000, 3, 1, 0, 0, 0)),
+        (1_709_210_096, date_time(2024, 2, 29, 12, 34, 56)),
+        (2_147_483_647, date_time(2038, 1, 19, 3, 14, 7)),
+        (4_107_542_399, date_time(2100, 2, 28, 23, 59, 59)),
+        (4_107_542_400, date_time(2100, 3, 1, 0, 0, 0)),
+        (13_574_563_200, date_time(2400, 2, 29, 0, 0, 0)),
+    ];
+    for (seconds, expected) in cases {
+        assert_eq!(
+            DateTime::from_unix_seconds(seconds),
+            expected,
+            "{}",
+            seconds
+        );
+        assert_eq!(expected.to_unix_seconds(), seconds, "{}", expected);
+    }
+}
+
+#[test]
+fn every_day_follows_the_one_before() {
+    // Counted a day at a time up to 2401, through the century years that
+    // are and are not leap years.
+    let mut expected = date_time(1970, 1, 1, 12, 0, 0);
+    for days in 0..days_before(2401) {
+        let seconds = days * 86_400 + 12 * 3600;
+        assert_eq!(DateTime::from_unix_seconds(seconds), expected);
+        assert_eq!(expected.to_unix_seconds(), seconds);
+
+        let month_length = match expected.month {
+            2 if leap(expected.year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        expected.day += 1;
+        if expected.day > month_length {
+            expected.day = 1;
+            expected.month += 1;
+            if expected.month > 12 {
+                expected.month = 1;
+                expected.year += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn time_of_day_wraps_at_midnight() {
+    assert_eq!(
+        DateTime::from_unix_seconds(86_399),
+        date_time(1970, 1, 1, 23, 59, 59)
+    );
+    assert_eq!(
+        DateTime::from_unix_seconds(86_400),
+        date_time(1970, 1, 2, 0, 0, 0)
+    );
+}
+
+#[test]
+fn parses_both_separators() {
+    let expected = date_time(2024, 2, 29, 12, 34, 56);
+    for text in [
+        "2024-02-29 12:34:56",
+        "2024-02-29T12:34:56",
+        "  2024-2-29 12:34:56\r\n",
+    ] {
+        assert_eq!(DateTime::parse(text), Some(expected), "{:?}", text);
+    }
+    assert_eq!(expected.to_string(), "2024-02-29 12:34:56");
+    assert_eq!(
+        DateTime::parse("1970-01-01 00:00:00"),
+        Some(date_time(1970, 1, 1, 0, 0, 0))
+    );
+}
+
+#[test]
+fn rejects_dates_that_do_not_exist() {
+    for text in [
+        "1969-12-31 23:59:59",
+        "2023-02-29 00:00:00",
+        "2100-02-29 00:00:00",
+        "2024-04-31 00:00:00",
+        "2024-13-01 00:00:00",
+        "2024-00-10 00:00:00",
+        "2024-01-00 00:00:00",
+        "2024-01-01 24:00:00",
+        "2024-01-01 00:60:00",
+        "2024-01-01 00:00:60",
+    ] {
+        assert_eq!(DateTime::parse(text), None, "{:?}", text);
+    }
+    for text in ["2000-02-29 00:00:00", "2024-12-31 23:59:59"] {
+        assert!(DateTime::parse(text).is_some(), "{:?}", text);
+    }
+}
+
+#[test]
+fn rejects_malformed_text() {
+    for text in [
+        "",
+        "2024-01-01",
+        "2024-01-01 12:00",
+        "2024-01-01 12:00:00:00",
+        "2024-01-01-01 12:00:00",
+        "2024/01/01 12:00:00",
+        "2024-01-01 12:00:0x",
+        "-2024-01-01 12:00:00",
+        "99999-01-01 12:00:00",
+        "2024-300-01 12:00:00",
+    ] {
+        assert_eq!(DateTime::parse(text), None, "{:?}", text);
+    }
+}
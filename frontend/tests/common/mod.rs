@@ -0,0 +1,43 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+
+use gb_frontend::storage::{FileStorage, StorageError};
+
+/// Files kept in memory, standing in for the SD card.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    pub fn with_file(mut self, name: &str, data: &[u8]) -> Self {
+        self.files.insert(name.to_string(), data.to_vec());
+        self
+    }
+
+    pub fn file(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(name).map(Vec::as_slice)
+    }
+}
+
+impl FileStorage for MemoryStorage {
+    fn read_file(&mut self, name: &str, buffer: &mut [u8]) -> Result<usize, StorageError> {
+        let data = self.files.get(name).ok_or(StorageError::NotFound)?;
+        let len = data.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn file_len(&mut self, name: &str) -> Result<usize, StorageError> {
+        self.file(name)
+            .map(<[u8]>::len)
+            .ok_or(StorageError::NotFound)
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), StorageError> {
+        self.files.insert(name.to_string(), data.to_vec());
+        Ok(())
+    }
+}
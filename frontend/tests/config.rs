@@ -0,0 +1,156 @@
+use gb_frontend::{
+    config::{ButtonMap, Config, ConfigError, GameKey, Palette, Scaling, MAX_CACHE_BANKS},
+    presenter::recolor,
+};
+
+const GAME_FILE: &str = "\
+[display]
+palette = green
+frame_skip = 2
+scaling = 2x
+
+[buttons]
+a = b
+b = a
+select = start
+
+[cheats]
+enabled = 0, 2
+";
+
+#[test]
+fn game_file_sets_palette_frame_skip_buttons_and_cheats() {
+    let (global, errors) = Config::parse("[display]\nscaling = fit\n");
+    assert!(errors.is_empty());
+    let (game, errors) = global.with_game_settings(GAME_FILE);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(game.palette, Palette::Green);
+    assert_eq!(game.frame_skip, 2);
+    assert_eq!(game.scaling, Scaling::Double);
+    assert_eq!(
+        game.buttons,
+        ButtonMap {
+            a: GameKey::B,
+            b: GameKey::A,
+            start: GameKey::Start,
+            select: GameKey::Start,
+        }
+    );
+    assert_eq!(game.enabled_cheats, Some(vec![0, 2]));
+    // The global settings are left alone.
+    assert_eq!(global.palette, Palette::Original);
+    assert_eq!(global.buttons, ButtonMap::default());
+    assert_eq!(global.enabled_cheats, None);
+}
+
+#[test]
+fn cheats_can_only_be_chosen_per_game() {
+    let (config, errors) = Config::parse("[cheats]\nenabled = 1\n");
+    assert_eq!(
+        errors,
+        [(2, ConfigError::OnlyPerGame("enabled".to_string()))]
+    );
+    assert_eq!(config.enabled_cheats, None);
+}
+
+#[test]
+fn game_file_cannot_change_hardware_settings() {
+    let (global, _) = Config::parse("");
+    let (game, errors) = global.with_game_settings("[display]\nspi_mhz = 20\n");
+    assert_eq!(
+        errors,
+        [(2, ConfigError::NotPerGame("spi_mhz".to_string()))]
+    );
+    assert_eq!(game.display_spi_mhz, global.display_spi_mhz);
+}
+
+#[test]
+fn rejects_unknown_buttons_keys_and_frame_skips() {
+    let (config, errors) =
+        Config::parse("[buttons]\na = turbo\nl = a\n[display]\nframe_skip = 4\npalette = sepia\n");
+    let invalid = |key: &str, value: &str| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    };
+    assert_eq!(
+        errors,
+        [
+            (2, invalid("a", "turbo")),
+            (3, ConfigError::UnknownKey("l".to_string())),
+            (5, invalid("frame_skip", "4")),
+            (6, invalid("palette", "sepia")),
+        ]
+    );
+    assert_eq!(config.buttons, ButtonMap::default());
+    assert_eq!(config.frame_skip, 0);
+    assert_eq!(config.palette, Palette::Original);
+}
+
+#[test]
+fn game_settings_file_only_keeps_the_differences() {
+    let (global, _) = Config::parse("[display]\nframe_skip = 2\n");
+    let (game, _) = global.with_game_settings(GAME_FILE);
+    let text = game.game_settings_ini(&global);
+    assert!(text.contains("palette = green\n"));
+    assert!(text.contains("scaling = 2x\n"));
+    assert!(text.contains("[buttons]\na = b\nb = a\nselect = start\n"));
+    assert!(text.contains("[cheats]\nenabled = 0, 2\n"));
+    assert!(!text.contains("frame_skip"));
+    assert!(!text.contains("start = "));
+    assert!(!text.contains("spi_mhz"));
+
+    // Read back over the same global settings it is the same game.
+    let (reread, errors) = global.with_game_settings(&text);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(reread.game_settings_ini(&global), text);
+}
+
+#[test]
+fn saving_for_every_game_keeps_the_cheats_per_game() {
+    let (global, _) = Config::parse("");
+    let (game, _) = global.with_game_settings(GAME_FILE);
+    let global = game.game_settings_over(&global);
+    assert_eq!(global.palette, Palette::Green);
+    assert_eq!(global.buttons, game.buttons);
+    assert_eq!(global.enabled_cheats, None);
+    assert!(!global.to_ini().contains("[cheats]"));
+}
+
+#[test]
+fn original_palette_leaves_the_picture_alone() {
+    assert_eq!(Palette::Original.colors(), None);
+    for palette in &Palette::ALL[1..] {
+        assert!(palette.colors().is_some(), "{}", palette.name());
+    }
+}
+
+#[test]
+fn recolor_maps_the_four_shades() {
+    let colors = [0x1111, 0x2222, 0x3333, 0x4444];
+    // White, light grey (0xAA), dark grey (0x55) and black in RGB565.
+    let shades = [0xFFFF, 0xAD55, 0x52AA, 0x0000];
+    for (shade, color) in shades.iter().zip(colors) {
+        assert_eq!(recolor(*shade, &colors), color, "{:04x}", shade);
+    }
+}
+
+#[test]
+fn cache_banks_stay_within_the_menu_limit() {
+    let (config, errors) = Config::parse(&format!("[cache]\nbanks = {}\n", MAX_CACHE_BANKS));
+    assert!(errors.is_empty());
+    assert_eq!(config.cache.capacity, MAX_CACHE_BANKS);
+    for banks in [0, MAX_CACHE_BANKS + 1] {
+        let (config, errors) = Config::parse(&format!("[cache]\nbanks = {}\n", banks));
+        assert_eq!(
+            errors,
+            [(
+                2,
+                ConfigError::InvalidValue {
+                    key: "banks".to_string(),
+                    value: banks.to_string(),
+                }
+            )]
+        );
+        assert_eq!(config.cache.capacity, Config::default().cache.capacity);
+    }
+}
@@ -0,0 +1,58 @@
+#!/usr/bin/env python3
+"""Writes the archive fixtures of tests/archive.rs into this directory."""
+
+import struct
+import zipfile
+import zlib
+
+ROM_SIZE = 0x2000
+README = ("README.TXT", b"Not a ROM.\n")
+
+
+def rom():
+    return bytes((i * 7 + 3) % 251 for i in range(ROM_SIZE))
+
+
+def write_zip(path, entries, compression, comment=b""):
+    with zipfile.ZipFile(path, "w", compression) as archive:
+        for name, data in entries:
+            info = zipfile.ZipInfo(name, date_time=(2024, 1, 1, 0, 0, 0))
+            info.compress_type = compression
+            archive.writestr(info, data)
+        archive.comment = comment
+
+
+def write_gzip(path, name=None, extra=None):
+    flags = (0x04 if extra is not None else 0) | (0x08 if name is not None else 0)
+    header = bytes([0x1F, 0x8B, 0x08, flags]) + struct.pack("<I", 0) + bytes([0, 0xFF])
+    if extra is not None:
+        header += struct.pack("<H", len(extra)) + extra
+    if name is not None:
+        header += name + b"\0"
+    deflate = zlib.compressobj(9, zlib.DEFLATED, -15)
+    body = deflate.compress(rom()) + deflate.flush()
+    trailer = struct.pack("<II", zlib.crc32(rom()), ROM_SIZE)
+    with open(path, "wb") as file:
+        file.write(header + body + trailer)
+
+
+def main():
+    write_zip("stored.zip", [README, ("GAME.GB", rom())], zipfile.ZIP_STORED)
+    write_zip("deflate.zip", [("Game.GBC", rom())], zipfile.ZIP_DEFLATED)
+    # Longer than the first search for the end record.
+    write_zip("comment.zip", [("GAME.GB", rom())], zipfile.ZIP_DEFLATED, b"comment " * 200)
+    write_zip("norom.zip", [README], zipfile.ZIP_DEFLATED)
+    write_gzip("plain.gz")
+    write_gzip("named.gz", name=b"tetris.gb", extra=b"AP\x04\x00test")
+
+    # The central directory records a CRC the data does not have.
+    with open("deflate.zip", "rb") as file:
+        data = bytearray(file.read())
+    central = data.index(b"PK\x01\x02")
+    data[central + 16] ^= 0xFF
+    with open("crc.zip", "wb") as file:
+        file.write(data)
+
+
+if __name__ == "__main__":
+    main()
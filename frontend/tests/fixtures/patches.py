@@ -0,0 +1,187 @@
+#!/usr/bin/env python3
+"""Writes the BPS and UPS fixtures of tests/patch.rs into this directory.
+
+The patched ROMs are produced by the byte-at-a-time appliers below, which
+follow the beat and UPS specifications directly, so the segment based
+RomPatch is checked against an independent implementation.
+"""
+
+import struct
+import zlib
+
+ROM_SIZE = 0x2000
+
+
+def rom():
+    return bytes((i * 7 + 3) % 251 for i in range(ROM_SIZE))
+
+
+def number(value):
+    encoded = b""
+    while True:
+        low = value & 0x7F
+        value >>= 7
+        if value == 0:
+            return encoded + bytes([0x80 | low])
+        encoded += bytes([low])
+        value -= 1
+
+
+def read_number(data, position):
+    value, shift = 0, 1
+    while True:
+        byte = data[position]
+        position += 1
+        value += (byte & 0x7F) * shift
+        if byte & 0x80:
+            return value, position
+        shift <<= 7
+        value += shift
+
+
+def footer(body, source, target):
+    body += struct.pack("<II", zlib.crc32(source), zlib.crc32(target))
+    return body + struct.pack("<I", zlib.crc32(body))
+
+
+class BpsWriter:
+    def __init__(self):
+        self.commands = b""
+        self.source_offset = 0
+        self.target_offset = 0
+
+    def command(self, action, length):
+        self.commands += number((length - 1) << 2 | action)
+
+    def relative(self, distance):
+        self.commands += number(abs(distance) << 1 | (distance < 0))
+
+    def source_read(self, length):
+        self.command(0, length)
+
+    def target_read(self, data):
+        self.command(1, len(data))
+        self.commands += data
+
+    def source_copy(self, offset, length):
+        self.command(2, length)
+        self.relative(offset - self.source_offset)
+        self.source_offset = offset + length
+
+    def target_copy(self, offset, length):
+        self.command(3, length)
+        self.relative(offset - self.target_offset)
+        self.target_offset = offset + length
+
+
+def apply_bps(source, patch):
+    assert patch[:4] == b"BPS1"
+    source_size, position = read_number(patch, 4)
+    target_size, position = read_number(patch, position)
+    metadata, position = read_number(patch, position)
+    assert source_size == len(source)
+    position += metadata
+    target = bytearray(target_size)
+    output = source_offset = target_offset = 0
+    while position < len(patch) - 12:
+        command, position = read_number(patch, position)
+        action, length = command & 3, (command >> 2) + 1
+        if action in (2, 3):
+            distance, position = read_number(patch, position)
+            distance = -(distance >> 1) if distance & 1 else distance >> 1
+            if action == 2:
+                source_offset += distance
+            else:
+                target_offset += distance
+        for _ in range(length):
+            if action == 0:
+                target[output] = source[output]
+            elif action == 1:
+                target[output] = patch[position]
+                position += 1
+            elif action == 2:
+                target[output] = source[source_offset]
+                source_offset += 1
+            else:
+                target[output] = target[target_offset]
+                target_offset += 1
+            output += 1
+    assert output == target_size
+    return bytes(target)
+
+
+def apply_ups(source, patch):
+    assert patch[:4] == b"UPS1"
+    source_size, position = read_number(patch, 4)
+    target_size, position = read_number(patch, position)
+    assert source_size == len(source)
+    target = bytearray(source[:target_size].ljust(target_size, b"\0"))
+    offset = 0
+    while position < len(patch) - 12:
+        skip, position = read_number(patch, position)
+        offset += skip
+        while patch[position] != 0:
+            target[offset] ^= patch[position]
+            offset += 1
+            position += 1
+        position += 1
+        offset += 1
+    return bytes(target)
+
+
+def write_bps(source):
+    """Every command, with runs that overlap the bytes they copy."""
+    target_size = ROM_SIZE + 0x200
+    writer = BpsWriter()
+    writer.source_read(0x134)
+    writer.target_read(b"PATCHED")  # 0x134..0x13B
+    writer.source_copy(0x1000, 0x80)  # 0x13B..0x1BB
+    writer.target_copy(0x1BA, 0x40)  # 0x1BB..0x1FB, one byte repeated
+    writer.target_copy(0x134, 0x20)  # 0x1FB..0x21B, copy of earlier output
+    writer.target_copy(0x218, 0x35)  # 0x21B..0x250, three source bytes repeated
+    writer.target_read(b"XYZ")  # 0x250..0x253
+    writer.target_copy(0x24C, 0x50)  # 0x253..0x2A3, four source and three patch bytes
+    writer.target_read(b"abc")  # 0x2A3..0x2A6
+    writer.target_copy(0x2A3, 0x30)  # 0x2A6..0x2D6, three patch bytes repeated
+    writer.source_copy(0x10, 0x20)  # 0x2D6..0x2F6, copying backwards
+    writer.source_read(ROM_SIZE - 0x2F6)
+    writer.target_copy(0x100, 0x200)  # past the end of the source
+    body = b"BPS1" + number(ROM_SIZE) + number(target_size)
+    metadata = b"<test/>"
+    body += number(len(metadata)) + metadata + writer.commands
+    target = apply_bps(source, footer(body, source, bytes(target_size)))
+    patch = footer(body, source, target)
+    assert apply_bps(source, patch) == target
+    return patch, target
+
+
+def write_ups(source):
+    """XOR runs inside the ROM, across its end and up to the new end."""
+    target_size = ROM_SIZE + 0x100
+    padded = source.ljust(target_size, b"\0")
+    runs = [(0x134, b"PATCHED"), (0x1FF0, bytes(range(1, 0x31))), (0x20F0, b"\xFF" * 0x10)]
+    body = b"UPS1" + number(ROM_SIZE) + number(target_size)
+    offset = 0
+    for start, data in runs:
+        xor = bytes(new ^ old for new, old in zip(data, padded[start:]))
+        assert 0 not in xor
+        body += number(start - offset) + xor + b"\0"
+        offset = start + len(data) + 1
+    target = apply_ups(source, footer(body, source, bytes(target_size)))
+    patch = footer(body, source, target)
+    assert apply_ups(source, patch) == target
+    return patch, target
+
+
+def main():
+    source = rom()
+    for patch, target in [write_bps(source), write_ups(source)]:
+        extension = patch[:3].decode().lower()
+        with open(f"game.{extension}", "wb") as file:
+            file.write(patch)
+        with open(f"game-{extension}.gb", "wb") as file:
+            file.write(target)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,267 @@
+use gb_frontend::gameboy::header::{
+    CartridgeHeader, CartridgeType, CgbSupport, GlobalChecksum, HeaderError, Licensee, Mbc,
+    HEADER_END,
+};
+
+/// Bank 0 of a 32 KiB MBC1 cartridge called `title`, checksum included.
+fn bank_0(title: &[u8]) -> Vec<u8> {
+    let mut bank = vec![0; 0x4000];
+    bank[0x134..0x134 + title.len()].copy_from_slice(title);
+    bank[0x147] = 0x01;
+    with_checksum(bank)
+}
+
+fn with_checksum(mut bank: Vec<u8>) -> Vec<u8> {
+    bank[0x14D] = bank[0x134..0x14D]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    bank
+}
+
+/// `bank` with the byte at `offset` replaced and the checksum fixed.
+fn with_byte(bank: &[u8], offset: usize, value: u8) -> Vec<u8> {
+    let mut bank = bank.to_vec();
+    bank[offset] = value;
+    with_checksum(bank)
+}
+
+#[test]
+fn parses_every_field() {
+    let mut bank = bank_0(b"POCKET MONSTERS");
+    bank[0x143] = 0x80;
+    bank[0x144..0x146].copy_from_slice(b"01");
+    bank[0x146] = 0x03;
+    bank[0x147] = 0x10;
+    bank[0x148] = 0x05;
+    bank[0x149] = 0x03;
+    bank[0x14B] = 0x33;
+    bank[0x14C] = 0x02;
+    bank[0x14E..0x150].copy_from_slice(&[0x12, 0x34]);
+    let bank = with_checksum(bank);
+
+    let header = CartridgeHeader::parse(&bank).unwrap();
+    assert_eq!(header.title, "POCKET MONSTERS");
+    assert_eq!(header.cgb, CgbSupport::Compatible);
+    assert!(header.sgb);
+    assert_eq!(header.cartridge_type.mbc, Mbc::Mbc3);
+    assert!(header.cartridge_type.ram && header.cartridge_type.battery);
+    assert!(header.cartridge_type.timer && !header.cartridge_type.rumble);
+    assert_eq!(header.rom_size, 0x10_0000);
+    assert_eq!(header.ram_size, 0x8000);
+    assert_eq!(header.save_size(), 0x8000);
+    assert_eq!(header.licensee, Licensee::New(*b"01"));
+    assert_eq!(header.version, 2);
+    assert_eq!(header.header_checksum, bank[0x14D]);
+    assert_eq!(header.global_checksum, 0x1234);
+}
+
+#[test]
+fn title_gives_its_last_byte_to_the_cgb_flag() {
+    let bank = bank_0(b"SIXTEEN BYTES!!!");
+    assert_eq!(
+        CartridgeHeader::parse(&bank).unwrap().title,
+        "SIXTEEN BYTES!!!"
+    );
+
+    let colour = CartridgeHeader::parse(&with_byte(&bank, 0x143, 0xC0)).unwrap();
+    assert_eq!(colour.cgb, CgbSupport::Required);
+    assert_eq!(colour.title, "SIXTEEN BYTES!!");
+}
+
+#[test]
+fn title_is_trimmed_and_made_printable() {
+    let header = CartridgeHeader::parse(&bank_0(b"GAME\x01\xFF  \0JUNK")).unwrap();
+    assert_eq!(header.title, "GAME??");
+    assert_eq!(header.cgb, CgbSupport::None);
+    assert!(!header.sgb);
+}
+
+#[test]
+fn old_licensee_codes_are_kept() {
+    let bank = with_byte(&bank_0(b"GAME"), 0x14B, 0x01);
+    let header = CartridgeHeader::parse(&bank).unwrap();
+    assert_eq!(header.licensee, Licensee::Old(0x01));
+    assert_eq!(header.licensee.to_string(), "01");
+    assert_eq!(Licensee::New(*b"8P").to_string(), "8P");
+}
+
+#[test]
+fn bad_header_checksum_is_rejected() {
+    let mut bank = bank_0(b"GAME");
+    let computed = bank[0x14D];
+    bank[0x14D] = computed.wrapping_add(1);
+    assert_eq!(
+        CartridgeHeader::parse(&bank),
+        Err(HeaderError::HeaderChecksum {
+            stored: computed.wrapping_add(1),
+            computed,
+        })
+    );
+
+    // Every header byte is covered, not only the title.
+    let mut bank = bank_0(b"GAME");
+    bank[0x14C] = 1;
+    assert!(matches!(
+        CartridgeHeader::parse(&bank),
+        Err(HeaderError::HeaderChecksum { .. })
+    ));
+}
+
+#[test]
+fn truncated_input_is_rejected() {
+    let bank = bank_0(b"GAME");
+    for len in [0, 0x100, HEADER_END - 1] {
+        assert_eq!(
+            CartridgeHeader::parse(&bank[..len]),
+            Err(HeaderError::TooShort(len))
+        );
+    }
+    assert!(CartridgeHeader::parse(&bank[..HEADER_END]).is_ok());
+}
+
+#[test]
+fn unknown_cartridge_types_are_rejected() {
+    let bank = bank_0(b"GAME");
+    for code in [0x04, 0x07, 0x0A, 0x0E, 0x14, 0x1F, 0x21, 0x80, 0xFB] {
+        assert_eq!(CartridgeType::from_code(code), None);
+        assert_eq!(
+            CartridgeHeader::parse(&with_byte(&bank, 0x147, code)),
+            Err(HeaderError::UnknownCartridgeType(code))
+        );
+    }
+}
+
+#[test]
+fn cartridges_the_core_cannot_run_are_rejected() {
+    let bank = bank_0(b"GAME");
+    for (code, mbc) in [
+        (0x0B, Mbc::Mmm01),
+        (0x20, Mbc::Mbc6),
+        (0x22, Mbc::Mbc7),
+        (0xFC, Mbc::PocketCamera),
+        (0xFD, Mbc::Tama5),
+        (0xFE, Mbc::HuC3),
+        (0xFF, Mbc::HuC1),
+    ] {
+        assert_eq!(
+            CartridgeHeader::parse(&with_byte(&bank, 0x147, code)),
+            Err(HeaderError::UnsupportedMbc(mbc))
+        );
+    }
+}
+
+#[test]
+fn cartridge_types_decode_their_features() {
+    // Code, controller, RAM, battery, timer, rumble.
+    let expected = [
+        (0x00, Mbc::RomOnly, false, false, false, false),
+        (0x03, Mbc::Mbc1, true, true, false, false),
+        (0x05, Mbc::Mbc2, false, false, false, false),
+        (0x06, Mbc::Mbc2, false, true, false, false),
+        (0x09, Mbc::RomOnly, true, true, false, false),
+        (0x0F, Mbc::Mbc3, false, true, true, false),
+        (0x11, Mbc::Mbc3, false, false, false, false),
+        (0x13, Mbc::Mbc3, true, true, false, false),
+        (0x1A, Mbc::Mbc5, true, false, false, false),
+        (0x1C, Mbc::Mbc5, false, false, false, true),
+        (0x1E, Mbc::Mbc5, true, true, false, true),
+    ];
+    for (code, mbc, ram, battery, timer, rumble) in expected {
+        assert_eq!(
+            CartridgeType::from_code(code),
+            Some(CartridgeType {
+                code,
+                mbc,
+                ram,
+                battery,
+                timer,
+                rumble,
+            })
+        );
+    }
+}
+
+#[test]
+fn rom_size_doubles_with_every_code() {
+    let bank = bank_0(b"GAME");
+    for code in 0..=8u8 {
+        let header = CartridgeHeader::parse(&with_byte(&bank, 0x148, code)).unwrap();
+        assert_eq!(header.rom_size, 0x8000 << code);
+    }
+    for code in [0x09, 0x52, 0xFF] {
+        assert_eq!(
+            CartridgeHeader::parse(&with_byte(&bank, 0x148, code)),
+            Err(HeaderError::UnknownRomSize(code))
+        );
+    }
+}
+
+#[test]
+fn ram_size_codes_decode() {
+    // MBC1 with RAM and battery.
+    let bank = with_byte(&bank_0(b"GAME"), 0x147, 0x03);
+    for (code, size) in [
+        (0x00, 0),
+        (0x01, 0x800),
+        (0x02, 0x2000),
+        (0x03, 0x8000),
+        (0x04, 0x20000),
+        (0x05, 0x10000),
+    ] {
+        let header = CartridgeHeader::parse(&with_byte(&bank, 0x149, code)).unwrap();
+        assert_eq!(header.ram_size, size, "code {:02X}", code);
+        assert_eq!(header.save_size(), size);
+    }
+    assert_eq!(
+        CartridgeHeader::parse(&with_byte(&bank, 0x149, 0x06)),
+        Err(HeaderError::UnknownRamSize(0x06))
+    );
+}
+
+#[test]
+fn mbc2_always_has_its_built_in_ram() {
+    let bank = with_byte(&bank_0(b"GAME"), 0x147, 0x06);
+    for code in [0x00, 0x03, 0x06] {
+        let header = CartridgeHeader::parse(&with_byte(&bank, 0x149, code)).unwrap();
+        assert_eq!(header.ram_size, 0x200);
+        assert_eq!(header.save_size(), 0x200);
+    }
+}
+
+#[test]
+fn save_size_is_zero_without_a_battery() {
+    // MBC1 with RAM but no battery.
+    let bank = with_byte(&with_byte(&bank_0(b"GAME"), 0x147, 0x02), 0x149, 0x02);
+    let header = CartridgeHeader::parse(&bank).unwrap();
+    assert_eq!(header.ram_size, 0x2000);
+    assert_eq!(header.save_size(), 0);
+}
+
+#[test]
+fn global_checksum_skips_its_own_bytes() {
+    let mut rom: Vec<u8> = (0..0x8000u32).map(|i| (i * 13 + 5) as u8).collect();
+    // A ROM-only cartridge, so the header parses.
+    rom[0x134..0x150].fill(0);
+    let mut rom = with_checksum(rom);
+    let sum = rom
+        .iter()
+        .enumerate()
+        .filter(|(offset, _)| !(0x14E..0x150).contains(offset))
+        .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+    rom[0x14E..0x150].copy_from_slice(&sum.to_be_bytes());
+    let header = CartridgeHeader::parse(&rom).unwrap();
+    assert_eq!(header.global_checksum, sum);
+
+    // Chunks that split the checksum bytes between two updates.
+    let mut checksum = GlobalChecksum::new();
+    for chunk in rom.chunks(0x14F) {
+        checksum.update(chunk);
+    }
+    assert_eq!(checksum.finish(), header.global_checksum);
+
+    let mut damaged = rom.clone();
+    damaged[0x4000] ^= 1;
+    let mut checksum = GlobalChecksum::new();
+    checksum.update(&damaged);
+    assert_ne!(checksum.finish(), header.global_checksum);
+}
@@ -0,0 +1,373 @@
+mod common;
+
+use common::MemoryStorage;
+use gb_frontend::{
+    checksum::crc32,
+    gameboy::patch::{load_patch, PatchError, PatchFormat, RomPatch},
+    storage::{FileStorage, StorageError},
+};
+
+const ROM_SIZE: usize = 0x8000;
+
+/// Made by `fixtures/patches.py`, with the ROMs its reference patchers produce.
+const BPS_PATCH: &[u8] = include_bytes!("fixtures/game.bps");
+const BPS_TARGET: &[u8] = include_bytes!("fixtures/game-bps.gb");
+const UPS_PATCH: &[u8] = include_bytes!("fixtures/game.ups");
+const UPS_TARGET: &[u8] = include_bytes!("fixtures/game-ups.gb");
+/// Size of the ROM the fixtures patch.
+const SOURCE_SIZE: usize = 0x2000;
+
+fn fixture_rom() -> Vec<u8> {
+    (0..SOURCE_SIZE)
+        .map(|i| ((i * 7 + 3) % 251) as u8)
+        .collect()
+}
+
+fn reader(rom: &[u8]) -> impl FnMut(usize, &mut [u8]) -> Result<(), StorageError> + '_ {
+    |offset, buffer| {
+        buffer.copy_from_slice(&rom[offset..offset + buffer.len()]);
+        Ok(())
+    }
+}
+
+/// The whole patched ROM, read in pieces that do not line up with the
+/// patched ranges.
+fn apply(patch: &RomPatch, rom: &[u8]) -> Vec<u8> {
+    let mut patched = vec![0; patch.target_size()];
+    for chunk_start in (0..patched.len()).step_by(0x1F3) {
+        let chunk_end = (chunk_start + 0x1F3).min(patched.len());
+        patch
+            .read(
+                chunk_start,
+                &mut patched[chunk_start..chunk_end],
+                reader(rom),
+            )
+            .unwrap();
+    }
+    patched
+}
+
+/// The BPS/UPS variable-length integer encoding.
+fn number(mut value: usize) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    loop {
+        let low = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            encoded.push(0x80 | low);
+            return encoded;
+        }
+        encoded.push(low);
+        value -= 1;
+    }
+}
+
+/// Appends the source and target CRCs and the CRC of the patch itself.
+fn with_footer(mut patch: Vec<u8>, source_crc: u32, target_crc: u32) -> Vec<u8> {
+    patch.extend_from_slice(&source_crc.to_le_bytes());
+    patch.extend_from_slice(&target_crc.to_le_bytes());
+    let crc = crc32(&patch);
+    patch.extend_from_slice(&crc.to_le_bytes());
+    patch
+}
+
+/// A BPS patch for a `SOURCE_SIZE` ROM with no metadata and these commands.
+fn bps(target_size: usize, commands: &[u8]) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    patch.extend(number(SOURCE_SIZE));
+    patch.extend(number(target_size));
+    patch.extend(number(0));
+    patch.extend_from_slice(commands);
+    with_footer(patch, 0, 0)
+}
+
+/// A BPS command of `action` (0 to 3) for `len` bytes.
+fn command(action: usize, len: usize) -> Vec<u8> {
+    number((len - 1) << 2 | action)
+}
+
+fn parse_bps(patch: &[u8]) -> Result<RomPatch, PatchError> {
+    RomPatch::parse(PatchFormat::Bps, patch, SOURCE_SIZE)
+}
+
+/// Rewrites the cartridge title and zero fills the start of bank 1.
+fn ips_patch() -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    patch.extend_from_slice(&[0x00, 0x01, 0x34, 0x00, 0x04]);
+    patch.extend_from_slice(b"TEST");
+    patch.extend_from_slice(&[0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00]);
+    patch.extend_from_slice(b"EOF");
+    patch
+}
+
+/// Reports a patch file too large to fit in memory.
+struct HugeFile;
+
+impl FileStorage for HugeFile {
+    fn read_file(&mut self, _name: &str, _buffer: &mut [u8]) -> Result<usize, StorageError> {
+        unreachable!("the patch is never read");
+    }
+
+    fn file_len(&mut self, _name: &str) -> Result<usize, StorageError> {
+        Ok(usize::MAX)
+    }
+
+    fn write_file(&mut self, _name: &str, _data: &[u8]) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn applies_the_ips_next_to_the_rom() {
+    let mut storage = MemoryStorage::default().with_file("GAME.IPS", &ips_patch());
+    let (file_name, patch) = match load_patch(&mut storage, "GAME.GB", ROM_SIZE) {
+        Ok(Some(found)) => found,
+        _ => panic!("GAME.IPS not applied"),
+    };
+    assert_eq!(file_name, "GAME.IPS");
+    assert_eq!(patch.target_size(), ROM_SIZE);
+
+    let rom = vec![0xAA; ROM_SIZE];
+    let mut patched = vec![0; ROM_SIZE];
+    patch
+        .read(0, &mut patched, |offset, buffer| {
+            buffer.copy_from_slice(&rom[offset..offset + buffer.len()]);
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(&patched[0x134..0x138], b"TEST");
+    assert!(patched[0x4000..0x4010].iter().all(|byte| *byte == 0));
+    assert_eq!(patched[0x4010], 0xAA);
+}
+
+#[test]
+fn no_patch_file_is_no_patch() {
+    let mut storage = MemoryStorage::default();
+    assert!(matches!(
+        load_patch(&mut storage, "GAME.GB", ROM_SIZE),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn patch_too_large_for_memory_is_an_error() {
+    assert!(matches!(
+        load_patch(&mut HugeFile, "GAME.GB", ROM_SIZE),
+        Err(PatchError::OutOfMemory(usize::MAX))
+    ));
+}
+
+#[test]
+fn bps_matches_the_reference_patcher() {
+    let rom = fixture_rom();
+    let mut storage = MemoryStorage::default().with_file("GAME.BPS", BPS_PATCH);
+    let (file_name, patch) = match load_patch(&mut storage, "GAME.GB", SOURCE_SIZE) {
+        Ok(Some(found)) => found,
+        _ => panic!("GAME.BPS not applied"),
+    };
+    assert_eq!(file_name, "GAME.BPS");
+    assert_eq!(patch.target_size(), BPS_TARGET.len());
+    assert_eq!(apply(&patch, &rom), BPS_TARGET);
+
+    let mut scratch = [0; 0x400];
+    patch.verify_source(&mut scratch, reader(&rom)).unwrap();
+    let crc = patch.target_crc(&mut scratch, reader(&rom)).unwrap();
+    assert_eq!(crc, crc32(BPS_TARGET));
+    assert_eq!(patch.verify_target(crc), Ok(()));
+}
+
+#[test]
+fn ups_matches_the_reference_patcher() {
+    let rom = fixture_rom();
+    let mut storage = MemoryStorage::default().with_file("GAME.UPS", UPS_PATCH);
+    let (file_name, patch) = match load_patch(&mut storage, "GAME.GB", SOURCE_SIZE) {
+        Ok(Some(found)) => found,
+        _ => panic!("GAME.UPS not applied"),
+    };
+    assert_eq!(file_name, "GAME.UPS");
+    assert_eq!(patch.target_size(), UPS_TARGET.len());
+    assert_eq!(apply(&patch, &rom), UPS_TARGET);
+
+    let mut scratch = [0; 0x400];
+    patch.verify_source(&mut scratch, reader(&rom)).unwrap();
+    let crc = patch.target_crc(&mut scratch, reader(&rom)).unwrap();
+    assert_eq!(crc, crc32(UPS_TARGET));
+    assert_eq!(patch.verify_target(crc), Ok(()));
+}
+
+#[test]
+fn another_rom_fails_the_source_check() {
+    let rom = fixture_rom();
+    let mut other = rom.clone();
+    other[0x150] ^= 1;
+    for (format, data) in [(PatchFormat::Bps, BPS_PATCH), (PatchFormat::Ups, UPS_PATCH)] {
+        let patch = RomPatch::parse(format, data, SOURCE_SIZE).unwrap();
+        let mut scratch = [0; 0x300];
+        assert_eq!(
+            patch.verify_source(&mut scratch, reader(&other)),
+            Err(PatchError::SourceChecksum {
+                expected: crc32(&rom),
+                actual: crc32(&other),
+            }),
+            "{:?}",
+            format
+        );
+        assert!(matches!(
+            RomPatch::parse(format, data, SOURCE_SIZE + 1),
+            Err(PatchError::SourceSize {
+                expected: SOURCE_SIZE,
+                actual,
+            }) if actual == SOURCE_SIZE + 1
+        ));
+    }
+}
+
+#[test]
+fn damaged_patches_fail_their_own_checksum() {
+    for (format, data) in [(PatchFormat::Bps, BPS_PATCH), (PatchFormat::Ups, UPS_PATCH)] {
+        // A command byte, the stored source CRC and the patch CRC itself.
+        for index in [6, data.len() - 12, data.len() - 1] {
+            let mut damaged = data.to_vec();
+            damaged[index] ^= 0x40;
+            assert!(
+                matches!(
+                    RomPatch::parse(format, &damaged, SOURCE_SIZE),
+                    Err(PatchError::PatchChecksum)
+                ),
+                "{:?} byte {}",
+                format,
+                index
+            );
+        }
+    }
+}
+
+#[test]
+fn wrong_result_fails_the_target_check() {
+    let rom = fixture_rom();
+    for (format, data, target) in [
+        (PatchFormat::Bps, BPS_PATCH, BPS_TARGET),
+        (PatchFormat::Ups, UPS_PATCH, UPS_TARGET),
+    ] {
+        // Same commands, but a target CRC the result cannot have.
+        let body = data[..data.len() - 12].to_vec();
+        let bogus = crc32(target) ^ 0xFFFF_FFFF;
+        let patch =
+            RomPatch::parse(format, &with_footer(body, crc32(&rom), bogus), SOURCE_SIZE).unwrap();
+        let mut scratch = [0; 0x400];
+        let actual = patch.target_crc(&mut scratch, reader(&rom)).unwrap();
+        assert_eq!(actual, crc32(target));
+        assert_eq!(
+            patch.verify_target(actual),
+            Err(PatchError::TargetChecksum {
+                expected: bogus,
+                actual,
+            })
+        );
+    }
+}
+
+#[test]
+fn bps_commands_must_stay_inside_the_target() {
+    let malformed = |patch: Vec<u8>| matches!(parse_bps(&patch), Err(PatchError::Malformed));
+    // Metadata running past the end of the patch.
+    let mut patch = b"BPS1".to_vec();
+    patch.extend(number(SOURCE_SIZE));
+    patch.extend(number(0x10));
+    patch.extend(number(usize::MAX - 2));
+    assert!(malformed(with_footer(patch, 0, 0)));
+    // A length that overflows, and one past the target size.
+    assert!(malformed(bps(0x10, &command(0, usize::MAX >> 2))));
+    assert!(malformed(bps(0x10, &command(0, 0x11))));
+    let mut commands = command(1, 0x11);
+    commands.extend(vec![0xAA; 0x11]);
+    assert!(malformed(bps(0x10, &commands)));
+    // A TargetRead with fewer bytes than it claims.
+    let mut commands = command(1, 8);
+    commands.extend(vec![0xAA; 4]);
+    assert!(malformed(bps(0x10, &commands)));
+    // Copies from before the start of the source or from unwritten output.
+    let mut commands = command(2, 4);
+    commands.extend(number(8 << 1 | 1));
+    assert!(malformed(bps(0x10, &commands)));
+    let mut commands = command(3, 4);
+    commands.extend(number(0));
+    assert!(malformed(bps(0x10, &commands)));
+    // Commands that leave the end of the target unwritten.
+    assert!(malformed(bps(0x10, &command(0, 8))));
+    // More than the largest cartridge.
+    assert!(parse_bps(&bps(0x80_0000, &command(0, 0x80_0000))).is_ok());
+    assert!(malformed(bps(0x80_0001, &command(0, 0x80_0001))));
+}
+
+#[test]
+fn bps_runs_are_limited() {
+    // A run longer than the target it is in.
+    let mut commands = command(1, 1);
+    commands.push(0xAA);
+    commands.extend(command(3, usize::MAX >> 3));
+    commands.extend(number(0));
+    assert!(matches!(
+        parse_bps(&bps(0x40000, &commands)),
+        Err(PatchError::Malformed)
+    ));
+
+    // A run of a patch byte and a source byte needs a range per byte.
+    let mut commands = command(1, 1);
+    commands.push(0xAA);
+    commands.extend(command(2, 1));
+    commands.extend(number(0x10 << 1));
+    commands.extend(command(3, 0x40000 - 2));
+    commands.extend(number(0));
+    assert!(matches!(
+        parse_bps(&bps(0x40000, &commands)),
+        Err(PatchError::TooManyRanges)
+    ));
+
+    // Without the source byte it is a single range.
+    let mut commands = command(1, 2);
+    commands.extend([0xAA, 0x55]);
+    commands.extend(command(3, 0x40000 - 2));
+    commands.extend(number(0));
+    let patch = parse_bps(&bps(0x40000, &commands)).unwrap();
+    let rom = fixture_rom();
+    let mut patched = [0; 5];
+    patch.read(0x3FFFD, &mut patched, reader(&rom)).unwrap();
+    assert_eq!(patched, [0x55, 0xAA, 0x55, 0xFF, 0xFF]);
+}
+
+#[test]
+fn ups_runs_must_stay_inside_the_target() {
+    let ups = |target_size: usize, runs: &[u8]| {
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(SOURCE_SIZE));
+        patch.extend(number(target_size));
+        patch.extend_from_slice(runs);
+        RomPatch::parse(PatchFormat::Ups, &with_footer(patch, 0, 0), SOURCE_SIZE)
+    };
+    // The terminator of the last run may lie just past the end.
+    let mut runs = number(SOURCE_SIZE - 2);
+    runs.extend([1, 1, 0]);
+    assert!(ups(SOURCE_SIZE, &runs).is_ok());
+
+    let mut runs = number(SOURCE_SIZE - 2);
+    runs.extend([1, 1, 1, 0]);
+    assert!(matches!(
+        ups(SOURCE_SIZE, &runs),
+        Err(PatchError::Malformed)
+    ));
+    // Offsets adding up past the end of the address space.
+    let mut runs = number(usize::MAX >> 1);
+    runs.extend([1, 0]);
+    runs.extend(number(usize::MAX >> 1));
+    runs.extend([1, 0]);
+    assert!(matches!(
+        ups(SOURCE_SIZE, &runs),
+        Err(PatchError::Malformed)
+    ));
+    // A run without a terminator.
+    assert!(matches!(
+        ups(SOURCE_SIZE, &[0x80, 1]),
+        Err(PatchError::Malformed)
+    ));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::MemoryStorage;
+use gb_frontend::presenter::{
+    encode_bmp, rgb888, FramePresenter, ImagePresenter, GB_HEIGHT, GB_WIDTH,
+};
+
+const HEADER_LEN: usize = 54;
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// A different RGB565 colour for every pixel.
+fn test_pixel(x: usize, y: usize) -> u16 {
+    ((x as u16 & 0x1F) << 11) | ((y as u16 & 0x3F) << 5) | ((x + y) as u16 & 0x1F)
+}
+
+#[test]
+fn writes_a_frame_as_a_bottom_up_bgr_bitmap() {
+    let mut presenter = ImagePresenter::new(MemoryStorage::default());
+    let mut frame = (0..GB_HEIGHT).flat_map(|y| (0..GB_WIDTH).map(move |x| test_pixel(x, y)));
+    presenter.present(&mut frame).unwrap();
+    let storage = presenter.into_inner();
+    let bmp = storage.file("GB000000.BMP").unwrap();
+
+    let row_len = GB_WIDTH * 3;
+    assert_eq!(&bmp[..2], b"BM");
+    assert_eq!(u32_at(bmp, 2) as usize, bmp.len());
+    assert_eq!(bmp.len(), HEADER_LEN + row_len * GB_HEIGHT);
+    assert_eq!(u32_at(bmp, 10) as usize, HEADER_LEN);
+    assert_eq!(u32_at(bmp, 14), 40);
+    assert_eq!(u32_at(bmp, 18) as usize, GB_WIDTH);
+    assert_eq!(u32_at(bmp, 22) as usize, GB_HEIGHT);
+    assert_eq!(u16_at(bmp, 26), 1);
+    assert_eq!(u16_at(bmp, 28), 24);
+    assert_eq!(u32_at(bmp, 30), 0);
+
+    for y in 0..GB_HEIGHT {
+        // The last row of the picture comes first.
+        let row = HEADER_LEN + (GB_HEIGHT - 1 - y) * row_len;
+        for x in 0..GB_WIDTH {
+            let [red, green, blue] = rgb888(test_pixel(x, y));
+            let offset = row + x * 3;
+            assert_eq!(
+                &bmp[offset..offset + 3],
+                [blue, green, red],
+                "({}, {})",
+                x,
+                y
+            );
+        }
+    }
+}
+
+#[test]
+fn numbers_the_frames() {
+    let mut presenter = ImagePresenter::new(MemoryStorage::default());
+    for _ in 0..2 {
+        presenter
+            .present(&mut core::iter::repeat(0xFFFF).take(GB_WIDTH * GB_HEIGHT))
+            .unwrap();
+    }
+    let storage = presenter.into_inner();
+    let names: Vec<_> = storage.files.keys().map(String::as_str).collect();
+    assert_eq!(names, ["GB000000.BMP", "GB000001.BMP"]);
+    let bmp = storage.file("GB000001.BMP").unwrap();
+    assert!(bmp[HEADER_LEN..].iter().all(|byte| *byte == 0xFF));
+}
+
+#[test]
+fn rows_are_padded_to_four_bytes() {
+    // Red, green; blue, white.
+    let bmp = encode_bmp(&[0xF800, 0x07E0, 0x001F, 0xFFFF], 2, 2);
+    assert_eq!(bmp.len(), HEADER_LEN + 2 * 8);
+    assert_eq!(u32_at(&bmp, 34), 16);
+    assert_eq!(
+        &bmp[HEADER_LEN..],
+        [
+            0xFF, 0, 0, 0xFF, 0xFF, 0xFF, 0, 0, //
+            0, 0, 0xFF, 0, 0xFF, 0, 0, 0,
+        ]
+    );
+}
+
+#[test]
+fn rgb888_keeps_black_and_white() {
+    assert_eq!(rgb888(0x0000), [0, 0, 0]);
+    assert_eq!(rgb888(0xFFFF), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(rgb888(0x8410), [0x84, 0x82, 0x84]);
+}
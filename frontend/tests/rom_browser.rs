@@ -0,0 +1,226 @@
+use gb_frontend::{
+    gameboy::header::HEADER_END,
+    storage::StorageError,
+    ui::{
+        rom_browser::{BrowserAction, RomBrowser, RomDirectory},
+        MenuInput,
+    },
+};
+
+/// A directory listing `files` in order, of which only the ones in
+/// `headers` can be read.
+#[derive(Default)]
+struct FakeDirectory {
+    files: Vec<String>,
+    headers: Vec<(&'static str, Vec<u8>)>,
+    error: Option<StorageError>,
+}
+
+impl FakeDirectory {
+    fn new(files: &[&str]) -> Self {
+        Self {
+            files: files.iter().map(|name| name.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    fn with_title(mut self, file: &'static str, title: &str) -> Self {
+        self.headers.push((file, header(title)));
+        self
+    }
+
+    fn failing(error: StorageError) -> Self {
+        Self {
+            error: Some(error),
+            ..Self::default()
+        }
+    }
+}
+
+impl RomDirectory for FakeDirectory {
+    fn for_each_file(&mut self, f: &mut dyn FnMut(&str)) -> Result<(), StorageError> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        self.files.iter().for_each(|name| f(name));
+        Ok(())
+    }
+
+    fn read_start(&mut self, name: &str, buffer: &mut [u8]) -> usize {
+        match self.headers.iter().find(|(file, _)| *file == name) {
+            Some((_, header)) => {
+                let len = header.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&header[..len]);
+                len
+            }
+            None => 0,
+        }
+    }
+}
+
+/// The header of an MBC1 cartridge called `title`.
+fn header(title: &str) -> Vec<u8> {
+    let mut header = vec![0; HEADER_END];
+    header[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+    header[0x147] = 0x01;
+    header[0x14D] = header[0x134..0x14D]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    header
+}
+
+fn file_names(browser: &RomBrowser) -> Vec<&str> {
+    browser
+        .entries()
+        .iter()
+        .map(|entry| entry.file_name.as_str())
+        .collect()
+}
+
+fn browser(entries: usize, visible_rows: usize) -> RomBrowser {
+    let mut directory = FakeDirectory {
+        files: (0..entries)
+            .map(|index| format!("GAME{:02}.GB", index))
+            .collect(),
+        ..FakeDirectory::default()
+    };
+    RomBrowser::new(&mut directory, visible_rows).unwrap()
+}
+
+#[test]
+fn lists_roms_and_archives_sorted_by_title() {
+    let mut directory = FakeDirectory::new(&[
+        "zelda.gbc",
+        "README.TXT",
+        "Tetris.gb",
+        "NOEXT",
+        "MARIO.ZIP",
+        "save.sav",
+        "kirby.gz",
+        "pokemon.gb",
+    ])
+    .with_title("pokemon.gb", "AAA POKEMON");
+    let browser = RomBrowser::new(&mut directory, 5).unwrap();
+    assert_eq!(
+        file_names(&browser),
+        [
+            "pokemon.gb",
+            "kirby.gz",
+            "MARIO.ZIP",
+            "Tetris.gb",
+            "zelda.gbc"
+        ]
+    );
+    // The title from the header, or the file name without one.
+    assert_eq!(browser.entries()[0].label, "AAA POKEMON");
+    assert_eq!(browser.entries()[3].label, "Tetris.gb");
+}
+
+#[test]
+fn listing_errors_are_returned() {
+    assert_eq!(
+        RomBrowser::new(&mut FakeDirectory::failing(StorageError::NoCard), 5).err(),
+        Some(StorageError::NoCard)
+    );
+    assert_eq!(
+        RomBrowser::new(&mut None::<FakeDirectory>, 5).err(),
+        Some(StorageError::NoCard)
+    );
+}
+
+#[test]
+fn first_directory_hides_roms_of_the_same_name() {
+    let mut first = FakeDirectory::new(&["Z.GB", "A.GB"]).with_title("Z.GB", "AAA GAME");
+    let mut second = FakeDirectory::new(&["z.gb", "B.GB"]);
+    let browser = RomBrowser::new(&mut (&mut first, &mut second), 5).unwrap();
+    assert_eq!(file_names(&browser), ["A.GB", "Z.GB", "B.GB"]);
+    assert_eq!(browser.entries()[1].label, "AAA GAME");
+}
+
+#[test]
+fn only_errors_from_the_second_directory_with_nothing_in_the_first_fail() {
+    let failing = || FakeDirectory::failing(StorageError::NoCard);
+    let listed = |mut directory: (FakeDirectory, FakeDirectory)| {
+        RomBrowser::new(&mut directory, 5).map(|browser| browser.entries().len())
+    };
+    assert_eq!(listed((FakeDirectory::new(&["A.GB"]), failing())), Ok(1));
+    assert_eq!(listed((failing(), FakeDirectory::new(&["A.GB"]))), Ok(1));
+    assert_eq!(
+        listed((FakeDirectory::new(&[]), failing())),
+        Err(StorageError::NoCard)
+    );
+}
+
+#[test]
+fn up_and_down_wrap_around() {
+    let mut browser = browser(3, 5);
+    assert_eq!(browser.handle_input(MenuInput::Up), BrowserAction::Redraw);
+    assert_eq!(browser.selected(), 2);
+    assert_eq!(browser.handle_input(MenuInput::Down), BrowserAction::Redraw);
+    assert_eq!(browser.selected(), 0);
+    assert_eq!(browser.handle_input(MenuInput::Down), BrowserAction::Redraw);
+    assert_eq!(browser.selected(), 1);
+}
+
+#[test]
+fn left_and_right_move_a_page_and_stop_at_the_ends() {
+    let mut browser = browser(10, 4);
+    assert_eq!(browser.handle_input(MenuInput::Left), BrowserAction::None);
+    assert_eq!(
+        browser.handle_input(MenuInput::Right),
+        BrowserAction::Redraw
+    );
+    assert_eq!(browser.selected(), 4);
+    assert_eq!(browser.visible_range(), 1..5);
+    browser.handle_input(MenuInput::Right);
+    browser.handle_input(MenuInput::Right);
+    assert_eq!(browser.selected(), 9);
+    assert_eq!(browser.visible_range(), 6..10);
+    assert_eq!(browser.handle_input(MenuInput::Right), BrowserAction::None);
+    assert_eq!(browser.handle_input(MenuInput::Left), BrowserAction::Redraw);
+    assert_eq!(browser.selected(), 5);
+    assert_eq!(browser.visible_range(), 5..9);
+}
+
+#[test]
+fn list_scrolls_to_keep_the_selection_visible() {
+    let mut browser = browser(6, 3);
+    assert_eq!(browser.visible_range(), 0..3);
+    for _ in 0..3 {
+        browser.handle_input(MenuInput::Down);
+    }
+    assert_eq!(browser.visible_range(), 1..4);
+    // Wrapping to the top scrolls back.
+    browser.handle_input(MenuInput::Down);
+    browser.handle_input(MenuInput::Down);
+    browser.handle_input(MenuInput::Down);
+    assert_eq!(browser.selected(), 0);
+    assert_eq!(browser.visible_range(), 0..3);
+    browser.handle_input(MenuInput::Up);
+    assert_eq!(browser.visible_range(), 3..6);
+}
+
+#[test]
+fn accept_launches_and_start_opens_the_settings() {
+    let mut browser = browser(3, 5);
+    browser.handle_input(MenuInput::Down);
+    assert_eq!(
+        browser.handle_input(MenuInput::Accept),
+        BrowserAction::Launch(1)
+    );
+    assert_eq!(
+        browser.handle_input(MenuInput::Start),
+        BrowserAction::Settings
+    );
+    assert_eq!(browser.handle_input(MenuInput::Back), BrowserAction::None);
+    assert_eq!(browser.selected(), 1);
+}
+
+#[test]
+fn empty_list_ignores_input() {
+    let mut browser = browser(0, 5);
+    assert_eq!(browser.visible_range(), 0..0);
+    for input in [MenuInput::Down, MenuInput::Accept, MenuInput::Start] {
+        assert_eq!(browser.handle_input(input), BrowserAction::None);
+    }
+}
@@ -0,0 +1,245 @@
+use gb_frontend::{
+    checksum::crc32,
+    gameboy::rom_partition::{
+        find_partition, normalize_name, FlashRom, MappedRoms, Partition, PartitionError,
+        RomPartition, PARTITION_TABLE_SIZE, SECTOR_SIZE,
+    },
+    ui::rom_browser::RomDirectory,
+};
+
+/// Made with `scripts/gbroms.py pack gbroms.bin tetris.gb zelda.gbc` from the
+/// ROMs `fixture_rom` returns.
+const PACKED_IMAGE: &[u8] = include_bytes!("fixtures/gbroms.bin");
+
+fn fixture_rom(length: usize, seed: usize) -> Vec<u8> {
+    (0..length).map(|i| ((i * 7 + seed) % 251) as u8).collect()
+}
+
+fn table_entry(kind: u8, subtype: u8, offset: u32, size: u32, label: &str) -> Vec<u8> {
+    let mut entry = vec![0xAA, 0x50, kind, subtype];
+    entry.extend_from_slice(&offset.to_le_bytes());
+    entry.extend_from_slice(&size.to_le_bytes());
+    let mut name = [0u8; 16];
+    name[..label.len()].copy_from_slice(label.as_bytes());
+    entry.extend_from_slice(&name);
+    entry.extend_from_slice(&0u32.to_le_bytes());
+    entry
+}
+
+fn rom(name: &str, offset: u32, length: u32) -> FlashRom {
+    FlashRom {
+        name: name.to_string(),
+        offset,
+        length,
+        crc: 0,
+    }
+}
+
+#[test]
+fn finds_the_data_partition_by_label() {
+    let mut table = Vec::new();
+    table.extend(table_entry(0x01, 0x02, 0x9000, 0x6000, "nvs"));
+    table.extend(table_entry(0x00, 0x00, 0x10000, 0x3F0000, "factory"));
+    table.extend(table_entry(0x01, 0x40, 0x400000, 0x400000, "gbroms"));
+    table.resize(PARTITION_TABLE_SIZE, 0xFF);
+    assert_eq!(
+        find_partition(&table, "gbroms"),
+        Some(Partition {
+            offset: 0x400000,
+            size: 0x400000,
+        })
+    );
+    // An app partition is not a data partition, whatever its label.
+    assert_eq!(find_partition(&table, "factory"), None);
+    assert_eq!(find_partition(&table, "missing"), None);
+}
+
+#[test]
+fn stops_at_the_end_of_the_table() {
+    let mut table = table_entry(0x01, 0x02, 0x9000, 0x6000, "nvs");
+    // The MD5 entry ends the table; what follows is not a partition.
+    let mut md5 = vec![0xEB, 0xEB];
+    md5.resize(32, 0xFF);
+    table.extend(md5);
+    table.extend(table_entry(0x01, 0x40, 0x800000, 0x1000, "gbroms"));
+    assert_eq!(find_partition(&table, "gbroms"), None);
+    assert_eq!(find_partition(&[0xFF; 64], "gbroms"), None);
+}
+
+#[test]
+fn erased_sector_is_an_empty_partition() {
+    let partition = RomPartition::parse(0x100000, &[0xFF; SECTOR_SIZE]).unwrap();
+    assert!(partition.roms().is_empty());
+    assert_eq!(partition.free_bytes(), 0x100000 - SECTOR_SIZE as u32);
+}
+
+#[test]
+fn directory_round_trips() {
+    let mut partition = RomPartition::empty(0x100000);
+    partition
+        .insert(FlashRom {
+            name: normalize_name("tetris.gb").unwrap(),
+            offset: 0x1000,
+            length: 0x8000,
+            crc: 0x1234_5678,
+        })
+        .unwrap();
+    partition.insert(rom("ZELDA.GBC", 0x9000, 0x10001)).unwrap();
+    let sector = partition.encode();
+    assert_eq!(sector.len(), SECTOR_SIZE);
+    let parsed = RomPartition::parse(0x100000, &sector).unwrap();
+    assert_eq!(parsed, partition);
+    assert_eq!(parsed.find("Tetris.gb").unwrap().crc, 0x1234_5678);
+}
+
+#[test]
+fn rejects_damaged_directories() {
+    let mut partition = RomPartition::empty(0x20000);
+    partition.insert(rom("A.GB", 0x1000, 0x10)).unwrap();
+
+    let mut sector = partition.encode();
+    sector[4] = 9;
+    assert_eq!(
+        RomPartition::parse(0x20000, &sector),
+        Err(PartitionError::UnsupportedVersion(9))
+    );
+
+    let mut sector = partition.encode();
+    sector[6] = 200;
+    assert_eq!(
+        RomPartition::parse(0x20000, &sector),
+        Err(PartitionError::TooManyRoms(200))
+    );
+
+    assert_eq!(
+        RomPartition::parse(0x20000, &[0; SECTOR_SIZE]),
+        Err(PartitionError::BadMagic)
+    );
+    // The entry is checked against the partition it is read from.
+    assert_eq!(
+        RomPartition::parse(0x1000, &partition.encode()),
+        Err(PartitionError::OutOfBounds("A.GB".to_string()))
+    );
+}
+
+#[test]
+fn insert_rejects_roms_outside_the_partition() {
+    let mut partition = RomPartition::empty(0x20000);
+    let out_of_bounds = |name: &str| Err(PartitionError::OutOfBounds(name.to_string()));
+    // Inside the directory sector.
+    assert_eq!(partition.insert(rom("A.GB", 0, 4)), out_of_bounds("A.GB"));
+    // Not on a sector boundary.
+    assert_eq!(
+        partition.insert(rom("A.GB", 0x1800, 4)),
+        out_of_bounds("A.GB")
+    );
+    // Past the end.
+    assert_eq!(
+        partition.insert(rom("A.GB", 0x1F000, 0x2000)),
+        out_of_bounds("A.GB")
+    );
+    assert_eq!(
+        partition.insert(rom("A.GB", 0x1000, u32::MAX)),
+        out_of_bounds("A.GB")
+    );
+    assert_eq!(
+        partition.insert(rom("A.GB", 0x1000, 0)),
+        Err(PartitionError::EmptyRom)
+    );
+    assert!(partition.roms().is_empty());
+}
+
+#[test]
+fn insert_rejects_overlaps_but_replaces_by_name() {
+    let mut partition = RomPartition::empty(0x20000);
+    partition.insert(rom("A.GB", 0x1000, 0x2000)).unwrap();
+    assert_eq!(
+        partition.insert(rom("B.GB", 0x2000, 0x10)),
+        Err(PartitionError::Overlap("B.GB".to_string()))
+    );
+    // A new copy of the same ROM may overlap the one it replaces.
+    partition.insert(rom("a.gb", 0x2000, 0x10)).unwrap();
+    assert_eq!(partition.roms().len(), 1);
+    assert_eq!(partition.roms()[0].offset, 0x2000);
+}
+
+#[test]
+fn allocate_finds_the_first_gap() {
+    let mut partition = RomPartition::empty(0x40000);
+    assert_eq!(partition.allocate(0x8000), Ok(0x1000));
+    partition.insert(rom("A.GB", 0x1000, 0x8000)).unwrap();
+    partition.insert(rom("B.GB", 0x9000, 0x4001)).unwrap();
+    // B ends inside a sector, so the next ROM starts on the one after.
+    assert_eq!(partition.allocate(0x10), Ok(0xE000));
+
+    partition.remove("A.GB").unwrap();
+    assert_eq!(partition.allocate(0x8000), Ok(0x1000));
+    assert_eq!(partition.allocate(0x8001), Ok(0xE000));
+    assert_eq!(partition.allocate(0x32000), Ok(0xE000));
+    assert_eq!(
+        partition.allocate(0x32001),
+        Err(PartitionError::NoSpace(0x32001))
+    );
+    assert_eq!(partition.allocate(0), Err(PartitionError::EmptyRom));
+    assert_eq!(
+        partition.remove("A.GB"),
+        Err(PartitionError::NotFound("A.GB".to_string()))
+    );
+}
+
+#[test]
+fn names_must_be_8_3_rom_files() {
+    assert_eq!(normalize_name("Red.gbc"), Ok("RED.GBC".to_string()));
+    for name in ["toolongname.gb", "game.zip", "a b.gb", ".gb", "game.gbcc"] {
+        assert_eq!(
+            normalize_name(name),
+            Err(PartitionError::BadName(name.to_string()))
+        );
+    }
+}
+
+#[test]
+fn mapped_roms_are_listed_and_read() {
+    let mut partition = RomPartition::empty(0x4000);
+    partition.insert(rom("X.GB", 0x1000, 0x200)).unwrap();
+    let mut data = vec![0xFF; 0x4000];
+    data[0x1000..0x1200].fill(7);
+    let mut mapped = MappedRoms {
+        partition: &partition,
+        data: &data,
+    };
+    let mut names = Vec::new();
+    mapped
+        .for_each_file(&mut |name| names.push(name.to_string()))
+        .unwrap();
+    assert_eq!(names, ["X.GB"]);
+    let mut buffer = [0; 0x300];
+    assert_eq!(mapped.read_start("x.gb", &mut buffer), 0x200);
+    assert!(buffer[..0x200].iter().all(|byte| *byte == 7));
+    assert_eq!(mapped.read_start("y.gb", &mut buffer), 0);
+    assert_eq!(mapped.rom("X.GB").map(<[u8]>::len), Some(0x200));
+}
+
+#[test]
+fn reads_an_image_packed_by_gbroms_py() {
+    let partition = RomPartition::parse(0x400000, &PACKED_IMAGE[..SECTOR_SIZE]).unwrap();
+    let names: Vec<_> = partition
+        .roms()
+        .iter()
+        .map(|rom| rom.name.as_str())
+        .collect();
+    assert_eq!(names, ["TETRIS.GB", "ZELDA.GBC"]);
+
+    let expected = [fixture_rom(0x1800, 1), fixture_rom(0x300, 2)];
+    for (rom, expected) in partition.roms().iter().zip(&expected) {
+        assert_eq!(rom.offset as usize % SECTOR_SIZE, 0);
+        assert_eq!(rom.crc, crc32(expected));
+        assert_eq!(
+            &PACKED_IMAGE[rom.offset as usize..rom.end() as usize],
+            expected.as_slice()
+        );
+    }
+    assert_eq!(partition.roms()[1].offset, 0x3000);
+    // Both write the same directory for the same ROMs.
+    assert_eq!(partition.encode(), &PACKED_IMAGE[..SECTOR_SIZE]);
+}
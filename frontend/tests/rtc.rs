@@ -0,0 +1,221 @@
+mod common;
+
+use common::MemoryStorage;
+use gb_frontend::{
+    clock::Clock,
+    gameboy::{
+        rtc::{RtcRegisters, RtcState, RtcTrailer, TRAILER_LEN},
+        save::BatterySave,
+    },
+};
+
+/// A wall clock stopped at the given Unix time.
+struct FixedClock(u64);
+
+impl Clock for FixedClock {
+    fn unix_seconds(&self) -> u64 {
+        self.0
+    }
+}
+
+fn registers(days: u16, hours: u8, minutes: u8, seconds: u8) -> RtcRegisters {
+    RtcRegisters {
+        seconds,
+        minutes,
+        hours,
+        days_low: days as u8,
+        days_high: (days >> 8) as u8 & 0x01,
+    }
+}
+
+#[test]
+fn advance_carries_into_larger_units() {
+    let mut clock = registers(0, 23, 59, 50);
+    clock.advance(15);
+    assert_eq!(clock, registers(1, 0, 0, 5));
+    clock.advance(3 * 86_400 + 2 * 3600 + 61);
+    assert_eq!(clock, registers(4, 2, 1, 6));
+}
+
+#[test]
+fn advance_sets_day_counter_bit_8() {
+    let mut clock = registers(255, 23, 59, 59);
+    clock.advance(1);
+    assert_eq!(clock, registers(256, 0, 0, 0));
+    assert_eq!(clock.days_high, 0x01);
+    assert_eq!(clock.days(), 256);
+}
+
+#[test]
+fn day_counter_overflow_sets_the_carry() {
+    let mut clock = registers(511, 23, 59, 59);
+    clock.advance(2);
+    assert_eq!(clock.days(), 0);
+    assert_eq!((clock.hours, clock.minutes, clock.seconds), (0, 0, 1));
+    assert_eq!(clock.days_high, 0x80);
+    // The carry stays until the game clears it.
+    clock.advance(86_400 * 300);
+    assert_eq!(clock.days(), 300);
+    assert_eq!(clock.days_high, 0x81);
+}
+
+#[test]
+fn halted_clock_does_not_move() {
+    let mut clock = registers(3, 4, 5, 6);
+    clock.days_high |= 0x40;
+    let halted = clock;
+    assert!(clock.halted());
+    clock.advance(100_000);
+    assert_eq!(clock, halted);
+}
+
+#[test]
+fn out_of_range_values_are_folded() {
+    let mut clock = registers(0, 0, 0, 70);
+    clock.advance(1);
+    assert_eq!(clock, registers(0, 0, 1, 11));
+}
+
+#[test]
+fn trailer_round_trips() {
+    let trailer = RtcTrailer {
+        current: registers(300, 12, 34, 56),
+        latched: registers(299, 1, 2, 3),
+        saved_at: 1_700_000_000,
+    };
+    let data = trailer.encode();
+    assert_eq!(data.len(), TRAILER_LEN);
+    // Every register is a 32-bit little-endian word.
+    assert_eq!(data[..8], [56, 0, 0, 0, 34, 0, 0, 0]);
+    assert_eq!(data[16..20], [0x01, 0, 0, 0]);
+    assert_eq!(data[40..], 1_700_000_000u64.to_le_bytes());
+    assert_eq!(RtcTrailer::decode(&data), Some(trailer));
+}
+
+#[test]
+fn short_trailer_has_a_32_bit_timestamp() {
+    let trailer = RtcTrailer {
+        current: registers(1, 2, 3, 4),
+        latched: registers(1, 2, 3, 0),
+        saved_at: 1_000_000,
+    };
+    assert_eq!(RtcTrailer::decode(&trailer.encode()[..44]), Some(trailer));
+    assert_eq!(RtcTrailer::decode(&trailer.encode()[..40]), None);
+    assert_eq!(RtcTrailer::decode(&[]), None);
+}
+
+#[test]
+fn resume_advances_only_the_current_registers() {
+    let trailer = RtcTrailer {
+        current: registers(0, 0, 0, 0),
+        latched: registers(0, 0, 0, 0),
+        saved_at: 1_000,
+    };
+    assert_eq!(
+        trailer.resume(1_000 + 3_661),
+        RtcState {
+            current: registers(0, 1, 1, 1),
+            latched: registers(0, 0, 0, 0),
+        }
+    );
+    // A wall clock behind the save leaves the clock as it was.
+    assert_eq!(trailer.resume(10).current, trailer.current);
+}
+
+#[test]
+fn battery_save_keeps_the_clock_with_the_ram() {
+    let mut storage = MemoryStorage::default();
+    let rtc = RtcState {
+        current: registers(511, 23, 59, 50),
+        latched: registers(511, 23, 58, 0),
+    };
+    let mut save = BatterySave::new("PKMN.GBC", 4);
+    save.flush(&mut storage, &[1, 2, 3, 4], Some(&rtc), &FixedClock(1_000))
+        .unwrap();
+    let data = storage.file("PKMN.SAV").unwrap();
+    assert_eq!(data.len(), 4 + TRAILER_LEN);
+    assert_eq!(data[..4], [1, 2, 3, 4]);
+
+    let mut ram = [0; 4];
+    let mut loaded = RtcState::default();
+    let mut save = BatterySave::new("PKMN.GBC", 4);
+    assert_eq!(
+        save.load(
+            &mut storage,
+            &mut ram,
+            Some(&mut loaded),
+            &FixedClock(1_015)
+        ),
+        Ok(true)
+    );
+    assert_eq!(ram, [1, 2, 3, 4]);
+    assert_eq!(loaded.latched, rtc.latched);
+    assert_eq!(loaded.current.days(), 0);
+    assert_eq!(loaded.current.days_high, 0x80);
+    assert_eq!(
+        (
+            loaded.current.hours,
+            loaded.current.minutes,
+            loaded.current.seconds
+        ),
+        (0, 0, 5)
+    );
+}
+
+#[test]
+fn save_without_a_trailer_leaves_the_clock_alone() {
+    let mut storage = MemoryStorage::default().with_file("PKMN.SAV", &[5, 6, 7, 8]);
+    let mut ram = [0; 4];
+    let mut rtc = RtcState::default();
+    let mut save = BatterySave::new("PKMN.GBC", 4);
+    assert_eq!(
+        save.load(&mut storage, &mut ram, Some(&mut rtc), &FixedClock(1)),
+        Ok(true)
+    );
+    assert_eq!(ram, [5, 6, 7, 8]);
+    assert_eq!(rtc, RtcState::default());
+}
+
+#[test]
+fn battery_save_is_written_when_only_the_clock_changed() {
+    let mut storage = MemoryStorage::default();
+    let mut rtc = RtcState {
+        current: registers(1, 2, 3, 4),
+        latched: registers(1, 2, 3, 0),
+    };
+    let ram = [1, 2, 3, 4];
+    let mut save = BatterySave::new("PKMN.GBC", 4);
+    save.flush(&mut storage, &ram, Some(&rtc), &FixedClock(1_000))
+        .unwrap();
+
+    // Nothing changed, nothing is written.
+    storage.files.clear();
+    save.flush(&mut storage, &ram, Some(&rtc), &FixedClock(1_001))
+        .unwrap();
+    assert_eq!(storage.file("PKMN.SAV"), None);
+
+    // The game set its clock but left RAM alone.
+    rtc.current = registers(9, 8, 7, 6);
+    save.flush(&mut storage, &ram, Some(&rtc), &FixedClock(1_002))
+        .unwrap();
+    let data = storage.file("PKMN.SAV").unwrap();
+    let trailer = RtcTrailer::decode(&data[4..]).unwrap();
+    assert_eq!(trailer.current, rtc.current);
+    assert_eq!(trailer.saved_at, 1_002);
+
+    // Loading counts as written: the clock read back needs no flush.
+    let mut loaded = RtcState::default();
+    let mut save = BatterySave::new("PKMN.GBC", 4);
+    let mut ram = [0; 4];
+    save.load(
+        &mut storage,
+        &mut ram,
+        Some(&mut loaded),
+        &FixedClock(1_002),
+    )
+    .unwrap();
+    storage.files.clear();
+    save.flush(&mut storage, &ram, Some(&loaded), &FixedClock(1_003))
+        .unwrap();
+    assert_eq!(storage.file("PKMN.SAV"), None);
+}
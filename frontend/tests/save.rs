@@ -0,0 +1,66 @@
+mod common;
+
+use common::MemoryStorage;
+use gb_frontend::{
+    clock::Clock,
+    gameboy::{rtc::RtcState, save::BatterySave},
+};
+
+struct FixedClock(u64);
+
+impl Clock for FixedClock {
+    fn unix_seconds(&self) -> u64 {
+        self.0
+    }
+}
+
+#[test]
+fn short_save_zeroes_the_rest_of_ram_with_or_without_a_clock() {
+    for with_rtc in [false, true] {
+        let mut storage = MemoryStorage::default().with_file("GAME.SAV", &[1, 2, 3]);
+        let mut ram = [0xFF; 6];
+        let mut rtc = RtcState::default();
+        let mut save = BatterySave::new("GAME.GB", 6);
+        let loaded = save.load(
+            &mut storage,
+            &mut ram,
+            with_rtc.then_some(&mut rtc),
+            &FixedClock(0),
+        );
+        assert_eq!(loaded, Ok(true));
+        assert_eq!(ram, [1, 2, 3, 0, 0, 0], "with clock: {}", with_rtc);
+    }
+}
+
+#[test]
+fn missing_save_leaves_ram_alone_with_or_without_a_clock() {
+    for with_rtc in [false, true] {
+        let mut ram = [0xFF; 6];
+        let mut rtc = RtcState::default();
+        let mut save = BatterySave::new("GAME.GB", 6);
+        let loaded = save.load(
+            &mut MemoryStorage::default(),
+            &mut ram,
+            with_rtc.then_some(&mut rtc),
+            &FixedClock(0),
+        );
+        assert_eq!(loaded, Ok(false));
+        assert_eq!(ram, [0xFF; 6], "with clock: {}", with_rtc);
+    }
+}
+
+#[test]
+fn only_the_declared_ram_size_is_saved() {
+    let mut storage = MemoryStorage::default();
+    let mut save = BatterySave::new("GAME.GB", 4);
+    save.flush(&mut storage, &[1, 2, 3, 4, 5, 6], None, &FixedClock(0))
+        .unwrap();
+    assert_eq!(storage.file("GAME.SAV"), Some(&[1, 2, 3, 4][..]));
+
+    let mut ram = [9; 6];
+    assert_eq!(
+        save.load(&mut storage, &mut ram, None, &FixedClock(0)),
+        Ok(true)
+    );
+    assert_eq!(ram, [1, 2, 3, 4, 9, 9]);
+}
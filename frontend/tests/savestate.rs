@@ -0,0 +1,165 @@
+mod common;
+
+use common::MemoryStorage;
+use gb_frontend::gameboy::savestate::{decode, encode, SaveStateError, SaveStates, SLOT_COUNT};
+
+const ROM_CHECKSUM: u32 = 0x1234_5678;
+
+/// Stands in for `GameBoy::save_state`, which needs the emulator core.
+fn snapshot() -> Vec<u8> {
+    (0..=255u8).cycle().take(3000).collect()
+}
+
+/// A deterministic machine in place of the emulator, whose whole state is
+/// its snapshot. The real core's snapshot is not covered by these tests.
+#[derive(Debug, Clone, PartialEq)]
+struct FakeMachine {
+    counter: u32,
+    ram: Vec<u8>,
+}
+
+impl FakeMachine {
+    fn new() -> Self {
+        Self {
+            counter: 1,
+            ram: vec![0; 0x2000],
+        }
+    }
+
+    fn step(&mut self) {
+        self.counter = self
+            .counter
+            .wrapping_mul(1_103_515_245)
+            .wrapping_add(12_345);
+        let index = self.counter as usize % self.ram.len();
+        self.ram[index] = self.ram[index].wrapping_add(self.counter as u8);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut snapshot = self.counter.to_le_bytes().to_vec();
+        snapshot.extend_from_slice(&self.ram);
+        snapshot
+    }
+
+    fn load_state(&mut self, snapshot: &[u8]) {
+        self.counter = u32::from_le_bytes(snapshot[..4].try_into().unwrap());
+        self.ram = snapshot[4..].to_vec();
+    }
+}
+
+#[test]
+fn encode_then_decode_returns_the_snapshot() {
+    let snapshot = snapshot();
+    let data = encode(ROM_CHECKSUM, &snapshot);
+    assert_eq!(&data[0..4], b"GBSS");
+    assert_eq!(data.len(), 20 + snapshot.len());
+    assert_eq!(decode(ROM_CHECKSUM, &data), Ok(snapshot.as_slice()));
+}
+
+#[test]
+fn save_then_load_returns_the_snapshot() {
+    let mut storage = MemoryStorage::default();
+    let mut states = SaveStates::new("TETRIS.GB", ROM_CHECKSUM);
+    states.next_slot();
+    states.save(&mut storage, &snapshot()).unwrap();
+    assert!(storage.file("TETRIS.SS1").is_some());
+    assert_eq!(states.load(&mut storage), Ok(snapshot()));
+
+    states.save_auto(&mut storage, b"auto").unwrap();
+    assert!(storage.file("TETRIS.SSA").is_some());
+    assert_eq!(states.load_auto(&mut storage), Ok(b"auto".to_vec()));
+}
+
+#[test]
+fn empty_slot_is_missing() {
+    let mut storage = MemoryStorage::default();
+    let states = SaveStates::new("TETRIS.GB", ROM_CHECKSUM);
+    assert_eq!(states.load(&mut storage), Err(SaveStateError::Missing));
+}
+
+#[test]
+fn slots_wrap_around() {
+    let mut states = SaveStates::new("TETRIS.GB", ROM_CHECKSUM);
+    states.previous_slot();
+    assert_eq!(states.slot(), SLOT_COUNT - 1);
+    assert_eq!(states.file_name(), "TETRIS.SS9");
+    states.next_slot();
+    assert_eq!(states.slot(), 0);
+}
+
+#[test]
+fn rejects_another_rom() {
+    let data = encode(ROM_CHECKSUM, &snapshot());
+    assert_eq!(
+        decode(0xDEAD_BEEF, &data),
+        Err(SaveStateError::RomMismatch {
+            expected: 0xDEAD_BEEF,
+            found: ROM_CHECKSUM,
+        })
+    );
+}
+
+#[test]
+fn rejects_damaged_files() {
+    let data = encode(ROM_CHECKSUM, &snapshot());
+
+    let mut corrupted = data.clone();
+    corrupted[100] ^= 0x01;
+    assert_eq!(
+        decode(ROM_CHECKSUM, &corrupted),
+        Err(SaveStateError::Corrupted)
+    );
+
+    let mut bad_magic = data.clone();
+    bad_magic[0] = b'X';
+    assert_eq!(
+        decode(ROM_CHECKSUM, &bad_magic),
+        Err(SaveStateError::BadMagic)
+    );
+
+    let mut newer = data.clone();
+    newer[4] = 2;
+    assert_eq!(
+        decode(ROM_CHECKSUM, &newer),
+        Err(SaveStateError::UnsupportedVersion(2))
+    );
+
+    assert_eq!(
+        decode(ROM_CHECKSUM, &data[..10]),
+        Err(SaveStateError::Truncated)
+    );
+    assert_eq!(
+        decode(ROM_CHECKSUM, &data[..data.len() - 1]),
+        Err(SaveStateError::Truncated)
+    );
+}
+
+#[test]
+fn huge_snapshot_length_is_truncated() {
+    let mut data = encode(ROM_CHECKSUM, b"state");
+    data[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(decode(ROM_CHECKSUM, &data), Err(SaveStateError::Truncated));
+}
+
+#[test]
+fn restored_machine_runs_on_like_the_original() {
+    let mut storage = MemoryStorage::default();
+    let states = SaveStates::new("TETRIS.GB", ROM_CHECKSUM);
+    let mut machine = FakeMachine::new();
+    for _ in 0..1000 {
+        machine.step();
+    }
+    states.save(&mut storage, &machine.save_state()).unwrap();
+    let saved = machine.clone();
+    for _ in 0..1000 {
+        machine.step();
+    }
+
+    let mut restored = FakeMachine::new();
+    restored.load_state(&states.load(&mut storage).unwrap());
+    assert_eq!(restored, saved);
+    for _ in 0..1000 {
+        restored.step();
+    }
+    assert_eq!(restored, machine);
+}
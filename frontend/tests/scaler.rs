@@ -0,0 +1,504 @@
+use gb_frontend::{
+    config::{Filter, Scaling},
+    presenter::{Layout, Rect, GB_HEIGHT, GB_WIDTH},
+    scaler::ScreenScaler,
+};
+
+/// A frame where neighbouring pixels differ, so a pixel taken from the wrong
+/// place shows up in the comparison.
+fn frame() -> Vec<u16> {
+    (0..GB_WIDTH * GB_HEIGHT)
+        .map(|i| ((i as u32).wrapping_mul(2_654_435_761) >> 16) as u16)
+        .collect()
+}
+
+/// The ratio table of the const-generic scaler: source pixel `i` ends before
+/// output pixel `ceil(ratio * (i + 1))`.
+fn generate_scaling_ratio(ratio: f32, size: usize) -> Vec<usize> {
+    (0..size)
+        .map(|i| (ratio * (i + 1) as f32).ceil() as usize)
+        .collect()
+}
+
+/// What the const-generic scaler drew for a whole frame scaled to
+/// `out_width` x `out_height`.
+fn ratio_scaled(frame: &[u16], out_width: usize, out_height: usize) -> Vec<u16> {
+    let columns = generate_scaling_ratio(out_width as f32 / GB_WIDTH as f32, GB_WIDTH);
+    let rows = generate_scaling_ratio(out_height as f32 / GB_HEIGHT as f32, GB_HEIGHT);
+    let mut output = Vec::new();
+    let mut line = vec![0; out_width];
+    let mut output_y = 0;
+    for (input_y, row_end) in rows.iter().enumerate() {
+        let mut output_x = 0;
+        for (input_x, column_end) in columns.iter().enumerate() {
+            line[output_x..*column_end].fill(frame[input_y * GB_WIDTH + input_x]);
+            output_x = *column_end;
+        }
+        for _ in output_y..*row_end {
+            output.extend_from_slice(&line);
+        }
+        output_y = *row_end;
+    }
+    output
+}
+
+/// What the integer scaler of the `Native` and `Double` modes drew: every
+/// pixel of `source` repeated `factor` times in both directions.
+fn integer_scaled(frame: &[u16], source: Rect, factor: usize) -> Vec<u16> {
+    let mut output = Vec::new();
+    for y in source.y..source.y + source.height {
+        let mut line = Vec::new();
+        for x in source.x..source.x + source.width {
+            let pixel = frame[y as usize * GB_WIDTH + x as usize];
+            line.extend(std::iter::repeat(pixel).take(factor));
+        }
+        for _ in 0..factor {
+            output.extend_from_slice(&line);
+        }
+    }
+    output
+}
+
+fn scaled(frame: &[u16], source: Rect, out_width: u16, out_height: u16) -> Vec<u16> {
+    let mut scaler = ScreenScaler::new(GB_WIDTH as u16, source, out_width, out_height);
+    let mut input = frame.iter().copied();
+    let output: Vec<u16> = scaler.scale_iterator(&mut input).collect();
+    // The emulator has to finish the frame whatever the scaler shows of it.
+    assert_eq!(input.next(), None);
+    output
+}
+
+fn full_frame() -> Rect {
+    Rect {
+        width: GB_WIDTH as u16,
+        height: GB_HEIGHT as u16,
+        ..Rect::default()
+    }
+}
+
+#[test]
+fn nearest_matches_the_ratio_tables() {
+    let frame = frame();
+    // Stretch and fit on the landscape panel, fit on the portrait one.
+    for (width, height) in [(320, 240), (266, 240), (240, 216)] {
+        assert_eq!(
+            scaled(&frame, full_frame(), width, height),
+            ratio_scaled(&frame, width as usize, height as usize),
+            "{}x{}",
+            width,
+            height
+        );
+    }
+}
+
+#[test]
+fn portrait_stretch_only_differs_where_the_ratio_table_rounded_up() {
+    let frame = frame();
+    let layout = Layout::new(Scaling::Stretch, 240, 320);
+    assert_eq!((layout.target.width, layout.target.height), (240, 320));
+    let output = scaled(&frame, layout.source, 240, 320);
+    let expected = ratio_scaled(&frame, 240, 320);
+    // 320 / 144 is not exact in f32, so where a source line ends exactly on
+    // an output line the old table started the next one a line late.
+    let lines: Vec<_> = output.chunks(240).collect();
+    let old_lines: Vec<_> = expected.chunks(240).collect();
+    let mut late_rows = Vec::new();
+    for row in 0..lines.len() {
+        if lines[row] != old_lines[row] {
+            assert_eq!(row * GB_HEIGHT % 320, 0, "row {}", row);
+            assert_eq!(lines[row], old_lines[row + 1], "row {}", row);
+            late_rows.push(row);
+        }
+    }
+    assert!(!late_rows.is_empty());
+}
+
+#[test]
+fn cropped_integer_layouts_match_the_integer_scaler() {
+    let frame = frame();
+    let cases = [
+        (Scaling::Double, 320, 240, 2),
+        (Scaling::Double, 240, 320, 2),
+        (Scaling::Native, 320, 240, 1),
+        (Scaling::Native, 128, 160, 1),
+    ];
+    for (scaling, panel_width, panel_height, factor) in cases {
+        let layout = Layout::new(scaling, panel_width, panel_height);
+        let target = layout.target;
+        assert_eq!(
+            scaled(&frame, layout.source, target.width, target.height),
+            integer_scaled(&frame, layout.source, factor),
+            "{:?} on {}x{}",
+            scaling,
+            panel_width,
+            panel_height
+        );
+    }
+}
+
+#[test]
+fn scaler_is_reused_across_frames() {
+    let frame = frame();
+    let layout = Layout::new(Scaling::Double, 240, 320);
+    let target = layout.target;
+    let mut scaler = ScreenScaler::new(GB_WIDTH as u16, layout.source, target.width, target.height);
+    let expected = integer_scaled(&frame, layout.source, 2);
+    for _ in 0..2 {
+        let output: Vec<u16> = scaler.scale_iterator(frame.iter().copied()).collect();
+        assert_eq!(output, expected);
+    }
+    // A frame cut short ends the output early and leaves nothing behind.
+    let partial = scaler
+        .scale_iterator(frame[..GB_WIDTH * 2].iter().copied())
+        .count();
+    assert!(partial < expected.len());
+    let output: Vec<u16> = scaler.scale_iterator(frame.iter().copied()).collect();
+    assert_eq!(output, expected);
+}
+
+/// A frame of three colours, so neighbouring pixels are often equal and the
+/// Scale2x rules fire in every direction.
+fn three_colour_frame() -> Vec<u16> {
+    frame()
+        .iter()
+        .map(|pixel| [0x0000, 0xFFFF, 0x07E0][*pixel as usize % 3])
+        .collect()
+}
+
+fn filtered(
+    frame: &[u16],
+    in_width: usize,
+    source: Rect,
+    filter: Filter,
+    out_width: u16,
+    out_height: u16,
+) -> Vec<u16> {
+    let mut scaler =
+        ScreenScaler::new(in_width as u16, source, out_width, out_height).with_filter(filter);
+    let mut input = frame.iter().copied();
+    let output: Vec<u16> = scaler.scale_iterator(&mut input).collect();
+    assert_eq!(input.next(), None);
+    output
+}
+
+fn channels(pixel: u16) -> [u32; 3] {
+    [
+        (pixel >> 11) as u32,
+        (pixel >> 5 & 0x3F) as u32,
+        (pixel & 0x1F) as u32,
+    ]
+}
+
+fn rgb565([red, green, blue]: [u32; 3]) -> u16 {
+    (red << 11 | green << 5 | blue) as u16
+}
+
+/// `from` moved `weight` 32nds of the way to `to`, one channel at a time and
+/// rounded to nearest.
+fn mix(from: [u32; 3], to: [u32; 3], weight: u32) -> [u32; 3] {
+    [0, 1, 2].map(|channel| (from[channel] * (32 - weight) + to[channel] * weight + 16) >> 5)
+}
+
+/// The source pixel at `position` and the weight of the next one in 32nds,
+/// kept inside a source `in_size` pixels long.
+fn sample_at(position: f64, in_size: usize) -> (usize, u32) {
+    let position = (position.max(0.0) * 32.0).round() as usize;
+    if position / 32 + 1 >= in_size {
+        (in_size - 1, 0)
+    } else {
+        (position / 32, (position % 32) as u32)
+    }
+}
+
+/// Bilinear: the centre of output pixel `out`, in source pixels.
+fn bilinear_sample(out: usize, in_size: usize, out_size: usize) -> (usize, u32) {
+    sample_at(
+        (out as f64 + 0.5) * in_size as f64 / out_size as f64 - 0.5,
+        in_size,
+    )
+}
+
+/// Sharp bilinear: bilinear filtering of the picture enlarged by the largest
+/// integer factor that fits. Two enlarged pixels only blend where they come
+/// from different source pixels.
+fn sharp_bilinear_sample(out: usize, in_size: usize, out_size: usize) -> (usize, u32) {
+    let scale = (out_size / in_size).max(1) as i64;
+    let enlarged = (out as f64 + 0.5) * (in_size as i64 * scale) as f64 / out_size as f64 - 0.5;
+    let pixel = enlarged.floor() as i64;
+    let position = if (pixel + 1) % scale == 0 {
+        pixel.div_euclid(scale) as f64 + (enlarged - pixel as f64)
+    } else {
+        pixel.div_euclid(scale) as f64
+    };
+    sample_at(position, in_size)
+}
+
+/// The `source` part of `frame` filtered channel by channel, with the
+/// samples of the bilinear filter being checked.
+fn bilinear_reference(
+    frame: &[u16],
+    in_width: usize,
+    source: Rect,
+    sample: fn(usize, usize, usize) -> (usize, u32),
+    out_width: usize,
+    out_height: usize,
+) -> Vec<u16> {
+    let (width, height) = (source.width as usize, source.height as usize);
+    let pixel = |x: usize, y: usize| {
+        let y = source.y as usize + y.min(height - 1);
+        channels(frame[y * in_width + source.x as usize + x.min(width - 1)])
+    };
+    let mut output = Vec::new();
+    for out_y in 0..out_height {
+        let (y, row_weight) = sample(out_y, height, out_height);
+        for out_x in 0..out_width {
+            let (x, column_weight) = sample(out_x, width, out_width);
+            let upper = mix(pixel(x, y), pixel(x + 1, y), column_weight);
+            let lower = mix(pixel(x, y + 1), pixel(x + 1, y + 1), column_weight);
+            output.push(rgb565(mix(upper, lower, row_weight)));
+        }
+    }
+    output
+}
+
+/// Scale2x as published: every pixel `P` becomes four from its neighbours
+/// `A` above, `B` right, `C` left and `D` below, repeating the edge pixels
+/// outside the source. The doubled picture is then scaled by nearest
+/// neighbour.
+fn scale2x_reference(
+    frame: &[u16],
+    in_width: usize,
+    source: Rect,
+    out_width: usize,
+    out_height: usize,
+) -> Vec<u16> {
+    let (width, height) = (source.width as isize, source.height as isize);
+    let pixel = |x: isize, y: isize| {
+        let y = source.y as isize + y.clamp(0, height - 1);
+        frame[y as usize * in_width + (source.x as isize + x.clamp(0, width - 1)) as usize]
+    };
+    let doubled_width = 2 * width as usize;
+    let mut doubled = vec![0; 4 * (width * height) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let (p, a, b, c, d) = (
+                pixel(x, y),
+                pixel(x, y - 1),
+                pixel(x + 1, y),
+                pixel(x - 1, y),
+                pixel(x, y + 1),
+            );
+            let top_left = 2 * y as usize * doubled_width + 2 * x as usize;
+            let bottom_left = top_left + doubled_width;
+            doubled[top_left] = if c == a && c != d && a != b { a } else { p };
+            doubled[top_left + 1] = if a == b && a != c && b != d { b } else { p };
+            doubled[bottom_left] = if d == c && d != b && c != a { c } else { p };
+            doubled[bottom_left + 1] = if b == d && b != a && d != c { d } else { p };
+        }
+    }
+    let doubled_height = 2 * height as usize;
+    (0..out_height)
+        .flat_map(|out_y| {
+            let row = out_y * doubled_height / out_height * doubled_width;
+            let doubled = &doubled;
+            (0..out_width).map(move |out_x| doubled[row + out_x * doubled_width / out_width])
+        })
+        .collect()
+}
+
+/// Output sizes below, at and above whole multiples of the frame, with
+/// different factors across and down.
+const FILTER_SIZES: [(u16, u16); 5] = [(320, 240), (266, 240), (240, 216), (320, 288), (480, 432)];
+
+#[test]
+fn bilinear_matches_a_per_channel_reference() {
+    let frame = frame();
+    for (width, height) in FILTER_SIZES {
+        assert_eq!(
+            filtered(
+                &frame,
+                GB_WIDTH,
+                full_frame(),
+                Filter::Bilinear,
+                width,
+                height
+            ),
+            bilinear_reference(
+                &frame,
+                GB_WIDTH,
+                full_frame(),
+                bilinear_sample,
+                width as usize,
+                height as usize
+            ),
+            "{}x{}",
+            width,
+            height
+        );
+    }
+}
+
+#[test]
+fn sharp_bilinear_matches_bilinear_of_the_enlarged_picture() {
+    let frame = frame();
+    for (width, height) in FILTER_SIZES {
+        assert_eq!(
+            filtered(
+                &frame,
+                GB_WIDTH,
+                full_frame(),
+                Filter::SharpBilinear,
+                width,
+                height
+            ),
+            bilinear_reference(
+                &frame,
+                GB_WIDTH,
+                full_frame(),
+                sharp_bilinear_sample,
+                width as usize,
+                height as usize
+            ),
+            "{}x{}",
+            width,
+            height
+        );
+    }
+}
+
+#[test]
+fn sharp_bilinear_only_blends_at_pixel_edges() {
+    // At a whole multiple it is nearest neighbour.
+    for out in 0..30 {
+        assert_eq!(sharp_bilinear_sample(out, 10, 30), (out / 3, 0), "{}", out);
+    }
+    // Just above it every source pixel is still shown unblended at least
+    // twice, and only the output pixels between two of them blend.
+    let samples: Vec<_> = (0..32)
+        .map(|out| sharp_bilinear_sample(out, 10, 32))
+        .collect();
+    for index in 0..10 {
+        let crisp = samples
+            .iter()
+            .filter(|sample| **sample == (index, 0))
+            .count();
+        assert!(crisp >= 2, "{}", index);
+    }
+    assert!(samples.iter().any(|(_, weight)| *weight != 0));
+}
+
+#[test]
+fn scale2x_matches_the_published_rules() {
+    let frame = three_colour_frame();
+    for (width, height) in FILTER_SIZES {
+        let output = filtered(
+            &frame,
+            GB_WIDTH,
+            full_frame(),
+            Filter::Scale2x,
+            width,
+            height,
+        );
+        let expected = scale2x_reference(
+            &frame,
+            GB_WIDTH,
+            full_frame(),
+            width as usize,
+            height as usize,
+        );
+        assert_eq!(output, expected, "{}x{}", width, height);
+    }
+    // The three colours are not there just by chance: the rules changed
+    // pixels compared to plain doubling.
+    let doubled = filtered(&frame, GB_WIDTH, full_frame(), Filter::Nearest, 320, 288);
+    let scale2x = filtered(&frame, GB_WIDTH, full_frame(), Filter::Scale2x, 320, 288);
+    assert!(doubled.iter().zip(&scale2x).any(|(a, b)| a != b));
+}
+
+#[test]
+fn scale2x_rounds_off_a_corner_at_the_edge() {
+    const W: u16 = 0xFFFF;
+    const B: u16 = 0x0000;
+    let source = Rect {
+        width: 2,
+        height: 2,
+        ..Rect::default()
+    };
+    // Outside the picture the edge pixels repeat, so only the inner corner
+    // of the white pixel is rounded off.
+    assert_eq!(
+        filtered(&[W, B, B, B], 2, source, Filter::Scale2x, 4, 4),
+        [
+            W, W, B, B, //
+            W, B, B, B, //
+            B, B, B, B, //
+            B, B, B, B,
+        ]
+    );
+}
+
+#[test]
+fn filters_read_only_the_cropped_source() {
+    // Lines above and below the source pass through the three line history
+    // without being stored, the lines inside wrap around it.
+    let source = Rect {
+        x: 8,
+        y: 5,
+        width: 140,
+        height: 131,
+    };
+    let frame = three_colour_frame();
+    assert_eq!(
+        filtered(&frame, GB_WIDTH, source, Filter::Scale2x, 280, 262),
+        scale2x_reference(&frame, GB_WIDTH, source, 280, 262)
+    );
+    let frame = self::frame();
+    for (filter, sample) in [
+        (
+            Filter::Bilinear,
+            bilinear_sample as fn(usize, usize, usize) -> (usize, u32),
+        ),
+        (Filter::SharpBilinear, sharp_bilinear_sample),
+    ] {
+        assert_eq!(
+            filtered(&frame, GB_WIDTH, source, filter, 320, 240),
+            bilinear_reference(&frame, GB_WIDTH, source, sample, 320, 240),
+            "{:?}",
+            filter
+        );
+    }
+}
+
+#[test]
+fn filters_keep_solid_colours_exact() {
+    // Blending a colour with itself must not round any channel up or down,
+    // white included, where every channel is at its maximum.
+    for color in [0x0000, 0xFFFF, 0xF800, 0x07E0, 0x001F, 0x8410] {
+        let frame = vec![color; GB_WIDTH * GB_HEIGHT];
+        for filter in Filter::ALL {
+            let output = filtered(&frame, GB_WIDTH, full_frame(), filter, 266, 240);
+            assert!(
+                output.iter().all(|pixel| *pixel == color),
+                "{:04x} with {:?}",
+                color,
+                filter
+            );
+        }
+    }
+}
+
+#[test]
+fn bilinear_rounds_each_channel_to_nearest() {
+    // Two pixels three times across: the middle output pixel is half way
+    // between them.
+    let source = Rect {
+        width: 2,
+        height: 1,
+        ..Rect::default()
+    };
+    let output = filtered(&[0x0000, 0xFFFF], 2, source, Filter::Bilinear, 3, 1);
+    // 15.5 and 31.5 round up to 16 and 32.
+    assert_eq!(output, [0x0000, 0x8410, 0xFFFF]);
+    let output = filtered(&[0xFFFF, 0x0000], 2, source, Filter::Bilinear, 3, 1);
+    assert_eq!(output, [0xFFFF, 0x8410, 0x0000]);
+}
@@ -0,0 +1,78 @@
+#!/usr/bin/env python3
+"""Fills the gbroms flash partition, see "Flash ROMs" in docs/README.md.
+
+  scripts/gbroms.py pack gbroms.bin ROM...     build a partition image
+  scripts/gbroms.py upload PORT ROM...         send ROMs to a running device
+
+The image is written with `espflash write-bin 0x400000 gbroms.bin`. Uploading
+needs pyserial.
+"""
+import os
+import struct
+import sys
+import zlib
+
+SECTOR_SIZE = 0x1000
+PARTITION_SIZE = 0x400000
+MAGIC = b"GBRP"
+VERSION = 1
+
+
+def rom_name(path):
+    name = os.path.basename(path).upper()
+    stem, _, extension = name.partition(".")
+    if not stem or len(stem) > 8 or extension not in ("GB", "GBC"):
+        sys.exit(f"{path}: needs an 8.3 name ending in .gb or .gbc")
+    return name
+
+
+def pack(output, paths):
+    image = bytearray(b"\xff" * SECTOR_SIZE)
+    entries = []
+    for path in paths:
+        with open(path, "rb") as rom:
+            data = rom.read()
+        entries.append((rom_name(path), len(image), len(data), zlib.crc32(data)))
+        image += data
+        image += b"\xff" * (-len(image) % SECTOR_SIZE)
+    if len(image) > PARTITION_SIZE:
+        sys.exit(f"{len(image) // 1024} KiB does not fit the {PARTITION_SIZE // 1024} KiB partition")
+    directory = MAGIC + struct.pack("<HH8x", VERSION, len(entries))
+    for name, offset, length, crc in entries:
+        directory += struct.pack("<12sIII8x", name.encode(), offset, length, crc)
+    image[: len(directory)] = directory
+    with open(output, "wb") as out:
+        out.write(image)
+
+
+def upload(port, paths):
+    import serial
+
+    with serial.Serial(port, timeout=30) as device:
+        for path in paths:
+            with open(path, "rb") as rom:
+                data = rom.read()
+            name = rom_name(path)
+            device.write(f"upload {name} {len(data)} {zlib.crc32(data):08x}\n".encode())
+            while True:
+                line = device.readline().decode(errors="replace").strip()
+                if not line:
+                    sys.exit(f"{name}: no reply")
+                if line == "ready":
+                    break
+                if "upload" in line.lower() or "partition" in line:
+                    sys.exit(line)
+            device.write(data)
+            while True:
+                line = device.readline().decode(errors="replace").strip()
+                if not line:
+                    sys.exit(f"{name}: no reply")
+                if line.startswith("Stored") or line.startswith("Upload failed"):
+                    print(line)
+                    break
+
+
+if __name__ == "__main__":
+    if len(sys.argv) < 4 or sys.argv[1] not in ("pack", "upload"):
+        sys.exit(__doc__)
+    {"pack": pack, "upload": upload}[sys.argv[1]](sys.argv[2], sys.argv[3:])
@@ -0,0 +1,76 @@
+use alloc::string::String;
+use core::fmt;
+
+use display_interface::DisplayError;
+
+use crate::{
+    gameboy::{boot::BOOT_ROM_FILE, rom::RomError},
+    storage::StorageError,
+};
+
+/// Everything that can stop the emulator from reaching the first frame.
+#[derive(Debug)]
+pub enum BootError {
+    /// No room left for the DMA line buffers.
+    DmaBuffer,
+    Display(DisplayError),
+    /// The SD card did not answer or holds no readable FAT volume.
+    Volume(StorageError),
+    RootDir(StorageError),
+    RomList(StorageError),
+    NoRoms,
+    BootRom(StorageError),
+    /// The ROM could not be read, or its patch could not be applied.
+    Rom {
+        name: String,
+        error: RomError,
+    },
+    Save {
+        name: String,
+        error: StorageError,
+    },
+    /// The ROM built in with the `embedded-rom` feature has an unusable header.
+    #[cfg(feature = "embedded-rom")]
+    EmbeddedRom(crate::gameboy::header::HeaderError),
+}
+
+impl BootError {
+    /// Number of LED flashes used to report the error when the screen is unusable.
+    pub fn blink_code(&self) -> u8 {
+        match self {
+            BootError::DmaBuffer => 1,
+            BootError::Display(_) => 2,
+            BootError::Volume(_) => 3,
+            BootError::RootDir(_) => 4,
+            BootError::RomList(_) => 5,
+            BootError::NoRoms => 6,
+            BootError::BootRom(_) => 7,
+            BootError::Rom { .. } => 8,
+            BootError::Save { .. } => 9,
+            #[cfg(feature = "embedded-rom")]
+            BootError::EmbeddedRom(_) => 10,
+        }
+    }
+}
+
+impl fmt::Display for BootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BootError::DmaBuffer => write!(f, "Not enough memory for the display buffers"),
+            BootError::Display(error) => write!(f, "Display error: {:?}", error),
+            BootError::Volume(error) => {
+                write!(f, "Cannot open the SD card, is it inserted? ({})", error)
+            }
+            BootError::RootDir(error) => {
+                write!(f, "Cannot open the SD card root directory ({})", error)
+            }
+            BootError::RomList(error) => write!(f, "Cannot list the SD card ({})", error),
+            BootError::NoRoms => write!(f, "No .gb/.gbc/.zip/.gz files found on the SD card"),
+            BootError::BootRom(error) => write!(f, "Cannot read {} ({})", BOOT_ROM_FILE, error),
+            BootError::Rom { name, error } => write!(f, "Cannot load {} ({})", name, error),
+            BootError::Save { name, error } => write!(f, "Cannot read {} ({})", name, error),
+            #[cfg(feature = "embedded-rom")]
+            BootError::EmbeddedRom(error) => write!(f, "Cannot start the built-in ROM ({})", error),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use alloc::boxed::Box;
+use gb_core::{gameboy::GameBoy, hardware::Screen};
+
+use crate::storage::{FileStorage, StorageError};
+
+pub const BOOT_ROM_FILE: &str = "dmg_boot.bin";
+pub const BOOT_ROM_SIZE: usize = 0x100;
+
+#[derive(Debug, Clone, Default)]
+pub struct BootConfig {
+    /// Start straight at the cartridge entry point even if `dmg_boot.bin` exists.
+    pub skip_boot_rom: bool,
+}
+
+/// Reads the boot ROM unless the settings skip it. Returns `None` when the game
+/// has to start from the post-boot state instead.
+pub fn load_boot_rom<S: FileStorage>(
+    storage: &mut S,
+    config: &BootConfig,
+) -> Result<Option<Box<[u8; BOOT_ROM_SIZE]>>, StorageError> {
+    if config.skip_boot_rom {
+        log::info!("Boot ROM: skipped by settings, starting from post-boot state");
+        return Ok(None);
+    }
+    let mut data = Box::new([0u8; BOOT_ROM_SIZE]);
+    match storage.read_file(BOOT_ROM_FILE, &mut *data) {
+        Ok(read) if read == BOOT_ROM_SIZE => {
+            log::info!("Boot ROM: {}", BOOT_ROM_FILE);
+            Ok(Some(data))
+        }
+        Ok(read) => {
+            log::warn!(
+                "Boot ROM: {} is {} bytes instead of {}, starting from post-boot state",
+                BOOT_ROM_FILE,
+                read,
+                BOOT_ROM_SIZE
+            );
+            Ok(None)
+        }
+        Err(StorageError::NotFound) => {
+            log::info!(
+                "Boot ROM: {} not found, starting from post-boot state",
+                BOOT_ROM_FILE
+            );
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl CpuRegisters {
+    /// Registers as the DMG boot ROM leaves them when it jumps to 0x100. The
+    /// half-carry and carry flags are only set if the header checksum is non-zero.
+    pub fn dmg_post_boot(header_checksum: u8) -> Self {
+        Self {
+            a: 0x01,
+            f: if header_checksum == 0 { 0x80 } else { 0xB0 },
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+}
+
+/// Writable I/O registers as the DMG boot ROM leaves them, in write order: NR52
+/// comes first because the other sound registers ignore writes while the APU
+/// is off. DIV, LY and STAT are left to the emulator since writes to them do
+/// not store the value, and DMA is skipped so no transfer is started.
+pub const DMG_IO_REGISTERS: [(u16, u8); 36] = [
+    (0xFF26, 0xF1), // NR52
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF40, 0x91), // LCDC
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF47, 0xFC), // BGP
+    (0xFF4A, 0x00), // WY
+    (0xFF4B, 0x00), // WX
+    (0xFFFF, 0x00), // IE
+];
+
+/// Puts a freshly created `GameBoy` without a boot ROM into the state the
+/// boot ROM would have handed over to the cartridge.
+pub fn apply_post_boot_state<SC: Screen>(gameboy: &mut GameBoy<'_, SC>, header_checksum: u8) {
+    let registers = CpuRegisters::dmg_post_boot(header_checksum);
+    let cpu = gameboy.get_registers_mut();
+    cpu.a = registers.a;
+    cpu.f = registers.f;
+    cpu.b = registers.b;
+    cpu.c = registers.c;
+    cpu.d = registers.d;
+    cpu.e = registers.e;
+    cpu.h = registers.h;
+    cpu.l = registers.l;
+    cpu.sp = registers.sp;
+    cpu.pc = registers.pc;
+    for (address, value) in DMG_IO_REGISTERS {
+        gameboy.write_memory(address, value);
+    }
+}
@@ -0,0 +1,14 @@
+use alloc::boxed::Box;
+
+use super::boot::{BootConfig, BOOT_ROM_SIZE};
+
+// Generated by build.rs from GB_ROM and GB_BOOT_ROM: `ROM_NAME`, `ROM` and `BOOT_ROM`.
+include!(concat!(env!("OUT_DIR"), "/embedded_rom.rs"));
+
+/// The boot ROM built into the firmware, unless the settings skip it. `None`
+/// leaves it to `dmg_boot.bin` on the card, if there is one.
+pub fn boot_rom(config: &BootConfig) -> Option<Box<[u8; BOOT_ROM_SIZE]>> {
+    let data = BOOT_ROM.filter(|_| !config.skip_boot_rom)?;
+    log::info!("Boot ROM: built in");
+    Some(Box::new(*data))
+}
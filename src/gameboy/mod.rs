@@ -4,9 +4,36 @@ use display::GameboyLineBufferDisplay;
 use embedded_hal::digital::InputPin;
 use gb_core::{gameboy::GameBoy, hardware::Screen};
 
+use crate::{
+    config::{ButtonMap, GameKey},
+    ui::MenuInput,
+};
+
+pub mod boot;
 pub mod display;
+#[cfg(feature = "embedded-rom")]
+pub mod embedded;
 pub mod rom;
 
+pub use gb_frontend::gameboy::{
+    bank_cache, cheats, header, patch, resume, rom_partition, rtc, save, savestate,
+};
+
+/// Frontend shortcuts, chorded with SELECT so they stay out of the way of normal play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    /// SELECT + START
+    Menu,
+    /// SELECT + B
+    SaveState,
+    /// SELECT + A
+    LoadState,
+    /// SELECT + RIGHT
+    NextSlot,
+    /// SELECT + LEFT
+    PreviousSlot,
+}
+
 pub trait GameboyButtonHandler<'a> {
     fn handle_button_clicks<SC: Screen>(&mut self, gameboy: &mut GameBoy<'a, SC>);
 }
@@ -82,6 +109,9 @@ pub struct InputButtonMapper<'a> {
     down_button_state: bool,
     left_button_state: bool,
     right_button_state: bool,
+    menu_state: u8,
+    hotkey_state: u8,
+    buttons: ButtonMap,
 }
 
 impl<'a, 'b> GameboyButtonHandler<'b> for InputButtonMapper<'a> {
@@ -90,48 +120,48 @@ impl<'a, 'b> GameboyButtonHandler<'b> for InputButtonMapper<'a> {
         ////
         if self.b_button.is_low().unwrap() {
             if self.b_button_state == false {
-                gameboy.key_pressed(gb_core::hardware::input::Button::B);
+                gameboy.key_pressed(Self::key(self.buttons.b));
                 self.b_button_state = true;
             }
         } else {
             if self.b_button_state == true {
-                gameboy.key_released(gb_core::hardware::input::Button::B);
+                gameboy.key_released(Self::key(self.buttons.b));
                 self.b_button_state = false;
             }
         }
         ////
         if self.a_button.is_low().unwrap() {
             if self.a_button_state == false {
-                gameboy.key_pressed(gb_core::hardware::input::Button::A);
+                gameboy.key_pressed(Self::key(self.buttons.a));
                 self.a_button_state = true;
             }
         } else {
             if self.a_button_state == true {
-                gameboy.key_released(gb_core::hardware::input::Button::A);
+                gameboy.key_released(Self::key(self.buttons.a));
                 self.a_button_state = false;
             }
         }
         ////
         if self.select_button.is_low().unwrap() {
             if self.select_button_state == false {
-                gameboy.key_pressed(gb_core::hardware::input::Button::SELECT);
+                gameboy.key_pressed(Self::key(self.buttons.select));
                 self.select_button_state = true;
             }
         } else {
             if self.select_button_state == true {
-                gameboy.key_released(gb_core::hardware::input::Button::SELECT);
+                gameboy.key_released(Self::key(self.buttons.select));
                 self.select_button_state = false;
             }
         }
         /////
         if self.start_button.is_low().unwrap() {
             if self.start_button_state == false {
-                gameboy.key_pressed(gb_core::hardware::input::Button::START);
+                gameboy.key_pressed(Self::key(self.buttons.start));
                 self.start_button_state = true;
             }
         } else {
             if self.start_button_state == true {
-                gameboy.key_released(gb_core::hardware::input::Button::START);
+                gameboy.key_released(Self::key(self.buttons.start));
                 self.start_button_state = false;
             }
         }
@@ -213,6 +243,78 @@ impl<'a> InputButtonMapper<'a> {
             down_button_state: false,
             left_button_state: false,
             right_button_state: false,
+            menu_state: 0,
+            hotkey_state: 0,
+            buttons: ButtonMap::default(),
+        }
+    }
+
+    /// Sets the keys the A, B, START and SELECT buttons press in game. Menus
+    /// and hotkeys keep the physical layout.
+    pub fn set_buttons(&mut self, buttons: ButtonMap) {
+        self.buttons = buttons;
+    }
+
+    fn key(key: GameKey) -> gb_core::hardware::input::Button {
+        match key {
+            GameKey::A => gb_core::hardware::input::Button::A,
+            GameKey::B => gb_core::hardware::input::Button::B,
+            GameKey::Start => gb_core::hardware::input::Button::START,
+            GameKey::Select => gb_core::hardware::input::Button::SELECT,
+        }
+    }
+
+    /// Polls the pad for menu navigation, reporting each button once per press.
+    pub fn menu_input(&mut self) -> Option<MenuInput> {
+        let buttons = [
+            (self.up_button.is_low().unwrap(), MenuInput::Up),
+            (self.down_button.is_low().unwrap(), MenuInput::Down),
+            (self.left_button.is_low().unwrap(), MenuInput::Left),
+            (self.right_button.is_low().unwrap(), MenuInput::Right),
+            (self.a_button.is_low().unwrap(), MenuInput::Accept),
+            (self.b_button.is_low().unwrap(), MenuInput::Back),
+            (self.start_button.is_low().unwrap(), MenuInput::Start),
+        ];
+        let mut state = 0u8;
+        let mut input = None;
+        for (bit, (pressed, menu_input)) in buttons.iter().enumerate() {
+            if !*pressed {
+                continue;
+            }
+            state |= 1 << bit;
+            if self.menu_state & (1 << bit) == 0 && input.is_none() {
+                input = Some(*menu_input);
+            }
+        }
+        self.menu_state = state;
+        input
+    }
+
+    /// Polls for a SELECT chord, reporting each hotkey once per press.
+    pub fn hotkey(&mut self) -> Option<Hotkey> {
+        if self.select_button.is_high().unwrap() {
+            self.hotkey_state = 0;
+            return None;
+        }
+        let chords = [
+            (self.start_button.is_low().unwrap(), Hotkey::Menu),
+            (self.b_button.is_low().unwrap(), Hotkey::SaveState),
+            (self.a_button.is_low().unwrap(), Hotkey::LoadState),
+            (self.right_button.is_low().unwrap(), Hotkey::NextSlot),
+            (self.left_button.is_low().unwrap(), Hotkey::PreviousSlot),
+        ];
+        let mut state = 0u8;
+        let mut hotkey = None;
+        for (bit, (pressed, chord)) in chords.iter().enumerate() {
+            if !*pressed {
+                continue;
+            }
+            state |= 1 << bit;
+            if self.hotkey_state & (1 << bit) == 0 && hotkey.is_none() {
+                hotkey = Some(*chord);
+            }
         }
+        self.hotkey_state = state;
+        hotkey
     }
 }
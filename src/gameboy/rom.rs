@@ -1,11 +1,209 @@
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use esp_hal::{time::Instant, timer::Timer};
 
-use alloc::{
-    boxed::Box,
-    string::{String, ToString},
+use alloc::{boxed::Box, rc::Rc, vec};
+use core::fmt;
+use gb_core::hardware::rom::RomManager;
+
+use super::{
+    bank_cache::{BankCache, BankCacheConfig, BANK_SIZE},
+    cheats::RomPatches,
+    header::{GlobalChecksum, HEADER_END},
+    patch::{self, PatchError, RomPatch},
+};
+use crate::{
+    archive::{self, ArchiveError, ArchiveKind},
+    checksum::crc32,
+    hardware::sdcard::{storage_error, CardDirectory},
+    storage::{FileStorage, StorageError},
 };
-use const_lru::ConstLru;
+
+/// Uncompressed copy of a zipped or gzipped ROM that cannot be inflated into PSRAM.
+const INFLATED_ROM_FILE: &str = "INFLATED.TMP";
+
+/// Why `RomSource::open` could not provide the ROM.
+#[derive(Debug)]
+pub enum RomError {
+    Storage(StorageError),
+    Archive(ArchiveError),
+    /// The `.ips`/`.bps`/`.ups` next to the ROM could not be applied.
+    Patch(PatchError),
+}
+
+impl From<ArchiveError> for RomError {
+    fn from(error: ArchiveError) -> Self {
+        RomError::Archive(error)
+    }
+}
+
+impl From<StorageError> for RomError {
+    fn from(error: StorageError) -> Self {
+        RomError::Storage(error)
+    }
+}
+
+impl<E: fmt::Debug> From<embedded_sdmmc::Error<E>> for RomError {
+    fn from(error: embedded_sdmmc::Error<E>) -> Self {
+        RomError::Storage(storage_error(error))
+    }
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Storage(error) => write!(f, "{}", error),
+            RomError::Archive(error) => write!(f, "{}", error),
+            RomError::Patch(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Bank cache counters, shared with the main loop so they can be logged per frame.
+#[derive(Default)]
+pub struct BankStats {
+    hits: Cell<u32>,
+    misses: Cell<u32>,
+    evictions: Cell<u32>,
+    miss_micros: Cell<u64>,
+    max_miss_micros: Cell<u64>,
+    read_errors: Cell<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BankStatsSnapshot {
+    /// Switches to a bank that was already cached.
+    pub hits: u32,
+    pub misses: u32,
+    pub evictions: u32,
+    pub miss_micros: u64,
+    pub max_miss_micros: u64,
+    /// Bank loads that failed and were served as 0xFF.
+    pub read_errors: u32,
+}
+
+impl BankStatsSnapshot {
+    pub fn average_miss_micros(&self) -> u64 {
+        if self.misses == 0 {
+            return 0;
+        }
+        self.miss_micros / self.misses as u64
+    }
+}
+
+impl BankStats {
+    fn record_hit(&self) {
+        self.hits.set(self.hits.get().saturating_add(1));
+    }
+
+    fn record_eviction(&self) {
+        self.evictions.set(self.evictions.get().saturating_add(1));
+    }
+
+    fn record_miss(&self, micros: u64) {
+        self.misses.set(self.misses.get().saturating_add(1));
+        self.miss_micros
+            .set(self.miss_micros.get().saturating_add(micros));
+        self.max_miss_micros
+            .set(self.max_miss_micros.get().max(micros));
+    }
+
+    fn record_read_error(&self) {
+        self.read_errors
+            .set(self.read_errors.get().saturating_add(1));
+    }
+
+    /// Returns the counters accumulated since the previous call and resets them.
+    pub fn take(&self) -> BankStatsSnapshot {
+        BankStatsSnapshot {
+            hits: self.hits.take(),
+            misses: self.misses.take(),
+            evictions: self.evictions.take(),
+            miss_micros: self.miss_micros.take(),
+            max_miss_micros: self.max_miss_micros.take(),
+            read_errors: self.read_errors.take(),
+        }
+    }
+}
+
+/// Fills `buffer` from `offset` in `file`, leaving zeros past its end.
+fn read_at<
+    D: embedded_sdmmc::BlockDevice,
+    T: embedded_sdmmc::TimeSource,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+>(
+    file: &mut embedded_sdmmc::File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    offset: usize,
+    buffer: &mut [u8],
+) -> Result<(), StorageError> {
+    file.seek_from_start(offset as u32).map_err(storage_error)?;
+    let mut read = 0;
+    while read < buffer.len() && !file.is_eof() {
+        read += file.read(&mut buffer[read..]).map_err(storage_error)?;
+    }
+    buffer[read..].fill(0);
+    Ok(())
+}
+
+/// Reads the start of `rom_name` into `buffer`, from inside the archive for
+/// `.zip`/`.gz` files. Returns the number of bytes read.
+pub fn read_rom_start<
+    D: embedded_sdmmc::BlockDevice,
+    T: embedded_sdmmc::TimeSource,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+>(
+    root_dir: &mut CardDirectory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    rom_name: &str,
+    buffer: &mut [u8],
+) -> Result<usize, RomError> {
+    let kind = match ArchiveKind::from_name(rom_name) {
+        Some(kind) => kind,
+        None => return Ok(root_dir.read_file(rom_name, buffer)?),
+    };
+    let mut file = root_dir.open_file_in_dir(rom_name, embedded_sdmmc::Mode::ReadOnly)?;
+    let length = file.length() as usize;
+    let entry = archive::find_rom(kind, length, |offset, buffer| {
+        read_at(&mut file, offset, buffer)
+    })?;
+    let read = archive::read_start(
+        &entry,
+        |offset, buffer| read_at(&mut file, offset, buffer),
+        buffer,
+    )?;
+    file.close()?;
+    Ok(read)
+}
+
+fn warn_on_global_checksum(rom: &[u8]) {
+    if rom.len() < HEADER_END {
+        return;
+    }
+    let mut checksum = GlobalChecksum::new();
+    checksum.update(rom);
+    let stored = u16::from_be_bytes([rom[0x14E], rom[0x14F]]);
+    if stored != checksum.finish() {
+        log::warn!(
+            "Global checksum mismatch: stored {:04X}, computed {:04X}",
+            stored,
+            checksum.finish()
+        );
+    }
+}
+
+/// Address the CPU used to read `index` of the bank at `seek_offset`. Banks
+/// other than 0 are assumed to be mapped at 0x4000.
+#[inline(always)]
+fn cpu_address(seek_offset: usize, index: usize) -> u16 {
+    if seek_offset == 0 {
+        index as u16
+    } else {
+        (BANK_SIZE + index) as u16
+    }
+}
+
 pub struct SdRomManager<
     'a,
     D: embedded_sdmmc::BlockDevice,
@@ -14,10 +212,13 @@ pub struct SdRomManager<
     const MAX_FILES: usize,
     const MAX_VOLUMES: usize,
 > {
-    rom_name: String,
-    root_dir: RefCell<embedded_sdmmc::Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>>,
+    rom_file: RefCell<embedded_sdmmc::File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>>,
     bank_0: Box<[u8; 0x4000]>,
-    bank_lru: RefCell<ConstLru<usize, Box<[u8; 0x4000]>, 4, u8>>,
+    bank_cache: RefCell<BankCache>,
+    stats: Rc<BankStats>,
+    patches: Rc<RomPatches>,
+    /// Applied to every bank as it is read from the card.
+    rom_patch: Option<RomPatch>,
     start_time: Instant,
     timer: Box<dyn Timer>,
 }
@@ -30,43 +231,93 @@ impl<
         const MAX_VOLUMES: usize,
     > SdRomManager<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
 {
+    /// The file stays open for the lifetime of the manager so bank misses
+    /// only cost the data read, not a directory walk.
     pub fn new(
-        rom_name: &str,
-        mut root_dir: embedded_sdmmc::Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        mut rom_file: embedded_sdmmc::File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
         timer: Box<dyn Timer>,
-    ) -> Self {
-        let mut rom_file = root_dir
-            .open_file_in_dir(rom_name, embedded_sdmmc::Mode::ReadOnly)
-            .unwrap();
+        cache_config: &BankCacheConfig,
+        patches: Rc<RomPatches>,
+        rom_patch: Option<RomPatch>,
+    ) -> Result<Self, StorageError> {
         let mut bank_0 = Box::new([0u8; 0x4000]);
-        rom_file.seek_from_start(0u32).unwrap();
-        rom_file.read(&mut *bank_0).unwrap();
-        rom_file.close().unwrap();
+        match &rom_patch {
+            Some(rom_patch) => rom_patch.read(0, &mut *bank_0, |offset, buffer| {
+                read_at(&mut rom_file, offset, buffer)
+            })?,
+            None => read_at(&mut rom_file, 0, &mut *bank_0)?,
+        }
 
         let result = Self {
-            rom_name: rom_name.to_string(),
+            rom_file: RefCell::new(rom_file),
             bank_0: bank_0,
-            root_dir: RefCell::new(root_dir),
-            bank_lru: RefCell::new(ConstLru::new()),
+            bank_cache: RefCell::new(BankCache::new(cache_config)),
+            stats: Rc::new(BankStats::default()),
+            patches,
+            rom_patch,
             start_time: timer.now(),
             timer,
         };
 
-        result
+        Ok(result)
+    }
+
+    pub fn bank_0(&self) -> &[u8; 0x4000] {
+        &self.bank_0
     }
-    fn read_bank(&self, bank_offset: usize) -> Box<[u8; 0x4000]> {
-        let mut binding = self.root_dir.borrow_mut();
-        let mut file = binding
-            .open_file_in_dir(self.rom_name.as_str(), embedded_sdmmc::Mode::ReadOnly)
-            .unwrap();
 
-        let mut buffer: Box<[u8; 0x4000]> = Box::new([0u8; 0x4000]);
+    pub fn stats(&self) -> Rc<BankStats> {
+        self.stats.clone()
+    }
+
+    fn read_bank(
+        &self,
+        bank_offset: usize,
+        buffer: &mut [u8; BANK_SIZE],
+    ) -> Result<(), StorageError> {
+        let start = self.timer.now();
+        let mut file = self.rom_file.borrow_mut();
 
-        file.seek_from_start(bank_offset as u32).unwrap();
-        file.read(&mut *buffer).unwrap();
+        match &self.rom_patch {
+            Some(rom_patch) => rom_patch.read(bank_offset, buffer, |offset, buffer| {
+                read_at(&mut *file, offset, buffer)
+            })?,
+            None => read_at(&mut *file, bank_offset, buffer)?,
+        }
 
-        file.close().unwrap();
-        buffer
+        let elapsed = self.timer.now() - start;
+        self.stats.record_miss(elapsed.to_micros());
+        Ok(())
+    }
+
+    fn read_unpatched(&self, seek_offset: usize, index: usize) -> u8 {
+        if seek_offset == 0x0000 {
+            return self.bank_0[index as usize];
+        }
+        let bank = seek_offset / BANK_SIZE;
+        let mut bank_cache = self.bank_cache.borrow_mut();
+        // A hit is a switch to a cached bank, not every byte read from it.
+        let switched = bank_cache.current_bank() != Some(bank);
+        if let Some(buffer) = bank_cache.get(bank) {
+            if switched {
+                self.stats.record_hit();
+            }
+            return buffer[index];
+        }
+        match bank_cache.load(bank, |buffer| self.read_bank(seek_offset, buffer)) {
+            Ok((buffer, evicted)) => {
+                if evicted.is_some() {
+                    self.stats.record_eviction();
+                }
+                buffer[index]
+            }
+            // The game sees open bus; the main loop reports the failures and
+            // the next access to this bank tries the card again.
+            Err(_) => {
+                self.stats.record_read_error();
+                0xFF
+            }
+        }
     }
 }
 impl<
@@ -76,25 +327,11 @@ impl<
         const MAX_DIRS: usize,
         const MAX_FILES: usize,
         const MAX_VOLUMES: usize,
-    > gb_core::hardware::rom::RomManager
-    for SdRomManager<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+    > RomManager for SdRomManager<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
 {
     fn read_from_offset(&self, seek_offset: usize, index: usize) -> u8 {
-        if seek_offset == 0x0000 {
-            return self.bank_0[index as usize];
-        }
-        let mut bank_lru = self.bank_lru.borrow_mut();
-        let bank = bank_lru.get(&seek_offset);
-        let value = match bank {
-            Some(buffer) => buffer[index],
-            None => {
-                let buffer: Box<[u8; 0x4000]> = self.read_bank(seek_offset);
-                let result = buffer[index];
-                bank_lru.insert(seek_offset, buffer);
-                result
-            }
-        };
-        value
+        let value = self.read_unpatched(seek_offset, index);
+        self.patches.apply(cpu_address(seek_offset, index), value)
     }
 
     fn clock(&self) -> u64 {
@@ -134,3 +371,367 @@ impl<
         return &self.bank_0[index];
     }
 }
+
+/// Serves the whole ROM from a single buffer, used when the image fits in PSRAM.
+pub struct PsramRomManager {
+    rom: Box<[u8]>,
+    patches: Rc<RomPatches>,
+    start_time: Instant,
+    timer: Box<dyn Timer>,
+}
+
+impl PsramRomManager {
+    pub fn new(rom: Box<[u8]>, timer: Box<dyn Timer>, patches: Rc<RomPatches>) -> Self {
+        Self {
+            rom,
+            patches,
+            start_time: timer.now(),
+            timer,
+        }
+    }
+
+    pub fn bank_0(&self) -> &[u8] {
+        &self.rom[..self.rom.len().min(0x4000)]
+    }
+}
+
+impl RomManager for PsramRomManager {
+    #[inline(always)]
+    fn read_from_offset(&self, seek_offset: usize, index: usize) -> u8 {
+        let value = match self.rom.get(seek_offset + index) {
+            Some(value) => *value,
+            None => 0xFF,
+        };
+        self.patches.apply(cpu_address(seek_offset, index), value)
+    }
+
+    fn clock(&self) -> u64 {
+        let current_time = self.timer.now();
+        let diff = current_time - self.start_time;
+        diff.to_micros()
+    }
+}
+
+impl core::ops::Index<usize> for PsramRomManager {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.rom[index]
+    }
+}
+
+impl core::ops::Index<core::ops::Range<usize>> for PsramRomManager {
+    type Output = [u8];
+
+    fn index(&self, index: core::ops::Range<usize>) -> &Self::Output {
+        &self.rom[index]
+    }
+}
+
+/// Serves a ROM that is mapped into the address space from flash, either built
+/// into the firmware image or stored in the `gbroms` partition. Reads go
+/// through the flash cache, so no RAM is spent on the image.
+pub struct FlashRomManager {
+    rom: &'static [u8],
+    patches: Rc<RomPatches>,
+    start_time: Instant,
+    timer: Box<dyn Timer>,
+}
+
+impl FlashRomManager {
+    pub fn new(rom: &'static [u8], timer: Box<dyn Timer>, patches: Rc<RomPatches>) -> Self {
+        Self {
+            rom,
+            patches,
+            start_time: timer.now(),
+            timer,
+        }
+    }
+
+    pub fn bank_0(&self) -> &[u8] {
+        &self.rom[..self.rom.len().min(0x4000)]
+    }
+
+    /// Flash reads never miss, so there are no bank statistics to report.
+    pub fn stats(&self) -> Option<Rc<BankStats>> {
+        None
+    }
+}
+
+impl RomManager for FlashRomManager {
+    #[inline(always)]
+    fn read_from_offset(&self, seek_offset: usize, index: usize) -> u8 {
+        let value = match self.rom.get(seek_offset + index) {
+            Some(value) => *value,
+            None => 0xFF,
+        };
+        self.patches.apply(cpu_address(seek_offset, index), value)
+    }
+
+    fn clock(&self) -> u64 {
+        let current_time = self.timer.now();
+        let diff = current_time - self.start_time;
+        diff.to_micros()
+    }
+}
+
+impl core::ops::Index<usize> for FlashRomManager {
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.rom[index]
+    }
+}
+
+impl core::ops::Index<core::ops::Range<usize>> for FlashRomManager {
+    type Output = [u8];
+
+    fn index(&self, index: core::ops::Range<usize>) -> &Self::Output {
+        &self.rom[index]
+    }
+}
+
+/// The ROM backends `main` can hand to `Rom::from_bytes`.
+pub enum RomSource<
+    'a,
+    D: embedded_sdmmc::BlockDevice,
+    T: embedded_sdmmc::TimeSource,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+> {
+    Psram(PsramRomManager),
+    Sd(SdRomManager<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>),
+    Flash(FlashRomManager),
+}
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > RomSource<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    /// Copies the ROM into PSRAM when it fits, otherwise streams banks from the SD card.
+    /// A patch next to the ROM is applied on the way in either case. Zipped
+    /// and gzipped ROMs are inflated into PSRAM, or into `INFLATED_ROM_FILE`
+    /// which is then used like any other ROM file.
+    pub fn open(
+        rom_name: &str,
+        root_dir: &mut CardDirectory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+        timer: Box<dyn Timer>,
+        cache_config: &BankCacheConfig,
+        patches: Rc<RomPatches>,
+    ) -> Result<Self, RomError> {
+        let mut rom_file = root_dir.open_file_in_dir(rom_name, embedded_sdmmc::Mode::ReadOnly)?;
+        let entry = match ArchiveKind::from_name(rom_name) {
+            Some(kind) => {
+                let length = rom_file.length() as usize;
+                Some(archive::find_rom(kind, length, |offset, buffer| {
+                    read_at(&mut rom_file, offset, buffer)
+                })?)
+            }
+            None => None,
+        };
+        if let Some(entry) = &entry {
+            log::info!(
+                "{}: {:?} {} ({} KiB)",
+                rom_name,
+                entry.compression,
+                entry.name,
+                entry.size / 1024
+            );
+        }
+        let length = entry
+            .as_ref()
+            .map_or(rom_file.length() as usize, |entry| entry.size);
+        let rom_patch = match patch::load_patch(root_dir, rom_name, length) {
+            Ok(rom_patch) => rom_patch.map(|(_, rom_patch)| rom_patch),
+            Err(error) => return Err(RomError::Patch(error)),
+        };
+        if let Some(entry) = &entry {
+            if rom_patch.is_none() {
+                if let Some(mut rom) = crate::hardware::psram::alloc_bytes(length) {
+                    let mut written = 0;
+                    archive::extract(
+                        entry,
+                        |offset, buffer| read_at(&mut rom_file, offset, buffer),
+                        |data| {
+                            rom[written..written + data.len()].copy_from_slice(data);
+                            written += data.len();
+                            Ok(true)
+                        },
+                    )?;
+                    rom_file.close()?;
+                    log::info!("Inflated {} ({} KiB) into PSRAM", rom_name, length / 1024);
+                    warn_on_global_checksum(&rom);
+                    return Ok(RomSource::Psram(PsramRomManager::new(rom, timer, patches)));
+                }
+            }
+            // Patches and bank streaming read the ROM from a file, so give them one.
+            let mut inflated = root_dir.open_file_in_dir(
+                INFLATED_ROM_FILE,
+                embedded_sdmmc::Mode::ReadWriteCreateOrTruncate,
+            )?;
+            archive::extract(
+                entry,
+                |offset, buffer| read_at(&mut rom_file, offset, buffer),
+                |data| {
+                    inflated.write(data).map_err(storage_error)?;
+                    Ok(true)
+                },
+            )?;
+            inflated.close()?;
+            rom_file.close()?;
+            log::info!(
+                "Inflated {} ({} KiB) into {}",
+                rom_name,
+                length / 1024,
+                INFLATED_ROM_FILE
+            );
+            rom_file =
+                root_dir.open_file_in_dir(INFLATED_ROM_FILE, embedded_sdmmc::Mode::ReadOnly)?;
+        }
+        let target_length = rom_patch.as_ref().map_or(length, RomPatch::target_size);
+        if let Some(rom_patch) = &rom_patch {
+            let mut scratch = vec![0u8; BANK_SIZE];
+            rom_patch
+                .verify_source(&mut scratch, |offset, buffer| {
+                    read_at(&mut rom_file, offset, buffer)
+                })
+                .map_err(RomError::Patch)?;
+        }
+        match crate::hardware::psram::alloc_bytes(target_length) {
+            Some(mut rom) => {
+                match &rom_patch {
+                    Some(rom_patch) => {
+                        rom_patch.read(0, &mut rom, |offset, buffer| {
+                            read_at(&mut rom_file, offset, buffer)
+                        })?;
+                        rom_patch
+                            .verify_target(crc32(&rom))
+                            .map_err(RomError::Patch)?;
+                    }
+                    None => read_at(&mut rom_file, 0, &mut rom)?,
+                }
+                rom_file.close()?;
+                log::info!(
+                    "Loaded {} ({} KiB) into PSRAM",
+                    rom_name,
+                    target_length / 1024
+                );
+                warn_on_global_checksum(&rom);
+                Ok(RomSource::Psram(PsramRomManager::new(rom, timer, patches)))
+            }
+            None => {
+                log::info!(
+                    "Streaming {} ({} KiB) from the SD card, {} bank cache slots ({:?})",
+                    rom_name,
+                    target_length / 1024,
+                    cache_config.capacity,
+                    cache_config.policy
+                );
+                if let Some(rom_patch) = &rom_patch {
+                    let mut scratch = vec![0u8; BANK_SIZE];
+                    let crc = rom_patch.target_crc(&mut scratch, |offset, buffer| {
+                        read_at(&mut rom_file, offset, buffer)
+                    })?;
+                    rom_patch.verify_target(crc).map_err(RomError::Patch)?;
+                }
+                Ok(RomSource::Sd(SdRomManager::new(
+                    rom_file,
+                    timer,
+                    cache_config,
+                    patches,
+                    rom_patch,
+                )?))
+            }
+        }
+    }
+
+    pub fn bank_0(&self) -> &[u8] {
+        match self {
+            RomSource::Psram(rom) => rom.bank_0(),
+            RomSource::Sd(rom) => &rom.bank_0()[..],
+            RomSource::Flash(rom) => rom.bank_0(),
+        }
+    }
+
+    pub fn stats(&self) -> Option<Rc<BankStats>> {
+        match self {
+            RomSource::Psram(_) => None,
+            RomSource::Sd(rom) => Some(rom.stats()),
+            RomSource::Flash(rom) => rom.stats(),
+        }
+    }
+}
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > RomManager for RomSource<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    #[inline(always)]
+    fn read_from_offset(&self, seek_offset: usize, index: usize) -> u8 {
+        match self {
+            RomSource::Psram(rom) => rom.read_from_offset(seek_offset, index),
+            RomSource::Sd(rom) => rom.read_from_offset(seek_offset, index),
+            RomSource::Flash(rom) => rom.read_from_offset(seek_offset, index),
+        }
+    }
+
+    fn clock(&self) -> u64 {
+        match self {
+            RomSource::Psram(rom) => rom.clock(),
+            RomSource::Sd(rom) => rom.clock(),
+            RomSource::Flash(rom) => rom.clock(),
+        }
+    }
+}
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > core::ops::Index<usize> for RomSource<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    type Output = u8;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self {
+            RomSource::Psram(rom) => &rom[index],
+            RomSource::Sd(rom) => &rom[index],
+            RomSource::Flash(rom) => &rom[index],
+        }
+    }
+}
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > core::ops::Index<core::ops::Range<usize>>
+    for RomSource<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    type Output = [u8];
+
+    fn index(&self, index: core::ops::Range<usize>) -> &Self::Output {
+        match self {
+            RomSource::Psram(rom) => &rom[index],
+            RomSource::Sd(rom) => &rom[index],
+            RomSource::Flash(rom) => &rom[index],
+        }
+    }
+}
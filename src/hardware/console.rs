@@ -0,0 +1,148 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use esp_hal::{peripherals::USB_DEVICE, usb_serial_jtag::UsbSerialJtag, Blocking};
+use esp_println::println;
+
+use super::{flash::RomFlash, rtc::RtcClock};
+use crate::{
+    clock::{Clock, DateTime},
+    console::{Command, LineReader, HELP},
+    gameboy::rom_partition::ROM_PARTITION_LABEL,
+};
+
+/// How long an upload waits for the next byte before giving up.
+const UPLOAD_TIMEOUT_MILLIS: u64 = 3000;
+
+/// Line based commands over the USB serial port, polled from the menu and
+/// emulation loops. Replies go through `esp_println` like the rest of the log.
+pub struct SerialConsole {
+    serial: UsbSerialJtag<'static, Blocking>,
+    line: LineReader,
+    clock: RtcClock,
+    /// Shared with the ROM browser, which lists and starts the ROMs stored here.
+    rom_flash: Option<Rc<RefCell<RomFlash>>>,
+    shutdown_requested: bool,
+}
+
+impl SerialConsole {
+    pub fn new(
+        usb_device: USB_DEVICE,
+        clock: RtcClock,
+        rom_flash: Option<Rc<RefCell<RomFlash>>>,
+    ) -> Self {
+        Self {
+            serial: UsbSerialJtag::new(usb_device),
+            line: LineReader::new(),
+            clock,
+            rom_flash,
+            shutdown_requested: false,
+        }
+    }
+
+    /// Returns `true` once after `shutdown` was entered.
+    pub fn take_shutdown_request(&mut self) -> bool {
+        core::mem::replace(&mut self.shutdown_requested, false)
+    }
+
+    /// Handles any complete commands waiting in the receive FIFO.
+    pub fn poll(&mut self) {
+        while let Ok(byte) = self.serial.read_byte() {
+            if let Some(line) = self.line.push(byte) {
+                self.execute(&line);
+            }
+        }
+    }
+
+    fn execute(&mut self, line: &str) {
+        match Command::parse(line) {
+            Ok(Command::Help) => println!("{}", HELP),
+            Ok(Command::ShowTime) if !self.clock.is_set() => println!("Clock not set"),
+            Ok(Command::ShowTime) => {
+                println!("{}", DateTime::from_unix_seconds(self.clock.unix_seconds()))
+            }
+            Ok(Command::SetTime(date_time)) => {
+                self.clock.set(date_time.to_unix_seconds());
+                println!("Clock set to {}", date_time);
+            }
+            Ok(Command::Shutdown) => {
+                self.shutdown_requested = true;
+                println!("Saving the game");
+            }
+            Ok(Command::ListRoms) => self.list_roms(),
+            Ok(Command::Upload { name, length, crc }) => self.upload(&name, length, crc),
+            Ok(Command::Delete(name)) => self.delete(&name),
+            Err(error) => println!("{}", error),
+        }
+    }
+
+    fn rom_flash(&self) -> Option<Rc<RefCell<RomFlash>>> {
+        if self.rom_flash.is_none() {
+            println!(
+                "No {} partition, flash with `cargo run-gbroms`",
+                ROM_PARTITION_LABEL
+            );
+        }
+        self.rom_flash.clone()
+    }
+
+    fn list_roms(&self) {
+        let rom_flash = match self.rom_flash() {
+            Some(rom_flash) => rom_flash,
+            None => return,
+        };
+        let rom_flash = rom_flash.borrow();
+        let directory = rom_flash.directory();
+        for rom in directory.roms() {
+            println!(
+                "{:12} {:5} KiB  crc {:08x}",
+                rom.name,
+                rom.length / 1024,
+                rom.crc
+            );
+        }
+        println!("{} KiB free", directory.free_bytes() / 1024);
+    }
+
+    fn delete(&self, name: &str) {
+        let rom_flash = match self.rom_flash() {
+            Some(rom_flash) => rom_flash,
+            None => return,
+        };
+        let result = rom_flash.borrow_mut().delete(name);
+        match result {
+            Ok(rom) => println!("Deleted {}", rom.name),
+            Err(error) => println!("Cannot delete {}: {}", name, error),
+        }
+    }
+
+    /// Receives the ROM as raw bytes straight after replying `ready`.
+    fn upload(&mut self, name: &str, length: u32, crc: u32) {
+        let rom_flash = match self.rom_flash() {
+            Some(rom_flash) => rom_flash,
+            None => return,
+        };
+        // Whatever is left of the command line, such as the `\n` of a `\r\n`.
+        while self.serial.read_byte().is_ok() {}
+        println!("ready");
+        let serial = &mut self.serial;
+        let result = rom_flash.borrow_mut().upload(name, length, crc, || {
+            let start = esp_hal::time::now();
+            loop {
+                if let Ok(byte) = serial.read_byte() {
+                    return Some(byte);
+                }
+                if (esp_hal::time::now() - start).to_millis() >= UPLOAD_TIMEOUT_MILLIS {
+                    return None;
+                }
+            }
+        });
+        match result {
+            Ok(rom) => println!(
+                "Stored {} ({} KiB), it is listed from the next start",
+                rom.name,
+                rom.length / 1024
+            ),
+            Err(error) => println!("Upload failed: {}", error),
+        }
+    }
+}
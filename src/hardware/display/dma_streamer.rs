@@ -1,4 +1,5 @@
-use super::{dma_transfer::DmaTransfer, LineTransfer};
+use super::{dma_transfer::DmaTransfer, LineTransfer, TransferStats};
+use alloc::rc::Rc;
 use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
 use esp_hal::{
     dma::DmaTxBuf,
@@ -17,6 +18,7 @@ where
     spare_buffer: Option<DmaTxBuf>,
     main_buffer: Option<DmaTxBuf>,
     spi: Option<SpiDma<'d, T, FullDuplexMode, M>>,
+    stats: Rc<TransferStats>,
 }
 
 impl<'d, T, M> DmaStreamer<'d, T, M>
@@ -33,17 +35,27 @@ where
             spi: Some(spi),
             spare_buffer: Some(spare_buffer),
             main_buffer: Some(main_buffer),
+            stats: Rc::new(TransferStats::default()),
         }
     }
+
+    pub fn stats(&self) -> Rc<TransferStats> {
+        self.stats.clone()
+    }
+
     pub fn stream_u8(&mut self, iterator: &mut dyn Iterator<Item = u8>) {
         let spare_buffer = core::mem::replace(&mut self.spare_buffer, None).unwrap();
         let main_buffer = core::mem::replace(&mut self.main_buffer, None).unwrap();
         let tx = core::mem::replace(&mut self.spi, None).unwrap();
-        let stream = DmaTransfer::new(tx, main_buffer);
+        let start = esp_hal::time::now();
+        let stream = DmaTransfer::new(tx, main_buffer, self.stats.clone());
 
         let (stream, spare_buffer) = Self::compute_line_u8(stream, spare_buffer, iterator);
 
+        // Waits for the last line, so the D/C pin is not switched under it.
         let (spi, main_buffer) = stream.free();
+        self.stats
+            .record_stream((esp_hal::time::now() - start).to_micros());
         self.main_buffer = Some(main_buffer);
         self.spare_buffer = Some(spare_buffer);
         self.spi = Some(spi);
@@ -57,10 +69,13 @@ where
         let main_buffer = core::mem::replace(&mut self.main_buffer, None).unwrap();
 
         let tx = core::mem::replace(&mut self.spi, None).unwrap();
-        let stream = DmaTransfer::new(tx, main_buffer);
+        let start = esp_hal::time::now();
+        let stream = DmaTransfer::new(tx, main_buffer, self.stats.clone());
         let (stream, spare_buffer) = Self::compute_line_u16(stream, spare_buffer, iterator, f);
 
         let (spi, main_buffer) = stream.free();
+        self.stats
+            .record_stream((esp_hal::time::now() - start).to_micros());
         self.main_buffer = Some(main_buffer);
         self.spare_buffer = Some(spare_buffer);
         self.spi = Some(spi);
@@ -77,7 +92,7 @@ where
             let out = pixel;
             buffer.as_mut_slice()[width_position] = out;
             width_position += 1;
-            if width_position == buffer.len() {
+            if width_position == buffer.capacity() {
                 buffer.set_length(width_position);
                 buffer = transfer.send_scanline(buffer);
                 width_position = 0;
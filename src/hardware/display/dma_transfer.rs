@@ -1,3 +1,4 @@
+use alloc::rc::Rc;
 use esp_hal::{
     dma::DmaTxBuf,
     spi::{
@@ -7,7 +8,7 @@ use esp_hal::{
     Mode,
 };
 
-use super::LineTransfer;
+use super::{LineTransfer, TransferStats};
 
 enum DmaState<'d, T, M>
 where
@@ -18,13 +19,18 @@ where
     RUNNING(SpiDmaTransfer<'d, T, FullDuplexMode, M, DmaTxBuf>),
 }
 
+/// Ping-pong transmission: while one buffer is sent by the DMA engine the
+/// caller fills the other one, and only waits when it hands over the next
+/// buffer before the previous transfer has finished.
 pub struct DmaTransfer<'d, T, M>
 where
     T: InstanceDma,
     M: Mode,
 {
     dma: Option<DmaState<'d, T, M>>,
+    /// The buffer not owned by the caller or the DMA engine, only while idle.
     spare_buffer: Option<DmaTxBuf>,
+    stats: Rc<TransferStats>,
 }
 
 impl<'d, T, M> DmaTransfer<'d, T, M>
@@ -32,39 +38,57 @@ where
     T: InstanceDma,
     M: Mode,
 {
-    pub fn new(spi: SpiDma<'d, T, FullDuplexMode, M>, buffer: DmaTxBuf) -> Self {
+    pub fn new(
+        spi: SpiDma<'d, T, FullDuplexMode, M>,
+        buffer: DmaTxBuf,
+        stats: Rc<TransferStats>,
+    ) -> Self {
         Self {
             spare_buffer: Some(buffer),
             dma: Some(DmaState::IDLE(spi)),
+            stats,
         }
     }
 
+    /// Starts sending `buffer` and returns the other buffer to be filled,
+    /// waiting for it if it is still being sent.
     #[inline(always)]
     pub fn do_transfer(&mut self, buffer: DmaTxBuf) -> DmaTxBuf {
-        // let dma_state: DmaState<'d, T, M> = core::mem::replace(&mut self.dma, None).unwrap();
-        // let (spi, spare_buffer) = match dma_state {
-        //     DmaState::IDLE(spi_dma) => {
-        //         let second_buffer = core::mem::replace(&mut self.spare_buffer, None).unwrap();
-        //         (spi_dma, second_buffer)
-        //     }
-        //     DmaState::RUNNING(spi_dma_transfer) => spi_dma_transfer.wait(),
-        // };
-        // self.dma = Some(DmaState::RUNNING(spi.dma_write(buffer).unwrap()));
-        // spare_buffer
-        buffer
+        let (spi, free_buffer) = self.idle();
+        let length = buffer.len();
+        match spi.dma_write(buffer) {
+            Ok(transfer) => {
+                self.stats.record_transfer(length);
+                self.dma = Some(DmaState::RUNNING(transfer));
+            }
+            // The line is dropped, the display shows stale pixels until the
+            // next frame.
+            Err((error, spi, buffer)) => {
+                log::error!("Display DMA transfer failed: {:?}", error);
+                self.dma = Some(DmaState::IDLE(spi));
+                self.spare_buffer = Some(buffer);
+            }
+        }
+        free_buffer
     }
 
+    /// Waits for the last transfer and returns the SPI bus and the buffer
+    /// that is not in the caller's hands.
     pub fn free(mut self) -> (SpiDma<'d, T, FullDuplexMode, M>, DmaTxBuf) {
-        let dma_state = core::mem::replace(&mut self.dma, None).unwrap();
-        let (spi, spare_buffer) = match dma_state {
-            DmaState::IDLE(spi_dma) => {
-                let second_buffer = core::mem::replace(&mut self.spare_buffer, None).unwrap();
-                (spi_dma, second_buffer)
-            }
-            DmaState::RUNNING(spi_dma_transfer) => spi_dma_transfer.wait(),
-        };
+        self.idle()
+    }
 
-        (spi, spare_buffer)
+    fn idle(&mut self) -> (SpiDma<'d, T, FullDuplexMode, M>, DmaTxBuf) {
+        match self.dma.take().unwrap() {
+            DmaState::IDLE(spi_dma) => (spi_dma, self.spare_buffer.take().unwrap()),
+            DmaState::RUNNING(spi_dma_transfer) => {
+                let start = esp_hal::time::now();
+                let idle = spi_dma_transfer.wait();
+                self.stats
+                    .record_wait((esp_hal::time::now() - start).to_micros());
+                idle
+            }
+        }
     }
 }
 
@@ -0,0 +1,106 @@
+use display_interface::{DisplayError, WriteOnlyDataCommand};
+use embedded_graphics::prelude::OriginDimensions;
+use embedded_hal::digital::OutputPin;
+use ili9341::Ili9341;
+
+use crate::{
+    config::Config,
+    presenter::{FramePresenter, Layout, Rect, GB_WIDTH},
+};
+use gb_frontend::scaler::ScreenScaler;
+
+/// Draws frames on the ILI9341. The address window is set once per frame and
+/// the scaled pixels are streamed into it while the emulator produces them.
+/// The border around the picture is only painted by `paint_border`.
+pub struct LcdPresenter<IFACE, RESET> {
+    display: Ili9341<IFACE, RESET>,
+    scaler: ScreenScaler,
+    layout: Layout,
+    border_color: u16,
+}
+
+impl<IFACE, RESET> LcdPresenter<IFACE, RESET>
+where
+    IFACE: WriteOnlyDataCommand,
+    RESET: OutputPin,
+{
+    /// Width and height follow the display's orientation, so the picture is
+    /// laid out for the panel whichever way round it is mounted.
+    pub fn new(display: Ili9341<IFACE, RESET>, config: &Config) -> Self {
+        let (width, height) = Self::panel_size(&display);
+        let layout = Layout::new(config.scaling, width, height);
+        Self {
+            scaler: Self::scaler(&layout, config),
+            display,
+            layout,
+            border_color: config.clear_color,
+        }
+    }
+
+    /// Takes over the scaling mode, filter and border colour of `config`,
+    /// and repaints the border.
+    pub fn configure(&mut self, config: &Config) -> Result<(), DisplayError> {
+        let (width, height) = Self::panel_size(&self.display);
+        self.layout = Layout::new(config.scaling, width, height);
+        self.scaler = Self::scaler(&self.layout, config);
+        self.border_color = config.clear_color;
+        self.paint_border()
+    }
+
+    /// Fills the panel around the picture, needed once after anything else
+    /// was drawn over it.
+    pub fn paint_border(&mut self) -> Result<(), DisplayError> {
+        let (width, height) = Self::panel_size(&self.display);
+        for border in self.layout.borders(width, height) {
+            if border.is_empty() {
+                continue;
+            }
+            let pixels = border.width as usize * border.height as usize;
+            let (x1, y1) = Self::last_pixel(&border);
+            self.display.draw_raw_iter(
+                border.x,
+                border.y,
+                x1,
+                y1,
+                core::iter::repeat(self.border_color).take(pixels),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The display, for menus and messages drawn between frames.
+    pub fn display_mut(&mut self) -> &mut Ili9341<IFACE, RESET> {
+        &mut self.display
+    }
+
+    fn scaler(layout: &Layout, config: &Config) -> ScreenScaler {
+        let target = layout.target;
+        ScreenScaler::new(GB_WIDTH as u16, layout.source, target.width, target.height)
+            .with_filter(config.filter)
+    }
+
+    fn panel_size(display: &Ili9341<IFACE, RESET>) -> (u16, u16) {
+        let size = display.size();
+        (size.width as u16, size.height as u16)
+    }
+
+    fn last_pixel(rect: &Rect) -> (u16, u16) {
+        (rect.x + rect.width - 1, rect.y + rect.height - 1)
+    }
+}
+
+impl<IFACE, RESET> FramePresenter for LcdPresenter<IFACE, RESET>
+where
+    IFACE: WriteOnlyDataCommand,
+    RESET: OutputPin,
+{
+    type Error = DisplayError;
+
+    fn present(&mut self, frame: &mut dyn Iterator<Item = u16>) -> Result<(), DisplayError> {
+        let target = self.layout.target;
+        let (x0, y0) = (target.x, target.y);
+        let (x1, y1) = Self::last_pixel(&target);
+        self.display
+            .draw_raw_iter(x0, y0, x1, y1, self.scaler.scale_iterator(frame))
+    }
+}
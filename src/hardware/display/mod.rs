@@ -1,8 +1,62 @@
+use core::cell::Cell;
 use esp_hal::dma::DmaTxBuf;
 
 pub mod dma_streamer;
 pub mod dma_transfer;
-pub mod scaler;
+pub mod lcd_presenter;
 trait LineTransfer {
     fn send_scanline(&mut self, line: DmaTxBuf) -> DmaTxBuf;
 }
+
+/// Display transfer counters, shared with the main loop so the DMA overlap
+/// can be checked per frame.
+#[derive(Default)]
+pub struct TransferStats {
+    bytes: Cell<u64>,
+    /// Time spent inside `DmaStreamer` calls, filling buffers included.
+    stream_micros: Cell<u64>,
+    /// Part of `stream_micros` spent waiting for the DMA engine.
+    wait_micros: Cell<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferStatsSnapshot {
+    pub bytes: u64,
+    pub stream_micros: u64,
+    pub wait_micros: u64,
+}
+
+impl TransferStatsSnapshot {
+    pub fn bytes_per_second(&self) -> u64 {
+        if self.stream_micros == 0 {
+            return 0;
+        }
+        self.bytes * 1_000_000 / self.stream_micros
+    }
+}
+
+impl TransferStats {
+    fn record_transfer(&self, bytes: usize) {
+        self.bytes
+            .set(self.bytes.get().saturating_add(bytes as u64));
+    }
+
+    fn record_wait(&self, micros: u64) {
+        self.wait_micros
+            .set(self.wait_micros.get().saturating_add(micros));
+    }
+
+    fn record_stream(&self, micros: u64) {
+        self.stream_micros
+            .set(self.stream_micros.get().saturating_add(micros));
+    }
+
+    /// Returns the counters accumulated since the previous call and resets them.
+    pub fn take(&self) -> TransferStatsSnapshot {
+        TransferStatsSnapshot {
+            bytes: self.bytes.take(),
+            stream_micros: self.stream_micros.take(),
+            wait_micros: self.wait_micros.take(),
+        }
+    }
+}
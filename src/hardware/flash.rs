@@ -0,0 +1,300 @@
+use alloc::{string::String, vec};
+use core::fmt;
+use embedded_storage::{nor_flash::NorFlash, ReadStorage};
+use esp_storage::{FlashStorage, FlashStorageError};
+
+use crate::{
+    checksum::Crc32,
+    gameboy::rom_partition::{
+        find_partition, normalize_name, FlashRom, MappedRoms, Partition, PartitionError,
+        RomPartition, PARTITION_TABLE_OFFSET, PARTITION_TABLE_SIZE, ROM_PARTITION_LABEL,
+        SECTOR_SIZE,
+    },
+};
+
+/// The cache MMU table: one entry per 64 KiB page of the data bus window,
+/// shared with the instruction bus.
+const MMU_TABLE: *const u32 = 0x600C_5000 as *const u32;
+const MMU_ENTRIES: usize = 512;
+const MMU_INVALID: u32 = 1 << 14;
+const MMU_ACCESS_FLASH: u32 = 0;
+const MMU_PAGE_SIZE: u32 = 0x10000;
+/// Start of the data bus window the MMU table maps.
+const DBUS_ORIGIN: u32 = 0x3C00_0000;
+
+// ROM functions, the same ones `esp_hal::psram` maps PSRAM with.
+extern "C" {
+    fn Cache_Suspend_DCache() -> u32;
+    fn Cache_Resume_DCache(state: u32);
+    fn cache_dbus_mmu_set(
+        ext_ram: u32,
+        vaddr: u32,
+        paddr: u32,
+        psize: u32,
+        num: u32,
+        fixed: u32,
+    ) -> i32;
+    fn Cache_Invalidate_Addr(addr: u32, size: u32);
+}
+
+#[derive(Debug)]
+pub enum FlashError {
+    Flash(FlashStorageError),
+    Partition(PartitionError),
+    /// The partition has to start on an MMU page to be mapped.
+    Misaligned(u32),
+    /// No run of free MMU pages is long enough for the partition.
+    NoAddressSpace,
+    /// The sender stopped after this many bytes.
+    Timeout {
+        received: u32,
+    },
+    Checksum {
+        expected: u32,
+        found: u32,
+    },
+    /// The ROM is the one being played.
+    InUse(String),
+}
+
+impl From<FlashStorageError> for FlashError {
+    fn from(error: FlashStorageError) -> Self {
+        FlashError::Flash(error)
+    }
+}
+
+impl From<PartitionError> for FlashError {
+    fn from(error: PartitionError) -> Self {
+        FlashError::Partition(error)
+    }
+}
+
+impl fmt::Display for FlashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlashError::Flash(error) => write!(f, "flash error: {:?}", error),
+            FlashError::Partition(error) => write!(f, "{}", error),
+            FlashError::Misaligned(offset) => write!(
+                f,
+                "the {} partition at {:#X} does not start on a 64 KiB boundary",
+                ROM_PARTITION_LABEL, offset
+            ),
+            FlashError::NoAddressSpace => write!(f, "no address space left to map the ROMs"),
+            FlashError::Timeout { received } => {
+                write!(f, "timed out after {} bytes", received)
+            }
+            FlashError::Checksum { expected, found } => write!(
+                f,
+                "CRC-32 mismatch: expected {:08x}, flash holds {:08x}",
+                expected, found
+            ),
+            FlashError::InUse(name) => write!(f, "{} is running", name),
+        }
+    }
+}
+
+/// The `gbroms` partition, mapped so ROMs can be played straight from flash
+/// and written to by the serial console.
+///
+/// Uploads and deletes rewrite the mapped flash behind the cache, so the
+/// mapping is kept as a raw pointer. Slices of it only live as long as a
+/// borrow of `self`, except for the ROM handed out by `lock`, which is never
+/// written again.
+pub struct RomFlash {
+    storage: FlashStorage,
+    partition: Partition,
+    roms: RomPartition,
+    data: *const u8,
+    running: Option<String>,
+}
+
+impl RomFlash {
+    /// Finds the partition and maps it. `None` when the partition table has
+    /// no `gbroms` partition.
+    pub fn open() -> Result<Option<Self>, FlashError> {
+        let mut storage = FlashStorage::new();
+        let mut table = vec![0u8; PARTITION_TABLE_SIZE];
+        storage.read(PARTITION_TABLE_OFFSET, &mut table)?;
+        let partition = match find_partition(&table, ROM_PARTITION_LABEL) {
+            Some(partition) => partition,
+            None => return Ok(None),
+        };
+        let mut sector = vec![0u8; SECTOR_SIZE];
+        storage.read(partition.offset, &mut sector)?;
+        // The directory is rewritten by the next upload.
+        let roms = RomPartition::parse(partition.size, &sector).unwrap_or_else(|error| {
+            log::error!("{} directory unreadable: {}", ROM_PARTITION_LABEL, error);
+            RomPartition::empty(partition.size)
+        });
+        let data = map(partition)?;
+        log::info!(
+            "{} partition: {} ROMs, {} KiB free",
+            ROM_PARTITION_LABEL,
+            roms.roms().len(),
+            roms.free_bytes() / 1024
+        );
+        Ok(Some(Self {
+            storage,
+            partition,
+            roms,
+            data,
+            running: None,
+        }))
+    }
+
+    pub fn directory(&self) -> &RomPartition {
+        &self.roms
+    }
+
+    /// The ROMs for the browser. Upload and delete need `&mut self`, so the
+    /// flash cannot change while they are borrowed.
+    pub fn roms(&self) -> MappedRoms<'_> {
+        MappedRoms {
+            partition: &self.roms,
+            // SAFETY: `map` mapped `partition.size` bytes at `data`.
+            data: unsafe { core::slice::from_raw_parts(self.data, self.partition.size as usize) },
+        }
+    }
+
+    /// Returns `name` to be played, protecting it from being deleted or
+    /// replaced from now on. Uploads only write to gaps, so the returned
+    /// bytes stay as they are for as long as the firmware runs.
+    pub fn lock(&mut self, name: &str) -> Option<&'static [u8]> {
+        let rom = self.roms.find(name)?.clone();
+        self.running = Some(rom.name);
+        // SAFETY: the ROM lies inside the mapped partition, as `insert`
+        // checks, and `check_not_running` keeps it from being rewritten.
+        Some(unsafe {
+            core::slice::from_raw_parts(self.data.add(rom.offset as usize), rom.length as usize)
+        })
+    }
+
+    fn check_not_running(&self, name: &str) -> Result<(), FlashError> {
+        match &self.running {
+            Some(running) if running.eq_ignore_ascii_case(name) => {
+                Err(FlashError::InUse(running.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Stores `length` bytes pulled from `next_byte`, which returns `None` once
+    /// the sender stalls. The data goes into a free gap and the directory only
+    /// changes once it reads back with the expected CRC-32, so a failed upload
+    /// leaves an older copy of the ROM in place.
+    pub fn upload<F: FnMut() -> Option<u8>>(
+        &mut self,
+        name: &str,
+        length: u32,
+        crc: u32,
+        mut next_byte: F,
+    ) -> Result<FlashRom, FlashError> {
+        let name = normalize_name(name)?;
+        self.check_not_running(&name)?;
+        let offset = self.roms.allocate(length)?;
+        let start = self.partition.offset + offset;
+        let mut sector = vec![0xFFu8; SECTOR_SIZE];
+        let mut received = 0;
+        while received < length {
+            let chunk = ((length - received) as usize).min(SECTOR_SIZE);
+            for byte in sector[..chunk].iter_mut() {
+                *byte = match next_byte() {
+                    Some(byte) => byte,
+                    None => return Err(FlashError::Timeout { received }),
+                };
+                received += 1;
+            }
+            sector[chunk..].fill(0xFF);
+            let address = start + received - chunk as u32;
+            self.storage.erase(address, address + SECTOR_SIZE as u32)?;
+            self.storage.write(address, &sector)?;
+        }
+
+        let mut check = Crc32::new();
+        let mut verified = 0;
+        while verified < length {
+            let chunk = ((length - verified) as usize).min(SECTOR_SIZE);
+            self.storage.read(start + verified, &mut sector[..chunk])?;
+            check.update(&sector[..chunk]);
+            verified += chunk as u32;
+        }
+        if check.finish() != crc {
+            return Err(FlashError::Checksum {
+                expected: crc,
+                found: check.finish(),
+            });
+        }
+
+        let rom = FlashRom {
+            name,
+            offset,
+            length,
+            crc,
+        };
+        self.roms.insert(rom.clone())?;
+        self.write_directory()?;
+        // The gap may have held a deleted ROM whose lines are still cached.
+        unsafe { Cache_Invalidate_Addr(self.data as u32 + offset, length) };
+        Ok(rom)
+    }
+
+    pub fn delete(&mut self, name: &str) -> Result<FlashRom, FlashError> {
+        self.check_not_running(name)?;
+        let rom = self.roms.remove(name)?;
+        if let Err(error) = self.write_directory() {
+            self.roms.insert(rom)?;
+            return Err(error);
+        }
+        Ok(rom)
+    }
+
+    fn write_directory(&mut self) -> Result<(), FlashError> {
+        let sector = self.roms.encode();
+        let offset = self.partition.offset;
+        self.storage.erase(offset, offset + SECTOR_SIZE as u32)?;
+        self.storage.write(offset, &sector)?;
+        Ok(())
+    }
+}
+
+/// Maps `partition` read-only into the first run of unused MMU pages, after
+/// the firmware and PSRAM.
+fn map(partition: Partition) -> Result<*const u8, FlashError> {
+    if partition.offset % MMU_PAGE_SIZE != 0 {
+        return Err(FlashError::Misaligned(partition.offset));
+    }
+    let pages = partition.size.div_ceil(MMU_PAGE_SIZE) as usize;
+    let mut free = 0;
+    let mut first_page = None;
+    for index in 0..MMU_ENTRIES {
+        let entry = unsafe { MMU_TABLE.add(index).read_volatile() };
+        free = if entry & MMU_INVALID != 0 {
+            free + 1
+        } else {
+            0
+        };
+        if free == pages {
+            first_page = Some(index + 1 - pages);
+            break;
+        }
+    }
+    let first_page = first_page.ok_or(FlashError::NoAddressSpace)?;
+    let address = DBUS_ORIGIN + first_page as u32 * MMU_PAGE_SIZE;
+    unsafe {
+        let state = Cache_Suspend_DCache();
+        let result = cache_dbus_mmu_set(
+            MMU_ACCESS_FLASH,
+            address,
+            partition.offset,
+            64,
+            pages as u32,
+            0,
+        );
+        Cache_Resume_DCache(state);
+        if result != 0 {
+            return Err(FlashError::NoAddressSpace);
+        }
+        Cache_Invalidate_Addr(address, pages as u32 * MMU_PAGE_SIZE);
+    }
+    Ok(address as *const u8)
+}
@@ -1,3 +1,8 @@
+pub mod console;
 pub mod display;
+pub mod flash;
+pub mod pins;
+pub mod psram;
+pub mod rtc;
 pub mod sdcard;
 pub mod sound;
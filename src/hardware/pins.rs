@@ -0,0 +1,192 @@
+use esp_hal::gpio::{AnyPin, GpioPin, Pin, Pins};
+
+use crate::config::PinConfig;
+
+const GPIO_COUNT: usize = 49;
+
+/// The SD card sits on fixed pins: `config.ini` is read from it, so its pins
+/// cannot come from there. Octal PSRAM takes GPIO36/37, so builds for it move
+/// CS and MOSI to GPIO40/41.
+pub struct SdCardPins {
+    pub sclk: GpioPin<38>,
+    pub miso: GpioPin<39>,
+    #[cfg(not(feature = "octal-psram"))]
+    pub mosi: GpioPin<37>,
+    #[cfg(not(feature = "octal-psram"))]
+    pub cs: GpioPin<36>,
+    #[cfg(feature = "octal-psram")]
+    pub mosi: GpioPin<41>,
+    #[cfg(feature = "octal-psram")]
+    pub cs: GpioPin<40>,
+}
+
+/// GPIOs that `config.ini` may assign, handed out by number.
+pub struct PinBank {
+    pins: [Option<AnyPin>; GPIO_COUNT],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinError {
+    /// Reserved for flash, PSRAM, USB or the SD card, or already taken.
+    Unavailable(u8),
+}
+
+impl core::fmt::Display for PinError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PinError::Unavailable(gpio) => write!(f, "GPIO{} cannot be used", gpio),
+        }
+    }
+}
+
+/// Splits the GPIOs into the SD card pins and the bank of assignable ones.
+/// GPIO19/20 (USB), 26-32 (flash) and 33-37 (octal PSRAM) are left out.
+pub fn split(pins: Pins) -> (SdCardPins, PinBank) {
+    let Pins {
+        gpio0,
+        gpio1,
+        gpio2,
+        gpio3,
+        gpio4,
+        gpio5,
+        gpio6,
+        gpio7,
+        gpio8,
+        gpio9,
+        gpio10,
+        gpio11,
+        gpio12,
+        gpio13,
+        gpio14,
+        gpio15,
+        gpio16,
+        gpio17,
+        gpio18,
+        gpio21,
+        #[cfg(not(feature = "octal-psram"))]
+        gpio36,
+        #[cfg(not(feature = "octal-psram"))]
+        gpio37,
+        gpio38,
+        gpio39,
+        gpio40,
+        gpio41,
+        gpio42,
+        gpio43,
+        gpio44,
+        gpio45,
+        gpio46,
+        gpio47,
+        gpio48,
+        ..
+    } = pins;
+    #[cfg(not(feature = "octal-psram"))]
+    let sd_pins = SdCardPins {
+        sclk: gpio38,
+        miso: gpio39,
+        mosi: gpio37,
+        cs: gpio36,
+    };
+    #[cfg(feature = "octal-psram")]
+    let sd_pins = SdCardPins {
+        sclk: gpio38,
+        miso: gpio39,
+        mosi: gpio41,
+        cs: gpio40,
+    };
+    let mut bank = PinBank {
+        pins: core::array::from_fn(|_| None),
+    };
+    let assignable = [
+        gpio0.degrade(),
+        gpio1.degrade(),
+        gpio2.degrade(),
+        gpio3.degrade(),
+        gpio4.degrade(),
+        gpio5.degrade(),
+        gpio6.degrade(),
+        gpio7.degrade(),
+        gpio8.degrade(),
+        gpio9.degrade(),
+        gpio10.degrade(),
+        gpio11.degrade(),
+        gpio12.degrade(),
+        gpio13.degrade(),
+        gpio14.degrade(),
+        gpio15.degrade(),
+        gpio16.degrade(),
+        gpio17.degrade(),
+        gpio18.degrade(),
+        gpio21.degrade(),
+        #[cfg(not(feature = "octal-psram"))]
+        gpio40.degrade(),
+        #[cfg(not(feature = "octal-psram"))]
+        gpio41.degrade(),
+        gpio42.degrade(),
+        gpio43.degrade(),
+        gpio44.degrade(),
+        gpio45.degrade(),
+        gpio46.degrade(),
+        gpio47.degrade(),
+        gpio48.degrade(),
+    ];
+    for pin in assignable {
+        let gpio = pin.number() as usize;
+        bank.pins[gpio] = Some(pin);
+    }
+    (sd_pins, bank)
+}
+
+/// The pins `main` wires up, as chosen in `PinConfig`.
+pub struct AssignedPins {
+    pub led: AnyPin,
+    pub lcd_mosi: AnyPin,
+    pub lcd_sclk: AnyPin,
+    pub lcd_reset: AnyPin,
+    pub lcd_dc: AnyPin,
+    pub a: AnyPin,
+    pub b: AnyPin,
+    pub start: AnyPin,
+    pub select: AnyPin,
+    pub up: AnyPin,
+    pub down: AnyPin,
+    pub left: AnyPin,
+    pub right: AnyPin,
+    pub low_battery: Option<AnyPin>,
+}
+
+impl PinBank {
+    /// Takes the pins of `config`. Nothing is taken if any of them is unavailable.
+    pub fn assign(&mut self, config: &PinConfig) -> Result<AssignedPins, PinError> {
+        let optional = config.low_battery.map(|gpio| ("low_battery", gpio));
+        for (_, gpio) in config.entries().into_iter().chain(optional) {
+            match self.pins.get(gpio as usize) {
+                Some(Some(_)) => {}
+                _ => return Err(PinError::Unavailable(gpio)),
+            }
+        }
+        Ok(AssignedPins {
+            led: self.take(config.led)?,
+            lcd_mosi: self.take(config.lcd_mosi)?,
+            lcd_sclk: self.take(config.lcd_sclk)?,
+            lcd_reset: self.take(config.lcd_reset)?,
+            lcd_dc: self.take(config.lcd_dc)?,
+            a: self.take(config.a)?,
+            b: self.take(config.b)?,
+            start: self.take(config.start)?,
+            select: self.take(config.select)?,
+            up: self.take(config.up)?,
+            down: self.take(config.down)?,
+            left: self.take(config.left)?,
+            right: self.take(config.right)?,
+            low_battery: config.low_battery.map(|gpio| self.take(gpio)).transpose()?,
+        })
+    }
+
+    fn take(&mut self, gpio: u8) -> Result<AnyPin, PinError> {
+        self.pins
+            .get_mut(gpio as usize)
+            .and_then(Option::take)
+            .ok_or(PinError::Unavailable(gpio))
+    }
+}
@@ -0,0 +1,49 @@
+use alloc::boxed::Box;
+use core::{
+    alloc::Layout,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use esp_hal::peripherals::PSRAM;
+
+/// PSRAM kept free for everything else (save states, decompression buffers)
+/// when deciding whether a ROM can be loaded entirely into PSRAM.
+const RESERVED_BYTES: usize = 512 * 1024;
+
+static PSRAM_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Maps PSRAM and registers it with `esp_alloc` as an external heap region.
+#[cfg(any(feature = "quad-psram", feature = "octal-psram"))]
+pub fn init(psram: PSRAM) {
+    let (start, size) = esp_hal::psram::init_psram(psram);
+    unsafe {
+        esp_alloc::HEAP.add_region(esp_alloc::HeapRegion::new(
+            start,
+            size,
+            esp_alloc::MemoryCapability::External.into(),
+        ));
+    }
+    PSRAM_SIZE.store(size, Ordering::Relaxed);
+    log::info!("PSRAM heap: {} KiB", size / 1024);
+}
+
+#[cfg(not(any(feature = "quad-psram", feature = "octal-psram")))]
+pub fn init(_psram: PSRAM) {
+    log::info!("Built without PSRAM support");
+}
+
+/// Allocates a zeroed buffer in PSRAM, or `None` if it would not leave
+/// `RESERVED_BYTES` free.
+pub fn alloc_bytes(len: usize) -> Option<Box<[u8]>> {
+    if len == 0 || len + RESERVED_BYTES > PSRAM_SIZE.load(Ordering::Relaxed) {
+        return None;
+    }
+    let layout = Layout::array::<u8>(len).ok()?;
+    unsafe {
+        let ptr = esp_alloc::HEAP.alloc_caps(esp_alloc::MemoryCapability::External.into(), layout);
+        if ptr.is_null() {
+            return None;
+        }
+        ptr.write_bytes(0, len);
+        Some(Box::from_raw(core::ptr::slice_from_raw_parts_mut(ptr, len)))
+    }
+}
@@ -0,0 +1,79 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use esp_hal::{macros::ram, peripherals::LPWR, rtc_cntl::Rtc};
+
+use crate::clock::{Clock, DateTime};
+
+/// Marks `WALL_CLOCK` as initialised, RTC memory holds garbage after power-on.
+const CLOCK_MAGIC: u64 = 0x4742_434c_4f43_4b31;
+
+/// Unix time at which the RTC timer read zero, kept in RTC memory so the clock
+/// survives soft resets. The RTC timer itself keeps counting through them.
+#[ram(rtc_fast, persistent)]
+static mut WALL_CLOCK: [u64; 2] = [0; 2];
+
+static RTC: Mutex<RefCell<Option<Rtc<'static>>>> = Mutex::new(RefCell::new(None));
+
+pub fn init(lpwr: LPWR) {
+    let rtc = Rtc::new(lpwr);
+    critical_section::with(|cs| RTC.borrow_ref_mut(cs).replace(rtc));
+    let clock = RtcClock;
+    if clock.is_set() {
+        log::info!(
+            "Clock: {}",
+            DateTime::from_unix_seconds(clock.unix_seconds())
+        );
+    } else {
+        log::info!("Clock not set, use the 'time' serial command");
+    }
+}
+
+/// Handle to the wall clock, cheap to copy into the SD card driver and cartridges.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RtcClock;
+
+impl RtcClock {
+    fn rtc_seconds(&self) -> u64 {
+        critical_section::with(|cs| {
+            RTC.borrow_ref(cs)
+                .as_ref()
+                .map_or(0, |rtc| rtc.time_since_boot().to_secs())
+        })
+    }
+
+    fn stored(&self) -> Option<u64> {
+        let [magic, offset] = unsafe { core::ptr::addr_of!(WALL_CLOCK).read_volatile() };
+        (magic == CLOCK_MAGIC).then_some(offset)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.stored().is_some()
+    }
+
+    pub fn set(&self, unix_seconds: u64) {
+        let offset = unix_seconds.wrapping_sub(self.rtc_seconds());
+        unsafe { core::ptr::addr_of_mut!(WALL_CLOCK).write_volatile([CLOCK_MAGIC, offset]) };
+    }
+}
+
+impl Clock for RtcClock {
+    /// Seconds since the RTC started if the clock was never set.
+    fn unix_seconds(&self) -> u64 {
+        self.rtc_seconds().wrapping_add(self.stored().unwrap_or(0))
+    }
+}
+
+impl embedded_sdmmc::TimeSource for RtcClock {
+    fn get_timestamp(&self) -> embedded_sdmmc::Timestamp {
+        let now = DateTime::from_unix_seconds(self.unix_seconds());
+        embedded_sdmmc::Timestamp {
+            year_since_1970: now.year.saturating_sub(1970).min(u8::MAX as u16) as u8,
+            zero_indexed_month: now.month - 1,
+            zero_indexed_day: now.day - 1,
+            hours: now.hour,
+            minutes: now.minute,
+            seconds: now.second,
+        }
+    }
+}
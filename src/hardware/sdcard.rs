@@ -1,17 +1,294 @@
-#[derive(Default)]
-pub struct DummyTimesource();
-
-impl embedded_sdmmc::TimeSource for DummyTimesource {
-    // In theory you could use the RTC of the rp2040 here, if you had
-    // any external time synchronizing device.
-    fn get_timestamp(&self) -> embedded_sdmmc::Timestamp {
-        embedded_sdmmc::Timestamp {
-            year_since_1970: 0,
-            zero_indexed_month: 0,
-            zero_indexed_day: 0,
-            hours: 0,
-            minutes: 0,
-            seconds: 0,
+use alloc::{format, rc::Rc, vec};
+use core::{
+    cell::Cell,
+    ops::{Deref, DerefMut},
+};
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx, SdCard, SdCardError};
+
+use crate::{
+    storage::{FileStorage, StorageError},
+    ui::rom_browser::RomDirectory,
+};
+
+/// Maps a card or filesystem error to the frontend's `StorageError`. Both
+/// types come from other crates, so this cannot be a `From` impl.
+pub fn storage_error<E: core::fmt::Debug>(error: embedded_sdmmc::Error<E>) -> StorageError {
+    match error {
+        embedded_sdmmc::Error::NotFound => StorageError::NotFound,
+        embedded_sdmmc::Error::NotEnoughSpace => StorageError::Full,
+        error => StorageError::Device(format!("{:?}", error)),
+    }
+}
+
+/// SPI clock rates tried once the card is initialised, fastest first.
+const BUS_SPEEDS_KHZ: [u32; 4] = [40_000, 20_000, 10_000, 4_000];
+/// Blocks read at each speed to check it works and measure throughput.
+const PROBE_BLOCKS: usize = 32;
+
+/// An SD card that is initialised at the slow clock the SPI bus was created
+/// with, then runs as fast as it reliably can. Failed transfers step the clock
+/// down and retry, so a marginal card or long wires cost speed, not a crash.
+pub struct AdaptiveSdCard<SPI, DELAYER, F>
+where
+    SPI: SpiDevice<u8>,
+    DELAYER: DelayNs,
+    F: Fn(&mut SPI, u32),
+{
+    card: SdCard<SPI, DELAYER>,
+    /// Sets the SPI bus clock, in kHz.
+    set_speed: F,
+    speed: Cell<usize>,
+    /// Fastest clock allowed, in kHz, shared with `main` which sets it from
+    /// `config.ini` once the card is readable.
+    max_khz: Rc<Cell<u32>>,
+}
+
+impl<SPI, DELAYER, F> AdaptiveSdCard<SPI, DELAYER, F>
+where
+    SPI: SpiDevice<u8>,
+    DELAYER: DelayNs,
+    F: Fn(&mut SPI, u32),
+{
+    pub fn new(card: SdCard<SPI, DELAYER>, set_speed: F) -> Self {
+        Self {
+            card,
+            set_speed,
+            speed: Cell::new(0),
+            max_khz: Rc::new(Cell::new(u32::MAX)),
+        }
+    }
+
+    pub fn speed_limit(&self) -> Rc<Cell<u32>> {
+        self.max_khz.clone()
+    }
+
+    /// Initialises the card, then picks the fastest clock at which reads succeed.
+    pub fn ramp_up(&self) -> Result<(), SdCardError> {
+        let size = self.card.num_bytes()?;
+        log::info!(
+            "SD card: {:?}, {} MiB",
+            self.card.get_card_type(),
+            size / (1024 * 1024)
+        );
+        let mut blocks = vec![Block::new(); PROBE_BLOCKS];
+        let mut result = Ok(());
+        for (index, khz) in BUS_SPEEDS_KHZ.iter().enumerate() {
+            if *khz > self.max_khz.get() && index + 1 < BUS_SPEEDS_KHZ.len() {
+                continue;
+            }
+            self.apply_speed(index);
+            let start = esp_hal::time::now();
+            result = self.card.read(&mut blocks, BlockIdx(0));
+            let micros = (esp_hal::time::now() - start).to_micros().max(1);
+            match &result {
+                Ok(()) => {
+                    let bytes = (PROBE_BLOCKS * Block::LEN) as u64;
+                    log::info!(
+                        "SD card SPI clock: {} kHz, read throughput {} KiB/s",
+                        khz,
+                        bytes * 1_000_000 / micros / 1024
+                    );
+                    return Ok(());
+                }
+                Err(error) => log::warn!("SD card read failed at {} kHz: {:?}", khz, error),
+            }
+        }
+        result
+    }
+
+    fn apply_speed(&self, index: usize) {
+        self.speed.set(index);
+        self.card
+            .spi(|spi| (self.set_speed)(spi, BUS_SPEEDS_KHZ[index]));
+    }
+
+    /// Steps down to the fastest clock within `max_khz` if it was lowered.
+    fn enforce_limit(&self) {
+        let current = self.speed.get();
+        if BUS_SPEEDS_KHZ[current] <= self.max_khz.get() {
+            return;
+        }
+        let index = BUS_SPEEDS_KHZ
+            .iter()
+            .position(|khz| *khz <= self.max_khz.get())
+            .unwrap_or(BUS_SPEEDS_KHZ.len() - 1);
+        if index != current {
+            log::info!("SD card SPI clock limited to {} kHz", BUS_SPEEDS_KHZ[index]);
+            self.apply_speed(index);
+        }
+    }
+
+    /// Drops to the next slower clock. Returns `false` if already at the slowest.
+    fn slow_down(&self, error: &SdCardError) -> bool {
+        let next = self.speed.get() + 1;
+        if next >= BUS_SPEEDS_KHZ.len() {
+            return false;
+        }
+        log::warn!(
+            "SD card error {:?} at {} kHz, retrying at {} kHz",
+            error,
+            BUS_SPEEDS_KHZ[next - 1],
+            BUS_SPEEDS_KHZ[next]
+        );
+        self.apply_speed(next);
+        true
+    }
+}
+
+impl<SPI, DELAYER, F> BlockDevice for AdaptiveSdCard<SPI, DELAYER, F>
+where
+    SPI: SpiDevice<u8>,
+    DELAYER: DelayNs,
+    F: Fn(&mut SPI, u32),
+{
+    type Error = SdCardError;
+
+    fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        self.enforce_limit();
+        loop {
+            match self.card.read(blocks, start_block_idx) {
+                Err(error) if self.slow_down(&error) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        self.enforce_limit();
+        loop {
+            match self.card.write(blocks, start_block_idx) {
+                Err(error) if self.slow_down(&error) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        self.card.num_blocks()
+    }
+}
+
+/// A directory on the card. The frontend's storage traits are implemented for
+/// this wrapper, as neither they nor `embedded_sdmmc::Directory` are defined
+/// in this crate. It dereferences to the directory for everything else.
+pub struct CardDirectory<
+    'a,
+    D: embedded_sdmmc::BlockDevice,
+    T: embedded_sdmmc::TimeSource,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+>(pub embedded_sdmmc::Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>);
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > Deref for CardDirectory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    type Target = embedded_sdmmc::Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > DerefMut for CardDirectory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > RomDirectory for CardDirectory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    fn for_each_file(&mut self, f: &mut dyn FnMut(&str)) -> Result<(), StorageError> {
+        self.iterate_dir(|entry| {
+            if entry.attributes.is_directory() || entry.attributes.is_volume() {
+                return;
+            }
+            f(format!("{}", entry.name).as_str());
+        })
+        .map_err(storage_error)
+    }
+
+    fn read_start(&mut self, name: &str, buffer: &mut [u8]) -> usize {
+        crate::gameboy::rom::read_rom_start(self, name, buffer).unwrap_or(0)
+    }
+}
+
+impl<
+        'a,
+        D: embedded_sdmmc::BlockDevice,
+        T: embedded_sdmmc::TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+    > FileStorage for CardDirectory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+{
+    fn read_file(&mut self, name: &str, buffer: &mut [u8]) -> Result<usize, StorageError> {
+        let mut file = self
+            .open_file_in_dir(name, embedded_sdmmc::Mode::ReadOnly)
+            .map_err(storage_error)?;
+        let mut total = 0;
+        while total < buffer.len() && !file.is_eof() {
+            total += file.read(&mut buffer[total..]).map_err(storage_error)?;
+        }
+        file.close().map_err(storage_error)?;
+        Ok(total)
+    }
+
+    fn file_len(&mut self, name: &str) -> Result<usize, StorageError> {
+        let entry = self.find_directory_entry(name).map_err(storage_error)?;
+        Ok(entry.size as usize)
+    }
+
+    fn write_file(&mut self, name: &str, data: &[u8]) -> Result<(), StorageError> {
+        let mut file = self
+            .open_file_in_dir(name, embedded_sdmmc::Mode::ReadWriteCreateOrTruncate)
+            .map_err(storage_error)?;
+        file.write(data).map_err(storage_error)?;
+        file.close().map_err(storage_error)
+    }
+}
+
+/// Opens the subdirectory `name` of `dir`, creating it first if `create` is set.
+pub fn open_subdir<
+    'a,
+    D: embedded_sdmmc::BlockDevice,
+    T: embedded_sdmmc::TimeSource,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+>(
+    dir: &mut CardDirectory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    name: &str,
+    create: bool,
+) -> Result<CardDirectory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>, StorageError> {
+    if create {
+        match dir.make_dir_in_dir(name) {
+            Ok(()) | Err(embedded_sdmmc::Error::DirAlreadyExists) => {}
+            Err(error) => return Err(storage_error(error)),
         }
     }
+    dir.open_dir(name).map(CardDirectory).map_err(storage_error)
 }
@@ -1,35 +1,63 @@
 #![no_std]
 #![no_main]
 
-use alloc::boxed::Box;
+use alloc::{boxed::Box, format, rc::Rc, string::String, vec::Vec};
+use config::{Config, Orientation, PinConfig};
+use display_interface::DisplayError;
+use embedded_graphics::{pixelcolor::Rgb565, prelude::DrawTarget};
+use embedded_hal::digital::OutputPin;
 use embedded_sdmmc::{SdCard, VolumeManager};
+use error::BootError;
 use esp_backtrace as _;
 use esp_hal::{
     delay::Delay,
     dma::{Dma, DmaPriority},
     dma_tx_buffer,
-    gpio::{Io, Level, Output},
+    gpio::{Input, Io, Level, Output, Pull},
     peripherals::TIMG0,
     prelude::*,
     spi::{master::Spi, SpiMode},
     timer::timg::{Timer, TimerX},
     Blocking,
 };
-use gameboy::{display::GameboyLineBufferDisplay, GameEmulationHandler, NullButtonHandler};
+use gameboy::{
+    boot::BootConfig,
+    cheats::{self, RomPatches},
+    display::GameboyLineBufferDisplay,
+    header::{CartridgeHeader, CgbSupport},
+    resume::{self, LowBatteryMonitor, ShutdownRequest},
+    rtc::{RtcRegisters, RtcState},
+    save::BatterySave,
+    savestate::SaveStates,
+    GameEmulationHandler, Hotkey, InputButtonMapper,
+};
 use gb_core::gameboy::GameBoy;
+use gb_frontend::{archive, checksum, clock, config, console, presenter, storage, ui};
 use hardware::{
+    console::SerialConsole,
     display::{
         dma_streamer::{DmaStreamer, SpiDmaCMInterface},
-        scaler::ScreenScaler,
+        lcd_presenter::LcdPresenter,
     },
+    flash::RomFlash,
+    rtc::RtcClock,
+    sdcard::{storage_error, AdaptiveSdCard, CardDirectory},
     sound::NullAudioPlayer,
 };
-use ili9341::{DisplaySize, DisplaySize240x320};
+use presenter::FramePresenter;
+use storage::StorageError;
+use ui::{pause_menu::PauseItem, settings_menu::SettingsScope, MenuInput};
+// Only the SD card build picks the game in the browser.
+#[cfg(not(feature = "embedded-rom"))]
+use gameboy::header::HEADER_END;
+#[cfg(not(feature = "embedded-rom"))]
+use ui::rom_browser::{BrowserExit, RomBrowser, RomBrowserView};
+mod error;
 mod gameboy;
 mod hardware;
 mod util;
 extern crate alloc;
-use core::mem::MaybeUninit;
+use core::{cell::RefCell, mem::MaybeUninit};
 
 fn init_heap() {
     const HEAP_SIZE: usize = 180_000;
@@ -44,6 +72,73 @@ fn init_heap() {
     }
 }
 
+/// Reports `error` on the status LED when the screen cannot be used: a burst of
+/// `blink_code` flashes followed by a pause, repeated forever.
+fn blink_error<L: OutputPin>(led: &mut L, error: &BootError) -> ! {
+    log::error!("{}", error);
+    let delay = Delay::new();
+    loop {
+        for _ in 0..error.blink_code() {
+            let _ = led.set_high();
+            delay.delay_millis(200);
+            let _ = led.set_low();
+            delay.delay_millis(200);
+        }
+        delay.delay_millis(1500);
+    }
+}
+
+/// Shows `error` on the LCD and restarts once a button is pressed, so a missing
+/// card can be inserted without power cycling.
+fn halt<DT, L, I>(display: &mut DT, led: &mut L, error: BootError, poll_input: I) -> !
+where
+    DT: DrawTarget<Color = Rgb565, Error = DisplayError>,
+    L: OutputPin,
+    I: FnMut() -> Option<MenuInput>,
+{
+    log::error!("{}", error);
+    let message = format!("{}. Press any button to restart.", error);
+    if let Err(display_error) =
+        ui::message::show_message_and_wait(display, "Cannot start", &message, poll_input)
+    {
+        blink_error(led, &BootError::Display(display_error));
+    }
+    esp_hal::reset::software_reset();
+    unreachable!("software reset returned")
+}
+
+/// Writes the settings file of the game whose global checksum is
+/// `global_checksum`, keeping only what `config` changes from `global`.
+fn save_game_settings<
+    D: embedded_sdmmc::BlockDevice,
+    T: embedded_sdmmc::TimeSource,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+>(
+    root_dir: Option<&mut CardDirectory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>>,
+    global: &Config,
+    config: &Config,
+    global_checksum: u16,
+) {
+    let saved = root_dir
+        .ok_or(StorageError::NoCard)
+        .and_then(|root_dir| {
+            hardware::sdcard::open_subdir(root_dir, config::GAME_SETTINGS_DIR, true)
+        })
+        .and_then(|mut settings_dir| {
+            config::save_game(&mut settings_dir, global, config, global_checksum)
+        });
+    match saved {
+        Ok(()) => log::info!(
+            "Saved {}/{}",
+            config::GAME_SETTINGS_DIR,
+            config::game_settings_file(global_checksum)
+        ),
+        Err(error) => log::error!("Cannot save the game settings: {}", error),
+    }
+}
+
 #[entry]
 fn main() -> ! {
     #[allow(unused)]
@@ -51,135 +146,701 @@ fn main() -> ! {
     let mut delay = Delay::new();
 
     init_heap();
+    hardware::psram::init(peripherals.PSRAM);
 
     let timg0 = esp_hal::timer::timg::TimerGroup::new(peripherals.TIMG0);
     let timer0: Timer<TimerX<TIMG0, 0>, Blocking> = timg0.timer0;
     let timer1: Timer<TimerX<TIMG0, 1>, Blocking> = timg0.timer1;
 
     esp_println::logger::init_logger_from_env();
+    hardware::rtc::init(peripherals.LPWR);
+    // Mapped after PSRAM, which takes the first free MMU pages.
+    let rom_flash = match RomFlash::open() {
+        Ok(rom_flash) => rom_flash.map(|rom_flash| Rc::new(RefCell::new(rom_flash))),
+        Err(error) => {
+            log::error!("Cannot open the ROM partition: {}", error);
+            None
+        }
+    };
+    let has_flash_roms = rom_flash.as_ref().map_or(false, |rom_flash| {
+        !rom_flash.borrow().directory().roms().is_empty()
+    });
+    let mut console = SerialConsole::new(peripherals.USB_DEVICE, RtcClock, rom_flash.clone());
 
     let io = Io::new(peripherals.GPIO, peripherals.IO_MUX);
-    let mut led = Output::new(io.pins.gpio47, Level::Low);
-
-    led.set_high();
+    let (sd_pins, mut pin_bank) = hardware::pins::split(io.pins);
 
-    const SCREEN_WIDTH: usize =
-        (<DisplaySize240x320 as DisplaySize>::WIDTH as f32 / 1.0f32) as usize;
-    const SCREEN_HEIGHT: usize =
-        (<DisplaySize240x320 as DisplaySize>::HEIGHT as f32 / 1.0f32) as usize;
-
-    log::info!("START ROM LOAD");
     /////////SDCARD
-    let sclk = io.pins.gpio38;
-    let miso = io.pins.gpio39;
-    let mosi = io.pins.gpio37;
-    let cs = Output::new(io.pins.gpio36, Level::Low);
+    // The card comes up first since `config.ini` decides every other pin. Its
+    // errors are only reported once the display works.
+    let cs = Output::new(sd_pins.cs, Level::Low);
 
-    let spi = Spi::new(peripherals.SPI3, 200.kHz(), SpiMode::Mode0)
-        .with_sck(sclk)
-        .with_miso(miso)
-        .with_mosi(mosi);
+    // Cards have to be initialised at 400 kHz or less, `ramp_up` speeds the bus up afterwards.
+    let spi = Spi::new(peripherals.SPI3, 400.kHz(), SpiMode::Mode0)
+        .with_sck(sd_pins.sclk)
+        .with_miso(sd_pins.miso)
+        .with_mosi(sd_pins.mosi);
 
-    let exclusive_spi = embedded_hal_bus::spi::ExclusiveDevice::new_no_delay(spi, cs).unwrap();
-    let sdcard = SdCard::new(exclusive_spi, delay);
+    // The chip select is a plain GPIO, which cannot fail to toggle.
+    let exclusive_spi = embedded_hal_bus::spi::ExclusiveDevice::new_no_delay(spi, cs)
+        .unwrap_or_else(|error| match error {});
+    let sdcard = AdaptiveSdCard::new(SdCard::new(exclusive_spi, delay), |device, khz| {
+        device.bus_mut().change_bus_frequency(khz.kHz())
+    });
+    let sd_speed_limit = sdcard.speed_limit();
+    let sd_ready = sdcard.ramp_up().map_err(|error| {
+        BootError::Volume(storage_error(embedded_sdmmc::Error::DeviceError(error)))
+    });
 
-    let mut volume_mgr = VolumeManager::new(sdcard, hardware::sdcard::DummyTimesource::default());
+    let volume_mgr = VolumeManager::new(sdcard, RtcClock);
+    let mut root_dir = sd_ready.and_then(|()| {
+        let volume = volume_mgr
+            .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+            .map_err(|error| BootError::Volume(storage_error(error)))?;
+        let root_dir = volume_mgr
+            .open_root_dir(volume)
+            .map_err(|error| BootError::RootDir(storage_error(error)))?;
+        Ok(CardDirectory(root_dir.to_directory(&volume_mgr)))
+    });
 
-    let mut volume0 = volume_mgr
-        .open_volume(embedded_sdmmc::VolumeIdx(0))
-        .unwrap();
+    let mut config = match root_dir.as_mut() {
+        Ok(root_dir) => config::load(root_dir),
+        Err(_) => Config::default(),
+    };
+    sd_speed_limit.set(config.sd_spi_khz);
+    let pins = pin_bank.assign(&config.pins).unwrap_or_else(|error| {
+        log::error!("{}, using the default pins", error);
+        config.pins = PinConfig::default();
+        pin_bank
+            .assign(&config.pins)
+            .expect("default pins are available")
+    });
 
-    let mut root_dir = volume0.open_root_dir().unwrap();
-
-    //Read boot rom
-    let mut boot_rom_file = root_dir
-        .open_file_in_dir("dmg_boot.bin", embedded_sdmmc::Mode::ReadOnly)
-        .unwrap();
-    let mut boot_rom_data = Box::new([0u8; 0x100]);
-    boot_rom_file.read(&mut *boot_rom_data).unwrap();
-    boot_rom_file.close().unwrap();
-
-    let roms = gameboy::rom::SdRomManager::new("sml.gb", root_dir, Box::new(timer1));
-    let gb_rom = gb_core::hardware::rom::Rom::from_bytes(roms);
-    let cartridge = gb_rom.into_cartridge();
+    let mut led = Output::new(pins.led, Level::Low);
 
-    let boot_rom = gb_core::hardware::boot_rom::Bootrom::new(Some(
-        gb_core::hardware::boot_rom::BootromData::from_bytes(&*boot_rom_data),
-    ));
-    core::mem::drop(boot_rom_data);
-    log::info!("ROM DATA LOADED");
+    led.set_high();
 
     //////////SCREEN SETUP
+    // The display comes up before anything that can fail so errors can be shown on it.
     // ANCHOR: init-dma
     // we need to create the DMA driver and get a channel
     let dma = Dma::new(peripherals.DMA);
     let dma_channel = dma.channel0;
 
-    let main_screen_buffer = dma_tx_buffer!(32000).unwrap();
-    let spare_screen_buffer = dma_tx_buffer!(32000).unwrap();
+    let main_screen_buffer =
+        dma_tx_buffer!(32000).unwrap_or_else(|_| blink_error(&mut led, &BootError::DmaBuffer));
+    let spare_screen_buffer =
+        dma_tx_buffer!(32000).unwrap_or_else(|_| blink_error(&mut led, &BootError::DmaBuffer));
 
-    let mosi = io.pins.gpio4;
-    let sclk = io.pins.gpio5;
+    let mosi = pins.lcd_mosi;
+    let sclk = pins.lcd_sclk;
     //
-    let reset = Output::new(io.pins.gpio6, Level::Low);
-    let dc = Output::new(io.pins.gpio7, Level::Low);
+    let reset = Output::new(pins.lcd_reset, Level::Low);
+    let dc = Output::new(pins.lcd_dc, Level::Low);
 
     // ANCHOR: configure-spi
     // we can call `.with_dma` on the SPI driver to make it use DMA
-    let spi = Spi::new(peripherals.SPI2, 300.MHz(), SpiMode::Mode0)
-        .with_sck(sclk)
-        .with_mosi(mosi)
-        .with_dma(dma_channel.configure(true, DmaPriority::Priority9));
+    let spi = Spi::new(
+        peripherals.SPI2,
+        config.display_spi_mhz.MHz(),
+        SpiMode::Mode0,
+    )
+    .with_sck(sclk)
+    .with_mosi(mosi)
+    .with_dma(dma_channel.configure(true, DmaPriority::Priority9));
 
     let streamer = DmaStreamer::new(spi, main_screen_buffer, spare_screen_buffer);
+    let display_stats = streamer.stats();
     let display_interface = SpiDmaCMInterface::new(streamer, dc);
+    let orientation = match config.orientation {
+        Orientation::Portrait => ili9341::Orientation::Portrait,
+        Orientation::PortraitFlipped => ili9341::Orientation::PortraitFlipped,
+        Orientation::Landscape => ili9341::Orientation::Landscape,
+        Orientation::LandscapeFlipped => ili9341::Orientation::LandscapeFlipped,
+    };
     let mut display = ili9341::Ili9341::new(
         display_interface,
         reset,
         &mut delay,
-        ili9341::Orientation::LandscapeFlipped,
+        orientation,
         ili9341::DisplaySize240x320,
     )
-    .unwrap();
+    .unwrap_or_else(|error| blink_error(&mut led, &BootError::Display(error)));
+
+    //////////BUTTONS
+    let mut a_button = Input::new(pins.a, Pull::Up);
+    let mut b_button = Input::new(pins.b, Pull::Up);
+    let mut start_button = Input::new(pins.start, Pull::Up);
+    let mut select_button = Input::new(pins.select, Pull::Up);
+    let mut up_button = Input::new(pins.up, Pull::Up);
+    let mut down_button = Input::new(pins.down, Pull::Up);
+    let mut left_button = Input::new(pins.left, Pull::Up);
+    let mut right_button = Input::new(pins.right, Pull::Up);
+    let low_battery = pins.low_battery.map(|pin| Input::new(pin, Pull::Up));
+    // Holding B while switching on goes to the browser instead of resuming.
+    let skip_resume = b_button.is_low();
+    let mut buttons = InputButtonMapper::new(
+        &mut a_button,
+        &mut b_button,
+        &mut start_button,
+        &mut select_button,
+        &mut up_button,
+        &mut down_button,
+        &mut left_button,
+        &mut right_button,
+    );
+    let mut poll_menu = || {
+        delay.delay_millis(20);
+        console.poll();
+        buttons.menu_input()
+    };
+
+    log::info!("START ROM LOAD");
+    // With the ROM built into the firmware or ROMs in the flash partition the
+    // card is optional, without it nothing is saved.
+    let mut root_dir = match root_dir {
+        Ok(root_dir) => Some(root_dir),
+        Err(error) if cfg!(feature = "embedded-rom") || has_flash_roms => {
+            log::warn!("{}, running without saves", error);
+            None
+        }
+        Err(error) => halt(&mut display, &mut led, error, &mut poll_menu),
+    };
+
+    //////////ROM SELECTION
+    let resume_rom = if skip_resume {
+        log::info!("B held, not resuming the last game");
+        None
+    } else {
+        resume::take(&mut root_dir).unwrap_or_else(|error| {
+            log::error!("Cannot read {}: {}", resume::RESUME_FILE, error);
+            None
+        })
+    };
+    #[cfg(feature = "embedded-rom")]
+    let (rom_name, header) = match CartridgeHeader::parse(gameboy::embedded::ROM) {
+        Ok(header) => (String::from(gameboy::embedded::ROM_NAME), header),
+        Err(error) => halt(
+            &mut display,
+            &mut led,
+            BootError::EmbeddedRom(error),
+            &mut poll_menu,
+        ),
+    };
+    // The game to resume or the default game from the settings is tried once,
+    // the browser takes over if it cannot be started.
+    #[cfg(not(feature = "embedded-rom"))]
+    let mut default_rom = resume_rom.clone().or_else(|| config.default_rom.clone());
+    #[cfg(not(feature = "embedded-rom"))]
+    let (rom_name, header) = loop {
+        let rom_name = match default_rom.take() {
+            Some(rom_name) => rom_name,
+            None => {
+                let flash = rom_flash.as_ref().map(|rom_flash| rom_flash.borrow());
+                let flash_roms = flash.as_ref().map(|flash| flash.roms());
+                let rows = RomBrowserView::visible_rows(&display);
+                let listed = RomBrowser::new(&mut (flash_roms, &mut root_dir), rows);
+                // Only borrowed while listing: the console writes to the
+                // partition while the browser is shown.
+                core::mem::drop(flash);
+                let mut browser = listed.unwrap_or_else(|error| {
+                    halt(
+                        &mut display,
+                        &mut led,
+                        BootError::RomList(error),
+                        &mut poll_menu,
+                    )
+                });
+                match ui::rom_browser::select_rom(&mut display, &mut browser, &mut poll_menu) {
+                    Ok(Some(BrowserExit::Launch(rom_name))) => rom_name,
+                    Ok(Some(BrowserExit::Settings)) => {
+                        let rom_names = browser
+                            .entries()
+                            .iter()
+                            .map(|entry| entry.file_name.clone())
+                            .collect();
+                        let edited = ui::settings_menu::edit_settings(
+                            &mut display,
+                            &config,
+                            rom_names,
+                            SettingsScope::Global,
+                            &mut poll_menu,
+                        )
+                        .unwrap_or_else(|error| blink_error(&mut led, &BootError::Display(error)));
+                        let new_config = match edited {
+                            Some((_, new_config)) => new_config,
+                            None => continue,
+                        };
+                        let restart = match config::save(&mut root_dir, &new_config) {
+                            Ok(()) => ui::message::show_message_and_wait(
+                                &mut display,
+                                "Settings saved",
+                                "They apply after a restart. A: restart now, \
+                                 any other button: back to the games.",
+                                &mut poll_menu,
+                            )
+                            .map(|input| input == MenuInput::Accept),
+                            Err(error) => {
+                                log::error!("Cannot write {}: {}", config::CONFIG_FILE, error);
+                                ui::message::show_message_and_wait(
+                                    &mut display,
+                                    "Cannot save settings",
+                                    &format!("{}: {}", config::CONFIG_FILE, error),
+                                    &mut poll_menu,
+                                )
+                                .map(|_| false)
+                            }
+                        };
+                        match restart {
+                            Ok(true) => esp_hal::reset::software_reset(),
+                            Ok(false) => {}
+                            Err(error) => blink_error(&mut led, &BootError::Display(error)),
+                        }
+                        continue;
+                    }
+                    Ok(None) => halt(&mut display, &mut led, BootError::NoRoms, &mut poll_menu),
+                    Err(error) => blink_error(&mut led, &BootError::Display(error)),
+                }
+            }
+        };
+        let mut header_data = [0u8; HEADER_END];
+        let flash_read = rom_flash.as_ref().and_then(|rom_flash| {
+            let rom_flash = rom_flash.borrow();
+            let rom = rom_flash.roms().rom(&rom_name)?;
+            let read = rom.len().min(HEADER_END);
+            header_data[..read].copy_from_slice(&rom[..read]);
+            Some(read)
+        });
+        let header_read = match (flash_read, root_dir.as_mut()) {
+            (Some(read), _) => Ok(read),
+            (None, Some(root_dir)) => {
+                gameboy::rom::read_rom_start(root_dir, &rom_name, &mut header_data)
+            }
+            (None, None) => Err(StorageError::NoCard.into()),
+        };
+        let problem = match header_read {
+            Ok(read) => match CartridgeHeader::parse(&header_data[..read]) {
+                Ok(header) => break (rom_name, header),
+                Err(error) => format!("{}", error),
+            },
+            Err(error) => format!("{}", error),
+        };
+        log::error!("{}: {}", rom_name, problem);
+        if let Err(error) = ui::message::show_message_and_wait(
+            &mut display,
+            "Cannot start game",
+            &format!("{}: {}", rom_name, problem),
+            &mut poll_menu,
+        ) {
+            blink_error(&mut led, &BootError::Display(error));
+        }
+    };
+    log::info!(
+        "Selected ROM: {} \"{}\" ({:?}, {} KiB ROM, {} KiB RAM, licensee {})",
+        rom_name,
+        header.title,
+        header.cartridge_type.mbc,
+        header.rom_size / 1024,
+        header.ram_size / 1024,
+        header.licensee
+    );
+
+    let mut cheats = cheats::load_cheats(&mut root_dir, &rom_name).unwrap_or_else(|error| {
+        log::error!("Cannot read cheats: {}", error);
+        Vec::new()
+    });
+    let rom_patches = Rc::new(RomPatches::default());
+    rom_patches.update(&cheats);
+
+    #[cfg(feature = "embedded-rom")]
+    let roms = {
+        let rom = gameboy::embedded::ROM;
+        log::info!("Running {} ({} KiB) from flash", rom_name, rom.len() / 1024);
+        gameboy::rom::FlashRomManager::new(rom, Box::new(timer1), rom_patches.clone())
+    };
+    // A ROM in the flash partition wins over a card file of the same name.
+    #[cfg(not(feature = "embedded-rom"))]
+    let flash_rom = rom_flash
+        .as_ref()
+        .and_then(|rom_flash| rom_flash.borrow_mut().lock(&rom_name));
+    #[cfg(not(feature = "embedded-rom"))]
+    let roms = match (flash_rom, root_dir.as_mut()) {
+        (Some(rom), _) => {
+            log::info!(
+                "Running {} ({} KiB) from the ROM partition",
+                rom_name,
+                rom.len() / 1024
+            );
+            Ok(gameboy::rom::RomSource::Flash(
+                gameboy::rom::FlashRomManager::new(rom, Box::new(timer1), rom_patches.clone()),
+            ))
+        }
+        (None, Some(root_dir)) => gameboy::rom::RomSource::open(
+            &rom_name,
+            root_dir,
+            Box::new(timer1),
+            &config.cache,
+            rom_patches.clone(),
+        ),
+        (None, None) => Err(StorageError::NoCard.into()),
+    }
+    .unwrap_or_else(|error| {
+        let error = BootError::Rom {
+            name: rom_name.clone(),
+            error,
+        };
+        halt(&mut display, &mut led, error, &mut poll_menu)
+    });
+    let bank_stats = roms.stats();
+
+    // A patch can change the header, so the saves, the cartridge clock and the
+    // per-game settings go by the ROM as loaded. The file's header only
+    // served to pick the game.
+    let header = match CartridgeHeader::parse(roms.bank_0()) {
+        Ok(header) => header,
+        Err(error) => {
+            log::warn!("Patched header unusable ({}), keeping the original", error);
+            header
+        }
+    };
+    let mut global_config = config.clone();
+    let global_checksum = header.global_checksum;
+    let settings_dir = root_dir
+        .as_mut()
+        .ok_or(StorageError::NoCard)
+        .and_then(|root_dir| {
+            hardware::sdcard::open_subdir(root_dir, config::GAME_SETTINGS_DIR, false)
+        });
+    config = match settings_dir {
+        Ok(mut settings_dir) => {
+            config::load_game(&mut settings_dir, &global_config, global_checksum)
+        }
+        Err(StorageError::NotFound | StorageError::NoCard) => global_config.clone(),
+        Err(error) => {
+            log::error!("Cannot open {}: {}", config::GAME_SETTINGS_DIR, error);
+            global_config.clone()
+        }
+    };
+    if let Some(numbers) = &config.enabled_cheats {
+        cheats::enable_only(&mut cheats, numbers);
+        rom_patches.update(&cheats);
+    }
+
+    //Read boot rom
+    let boot_config = BootConfig {
+        skip_boot_rom: config.skip_boot_rom,
+    };
+    #[cfg(feature = "embedded-rom")]
+    let built_in_boot_rom = gameboy::embedded::boot_rom(&boot_config);
+    #[cfg(not(feature = "embedded-rom"))]
+    let built_in_boot_rom = None;
+    let boot_rom_data = match built_in_boot_rom {
+        Some(data) => Some(data),
+        None => gameboy::boot::load_boot_rom(&mut root_dir, &boot_config).unwrap_or_else(|error| {
+            halt(
+                &mut display,
+                &mut led,
+                BootError::BootRom(error),
+                &mut poll_menu,
+            )
+        }),
+    };
+
+    let mut save_states = SaveStates::new(&rom_name, checksum::crc32(roms.bank_0()));
+    let gb_rom = gb_core::hardware::rom::Rom::from_bytes(roms);
+    let mut cartridge = gb_rom.into_cartridge();
+    let mut battery_save = if header.cartridge_type.battery {
+        let mut battery_save = BatterySave::new(&rom_name, header.save_size());
+        let mut rtc = header.cartridge_type.timer.then(|| RtcState {
+            current: RtcRegisters::from_bytes(cartridge.rtc_registers()),
+            latched: RtcRegisters::from_bytes(cartridge.latched_rtc_registers()),
+        });
+        // Starting without the save would overwrite it at the first flush.
+        match battery_save.load(&mut root_dir, cartridge.ram_mut(), rtc.as_mut(), &RtcClock) {
+            Ok(true) => log::info!("Loaded {}", battery_save.file_name()),
+            Ok(false) => {}
+            Err(error) => {
+                let error = BootError::Save {
+                    name: String::from(battery_save.file_name()),
+                    error,
+                };
+                halt(&mut display, &mut led, error, &mut poll_menu)
+            }
+        }
+        if let Some(rtc) = rtc {
+            log::info!(
+                "Cartridge clock: day {} {:02}:{:02}:{:02}",
+                rtc.current.days(),
+                rtc.current.hours,
+                rtc.current.minutes,
+                rtc.current.seconds
+            );
+            cartridge.set_rtc_registers(rtc.current.to_bytes());
+            cartridge.set_latched_rtc_registers(rtc.latched.to_bytes());
+        }
+        Some(battery_save)
+    } else {
+        None
+    };
+
+    let has_boot_rom = boot_rom_data.is_some();
+    let boot_rom = gb_core::hardware::boot_rom::Bootrom::new(
+        boot_rom_data
+            .as_deref()
+            .map(|data| gb_core::hardware::boot_rom::BootromData::from_bytes(data)),
+    );
+    core::mem::drop(boot_rom_data);
+    log::info!("ROM DATA LOADED");
 
     //GAMEBOY INIT
     let screen = GameboyLineBufferDisplay::new(Box::new(timer0));
     let mut gameboy = GameBoy::create(screen, cartridge, boot_rom, Box::new(NullAudioPlayer));
-    let scaler: ScreenScaler<144, 160, { SCREEN_WIDTH }, { SCREEN_HEIGHT }> = ScreenScaler::new();
-    ////
-    display.clear_screen(0xf9b0).unwrap();
+    if !has_boot_rom {
+        gameboy::boot::apply_post_boot_state(&mut gameboy, header.header_checksum);
+    }
+    if resume_rom.as_deref() == Some(rom_name.as_str()) {
+        match save_states.load_auto(&mut root_dir) {
+            Ok(snapshot) => match gameboy.load_state(&snapshot) {
+                Ok(()) => log::info!("Resumed from {}", save_states.auto_file_name()),
+                Err(error) => log::warn!("Emulator rejected save state: {:?}", error),
+            },
+            Err(error) => log::warn!(
+                "Cannot resume from {}: {:?}",
+                save_states.auto_file_name(),
+                error
+            ),
+        }
+    }
+    let mut presenter = LcdPresenter::new(display, &config);
+    if let Err(error) = presenter.paint_border() {
+        blink_error(&mut led, &BootError::Display(error));
+    }
+    buttons.set_buttons(config.buttons);
+    // Colour games keep their own colours.
+    let monochrome = header.cgb == CgbSupport::None;
+    let game_palette = |config: &Config| config.palette.colors().filter(|_| monochrome);
+    let mut palette = game_palette(&config);
 
+    let mut low_battery_monitor = LowBatteryMonitor::new();
     let mut loop_counter: usize = 0;
-    let mut sample_count: usize = 0;
     loop {
         // display.clear_screen(0xf9b0).unwrap();
         // display.clear_screen(0x423f).unwrap();
         // log::info!("Hello world!");
         let start_time = esp_hal::time::now();
-        for x in (GameEmulationHandler::new(&mut gameboy, &mut NullButtonHandler)) {
-            sample_count = sample_count.saturating_add(1);
-        }
-        // display
-        //     .draw_raw_iter(
-        //         0,
-        //         0,
-        //         (SCREEN_HEIGHT - 1) as u16,
-        //         (SCREEN_WIDTH - 1) as u16,
-        //         scaler.scale_iterator(GameEmulationHandler::new(
-        //             &mut gameboy,
-        //             &mut NullButtonHandler,
-        //         )),
-        //     )
-        //     .unwrap();
+        let mut frame = GameEmulationHandler::new(&mut gameboy, &mut buttons);
+        let presented = if loop_counter % (config.frame_skip as usize + 1) != 0 {
+            // Skipped frames still run the emulator, only the drawing is left out.
+            for _ in &mut frame {}
+            Ok(())
+        } else if let Some(colors) = palette {
+            presenter.present(&mut frame.map(|pixel| presenter::recolor(pixel, &colors)))
+        } else {
+            presenter.present(&mut frame)
+        };
+        if let Err(error) = presented {
+            log::error!("Cannot draw the frame: {:?}", error);
+        }
+        for code in cheats::ram_writes(&cheats) {
+            gameboy.write_memory(code.address, code.value);
+        }
+        console.poll();
+        let mut shutdown = None;
+        if console.take_shutdown_request() {
+            shutdown = Some(ShutdownRequest::Console);
+        }
+        if let Some(low_battery) = low_battery.as_ref() {
+            if low_battery_monitor.update(low_battery.is_low()) {
+                shutdown = Some(ShutdownRequest::LowBattery);
+            }
+        }
+        let hotkey = buttons.hotkey();
+        match hotkey {
+            Some(Hotkey::SaveState) => {
+                match save_states.save(&mut root_dir, &gameboy.save_state()) {
+                    Ok(()) => log::info!("Saved state to {}", save_states.file_name()),
+                    Err(error) => log::error!("Cannot save {}: {}", save_states.file_name(), error),
+                }
+            }
+            Some(Hotkey::LoadState) => match save_states.load(&mut root_dir) {
+                Ok(snapshot) => match gameboy.load_state(&snapshot) {
+                    Ok(()) => log::info!("Loaded state from {}", save_states.file_name()),
+                    Err(error) => log::warn!("Emulator rejected save state: {:?}", error),
+                },
+                Err(error) => log::warn!("Cannot load {}: {:?}", save_states.file_name(), error),
+            },
+            Some(Hotkey::NextSlot) => {
+                save_states.next_slot();
+                log::info!("Save state slot {}", save_states.slot());
+            }
+            Some(Hotkey::PreviousSlot) => {
+                save_states.previous_slot();
+                log::info!("Save state slot {}", save_states.slot());
+            }
+            Some(Hotkey::Menu) => {
+                // Swallow the START press that opened the menu.
+                buttons.menu_input();
+                let mut poll_pause_menu = || {
+                    delay.delay_millis(20);
+                    console.poll();
+                    buttons.menu_input()
+                };
+                match ui::pause_menu::pause(
+                    presenter.display_mut(),
+                    !cheats.is_empty(),
+                    &mut poll_pause_menu,
+                ) {
+                    Ok(PauseItem::Resume) => {}
+                    Ok(PauseItem::PowerOff) => shutdown = Some(ShutdownRequest::Menu),
+                    Ok(PauseItem::Cheats) => {
+                        match ui::cheat_menu::edit_cheats(
+                            presenter.display_mut(),
+                            &mut cheats,
+                            &mut poll_pause_menu,
+                        ) {
+                            Ok(true) => {
+                                rom_patches.update(&cheats);
+                                let enabled = cheats.iter().filter(|cheat| cheat.enabled).count();
+                                log::info!("{} of {} cheats enabled", enabled, cheats.len());
+                                config.enabled_cheats = Some(cheats::enabled_numbers(&cheats));
+                                save_game_settings(
+                                    root_dir.as_mut(),
+                                    &global_config,
+                                    &config,
+                                    global_checksum,
+                                );
+                            }
+                            Ok(false) => {}
+                            Err(error) => log::error!("Cannot draw the cheat menu: {:?}", error),
+                        }
+                    }
+                    Ok(PauseItem::Settings) => {
+                        match ui::settings_menu::edit_settings(
+                            presenter.display_mut(),
+                            &config,
+                            Vec::new(),
+                            SettingsScope::Game,
+                            &mut poll_pause_menu,
+                        ) {
+                            Ok(Some((scope, edited))) => {
+                                if scope == SettingsScope::Global {
+                                    global_config = edited.game_settings_over(&global_config);
+                                    if let Err(error) = config::save(&mut root_dir, &global_config)
+                                    {
+                                        log::error!(
+                                            "Cannot write {}: {}",
+                                            config::CONFIG_FILE,
+                                            error
+                                        );
+                                    }
+                                }
+                                // Rewritten for both scopes: saving for every game drops
+                                // the values this game had set for itself.
+                                save_game_settings(
+                                    root_dir.as_mut(),
+                                    &global_config,
+                                    &edited,
+                                    global_checksum,
+                                );
+                                config = edited;
+                            }
+                            Ok(None) => {}
+                            Err(error) => log::error!("Cannot draw the settings: {:?}", error),
+                        }
+                    }
+                    Err(error) => log::error!("Cannot draw the pause menu: {:?}", error),
+                }
+                buttons.set_buttons(config.buttons);
+                palette = game_palette(&config);
+                // Also repaints the border over the menu, for a new mode or colour.
+                if let Err(error) = presenter.configure(&config) {
+                    log::error!("Cannot clear the screen: {:?}", error);
+                }
+            }
+            _ => {}
+        }
+        if let Some(battery_save) = battery_save.as_mut() {
+            let now = esp_hal::time::now().duration_since_epoch().to_millis();
+            let cartridge = gameboy.get_cartridge();
+            let ram = cartridge.ram();
+            if battery_save.poll(ram, now) || hotkey == Some(Hotkey::Menu) || shutdown.is_some() {
+                let rtc = header.cartridge_type.timer.then(|| RtcState {
+                    current: RtcRegisters::from_bytes(cartridge.rtc_registers()),
+                    latched: RtcRegisters::from_bytes(cartridge.latched_rtc_registers()),
+                });
+                if let Err(error) = battery_save.flush(&mut root_dir, ram, rtc.as_ref(), &RtcClock)
+                {
+                    log::error!("Cannot write {}: {}", battery_save.file_name(), error);
+                }
+            }
+        }
+        if let Some(request) = shutdown {
+            log::info!("Stopping the game: {}", request);
+            let saved = save_states
+                .save_auto(&mut root_dir, &gameboy.save_state())
+                .and_then(|()| resume::record(&mut root_dir, &rom_name));
+            let (title, message) = match saved {
+                Ok(()) => (
+                    "Game saved",
+                    String::from(
+                        "It is safe to switch off now. The game continues where it \
+                         left off at the next start, or press any button.",
+                    ),
+                ),
+                Err(error) => {
+                    log::error!("Cannot write {}: {}", save_states.auto_file_name(), error);
+                    (
+                        "Cannot save the game state",
+                        format!("{}. Battery saves were written.", error),
+                    )
+                }
+            };
+            let poll_input = || {
+                delay.delay_millis(20);
+                console.poll();
+                buttons.menu_input()
+            };
+            // Restarting rather than spinning keeps a plugged-in handheld usable.
+            match ui::message::show_message_and_wait(
+                presenter.display_mut(),
+                title,
+                &message,
+                poll_input,
+            ) {
+                Ok(_) => esp_hal::reset::software_reset(),
+                Err(error) => blink_error(&mut led, &BootError::Display(error)),
+            }
+        }
 
         let end_time = esp_hal::time::now();
         let diff = end_time - start_time;
         let milliseconds = diff.to_millis();
+        let bank_counters = bank_stats
+            .as_ref()
+            .map(|stats| stats.take())
+            .unwrap_or_default();
+        if bank_counters.read_errors > 0 {
+            log::error!(
+                "{} ROM bank reads failed, the game may misbehave",
+                bank_counters.read_errors
+            );
+        }
+        let display_counters = display_stats.take();
         log::info!(
-            "Loop: {}, Time elapsed: {}:{}",
+            "Loop: {}, Time elapsed: {}:{}, Bank hits: {}, misses: {} (avg {}us, max {}us), evictions: {}, \
+             Display: {} B in {}us ({} KiB/s, {}us waiting for DMA)",
             loop_counter,
             milliseconds / 1000,
-            milliseconds % 1000
+            milliseconds % 1000,
+            bank_counters.hits,
+            bank_counters.misses,
+            bank_counters.average_miss_micros(),
+            bank_counters.max_miss_micros,
+            bank_counters.evictions,
+            display_counters.bytes,
+            display_counters.stream_micros,
+            display_counters.bytes_per_second() / 1024,
+            display_counters.wait_micros
         );
         loop_counter += 1;
     }